@@ -1,113 +1,175 @@
 #[doc(inline)]
-use std::borrow::BorrowMut;
 //use std::thread;
-use std::collections::HashMap;
-use std::env;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
+use std::str::FromStr;
 
 use colored::Colorize;
+use tokio::sync::broadcast;
 //use std::time::Duration;
-use rsa::{pkcs1v15::SigningKey, pkcs8::DecodePrivateKey, RsaPublicKey};
-use rsa::sha2::Sha256;
 
+use crate::auction::auction_book::{AuctionBook, BookEvent};
+use crate::auction::events::{AuctionEvent, EventKind, EventTracker};
+use crate::auction::item::ItemDescriptor;
+use crate::auction::wallet::Wallet;
 use crate::auxi;
 use crate::kademlia::node::Node;
-use crate::marco::auction::Auction as MarcoAuction;
-use crate::marco::bid::Bid;
-use crate::marco::marco::{Data, Marco};
-use crate::marco::winner::Winner;
+use crate::marco::amount::Amount;
+use crate::marco::auction::{AuctionType, DutchTerms, PriceTerms};
+use crate::marco::marco::Marco;
+use crate::marco::sealed_bid::BidReveal;
 use crate::p2p::peer::Peer;
 
 pub struct Auction {
-    pub client: Peer,
-    pub id:String,
-    pub pkey:RsaPublicKey,
-    pub skey: SigningKey<Sha256>,
-    ///vector of all your
-    pub your_bids: HashMap<String,f64>,
-    pub open: HashMap<String,Marco>,
-    ///max bid for auction with hash
-    pub all_bids: HashMap<String,f64>,
-
-    pub my_auction: String,
-    pub my_auction_highest: Bid,
+    pub wallet: Wallet,
+    ///auctions, bids and winners seen so far
+    pub book: AuctionBook,
+
+    ///blocks of the confirmed chain already added to the book
+    book_blocks: usize,
+    ///[Blockchain::changes](crate::ledger::blockchain::Blockchain::changes) at the last update of the book
+    book_changes: u64,
+
+    ///sealed bids waiting to be revealed: amount and salt by auction hash
+    pub sealed_bids: HashMap<String,(Amount,String)>,
 }
 
 
 impl Auction {
-    fn update_open_auctions(&mut self){
-        //search bc for auctions
-        let list = self.client.blockchain.lock().unwrap()
-            .marco_set.clone();
-
-        //println!("{:?}",list);
-        let mut t:Vec<(&String,&Marco)> = list.iter().collect();
-
-        t.sort_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp));
-
-        let _:Vec<_> = t.into_iter().map(|(key, value)| (
-                match &value.data {
-                    Data::CreateAuction(_) => {
-                        self.open.insert(key.clone(),value.clone());
-                    },
-                    Data::Bid(b) => {
-                        if b.auction_id == self.my_auction {
-                            if b.amount > self.my_auction_highest.amount{
-                                self.my_auction_highest = b.clone();
-                            }
-                            return;
-                        }
-                        if b.seller_id == self.id {
-                            return;
-                        }
+    /// adds the new Marcos of the blockchain to the book and prints what changed for us.
+    /// Only the blocks that became final since the last update are read from the confirmed chain
+    fn update_book(&mut self){
+        let (finals, main, pending) = {
+            let bc = self.wallet.peer.blockchain.lock().unwrap();
+            if bc.changes == self.book_changes {
+                return;
+            }
+            self.book_changes = bc.changes;
+            let finals: Vec<Marco> = bc.chain[self.book_blocks..].iter()
+                .flat_map(|b| b.transactions.iter().cloned())
+                .collect();
+            self.book_blocks = bc.chain.len();
+            let main: Vec<Marco> = bc.heads.get_main().into_iter()
+                .flat_map(|b| b.transactions)
+                .collect();
+            (finals, main, bc.pending_marcos())
+        };
+
+        // Bids and winners of our auctions are printed by [Auction::print_events] as they happen
+        for event in self.book.update(&finals, &main, &pending) {
+            if let BookEvent::AuctionOpened { auction_id, amount } = event {
+                println!("New auction {} of {}", auction_id, amount);
+            }
+        }
+    }
 
-                        //update all_bids
-                        let res = self.all_bids.get(&b.auction_id);
-                        match res {
-                            None => {
-                                self.all_bids.insert(b.auction_id.clone() ,b.amount);
-                            },
-                            Some(bid) => {
-                                if bid < &b.amount {
-                                    self.all_bids.insert(b.auction_id.clone() ,b.amount);
-                                }
-                            }
-                        };
-
-                        //update my_bids
-                        let res = self.your_bids.get(&b.auction_id);
-                        match res {
-                            None => {
-                                self.open.insert(key.clone(),value.clone());
-                            },
-                            Some(bid) => {
-                                if bid >= &b.amount {
-                                    return;
-                                };
-                                println!("New Bid for auction you subscribe");
-                                let mut entries: Vec<_> = self.open.iter().collect();
-                                entries.sort_by(|a, b| a.0.cmp(b.0));
-                                let mut i = 0;
-                                for (k, _) in entries {
-                                    if k == &b.auction_id {
-                                        println!("Auction id: {}", i);
-                                        break;
-                                    }
-                                    i+=1;
-                                }
-
-                                self.your_bids.insert(b.auction_id.clone() ,b.amount);
-                                println!("Bid of {} by someone", b.amount);
-                            }
-                        };
-                        println!("");
-                    },
-                    Data::Winner(w) => {
-                        self.open.remove(&w.auction);
+    /// prints the events of the auctions we sell or bid on, the first time they are seen
+    async fn print_events(mut receiver: broadcast::Receiver<AuctionEvent>) {
+        let mut printed = HashSet::new();
+        loop {
+            let e = match receiver.recv().await {
+                Ok(e) => e,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            if !e.mine || !printed.insert(e.key()) {
+                continue;
+            }
+            match e.kind {
+                EventKind::NewBid => println!("\nBid of {} by {} on auction {}", e.amount, e.buyer_id, e.auction_id),
+                EventKind::Outbid => println!("\nNew Bid for auction you subscribe, you were outbid on auction {} with {}", e.auction_id, e.amount),
+                EventKind::Closed => println!("\nAuction {} no longer takes bids", e.auction_id),
+                EventKind::WinnerDeclared if e.buyer_id.is_empty() => println!("\nAuction {} closed without bids", e.auction_id),
+                EventKind::WinnerDeclared => println!("\nAuction {} was won by {} with {}", e.auction_id, e.buyer_id, e.amount),
+            }
+        }
+    }
+
+    /// reveals our sealed bids of the auctions in their reveal phase
+    fn reveal_sealed_bids(&mut self) {
+        let (auctions, (height, timestamp)) = {
+            let bc = self.wallet.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        let mut pending: Vec<_> = self.sealed_bids.clone().into_iter().collect();
+        pending.sort_by(|a, b| a.0.cmp(&b.0));
+        for (auction_id, (amount, salt)) in pending {
+            match auctions.get(&auction_id) {
+                Some(state) if state.in_reveal_phase(height, timestamp) => {
+                    let m = Marco::from_bid_reveal(BidReveal::new(auction_id.clone(), self.wallet.id.clone(), amount, salt), self.wallet.next_nonce());
+                    self.add_and_broadcast(m);
+                    self.sealed_bids.remove(&auction_id);
+                }
+                Some(state) if state.bidding_over(height, timestamp) => {
+                    println!("The reveal phase of auction {} is over, the deposit was forfeited", auction_id);
+                    self.sealed_bids.remove(&auction_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// asks for a number of blocks, at least 1
+    fn get_block_count(&self, prompt: &str) -> u64 {
+        loop {
+            let x = self.get_user_input(prompt);
+            let result = x.trim().parse::<u64>();
+
+            match result {
+                Ok(number) if number > 0 => return number,
+                Ok(_) => println!("At least 1 block is needed"),
+                Err(e) => println!("A positive integer is needed: {}", e),
+            }
+        }
+    }
+
+    /// asks for the auction type, and the prices of a Dutch auction
+    fn get_auction_type(&self) -> AuctionType {
+        loop {
+            let x = self.get_user_input("Which kind of auction? (1. English, 2. Dutch, 3. Vickrey)\n");
+            match x.trim() {
+                "" | "1" => return AuctionType::English,
+                "3" => return AuctionType::Vickrey,
+                "2" => {
+                    let start_price = self.get_optional_amount("Starting price?\n");
+                    let decrement = self.get_optional_amount("How much does the price drop every block?\n");
+                    let floor = self.get_optional_amount("Lowest price?\n");
+                    match (start_price, decrement, floor) {
+                        (Some(start_price), Some(decrement), Some(floor)) => {
+                            return AuctionType::Dutch(DutchTerms { start_price, decrement, floor });
+                        }
+                        _ => println!("A Dutch auction needs the 3 prices"),
                     }
-                    _ => {}
                 }
-            )).collect();
+                _ => println!("1, 2 or 3 is needed"),
+            }
+        }
+    }
+
+    /// asks for the item sold with the coins, an empty title is None
+    fn get_item(&self) -> Option<ItemDescriptor> {
+        let title = self.get_user_input("What item do you sell with the coins (empty for none)?\n");
+        if title.trim().is_empty() {
+            return None;
+        }
+        let description = self.get_user_input("Describe the item:\n");
+        let image = self.get_user_input("Link to a picture of the item (empty for none)?\n");
+        let terms = self.get_user_input("Conditions of the sale (empty for none)?\n");
+        Some(ItemDescriptor::new(title.trim().to_string(), description.trim().to_string(), image.trim().to_string(), terms.trim().to_string()))
+    }
+
+    /// asks for an amount, an empty answer is None
+    fn get_optional_amount(&self, prompt: &str) -> Option<Amount> {
+        loop {
+            let x = self.get_user_input(prompt);
+            if x.trim().is_empty() {
+                return None;
+            }
+            match Amount::from_str(&x) {
+                Ok(number) => return Some(number),
+                Err(e) => println!("A decimal amount is needed: {}", e),
+            }
+        }
     }
 
     fn get_user_input(&self, prompt: &str) -> String {
@@ -119,57 +181,40 @@ impl Auction {
     }
 
     fn print_bc(&self){
-        let list = self.client.blockchain.lock().unwrap().chain.clone();
+        let list = self.wallet.peer.blockchain.lock().unwrap().chain.clone();
         for i in list {
             println!("Block: id: {{{}}} hash:{}",i.index.clone(), i.hash.clone());
         }
-        let list = self.client.blockchain.lock().unwrap().heads.get_main().clone();
+        let list = self.wallet.peer.blockchain.lock().unwrap().heads.get_main().clone();
         for i in list {
             println!("Block{{Head}}: id: {{{}}} hash:{}",i.index.clone(), i.hash.clone());
         }
     }
 
-    fn add_and_broadcast(&self, m: &mut Marco){
-        m.calc_hash();
-        m.sign(self.skey.clone());
-        let (res,ob) = self.client.blockchain.lock().unwrap().add_marco(m.clone(),self.pkey.clone());
-        if !res {
-            println!("There was an issue with the generated auction");
-            return;
-        }
-        match ob {
-            None => {
-                let local_client = self.client.clone();
-                let m_clone = m.clone();
-                tokio::spawn(async move {
-                    //open auctin with value
-                    local_client.send_marco(m_clone).await
-                });
-            },
-            Some(b) => {
-                let local_client = self.client.clone();
-                tokio::spawn(async move {
-                    //open auctin with value
-                    local_client.send_block(b).await
-                });
-            },
+    fn add_and_broadcast(&self, m: Marco){
+        if let Err(e) = self.wallet.submit(m) {
+            println!("There was an issue with the generated auction: {}", e);
         }
     }
 
     pub async fn main(&mut self) {
         loop {
-            self.update_open_auctions();
+            self.update_book();
+            self.wallet.publish_ended_auctions();
+            self.reveal_sealed_bids();
+            let balance = self.wallet.peer.blockchain.lock().unwrap().state().balances.get(&self.wallet.id);
+            println!("Balance: {} ({} locked in auctions)", balance.available, balance.locked);
             println!("Choose an action:");
             println!("1. Open New Auction");
             println!("2. Place Bid");
             println!("3. Show Auctions");
             println!("4. Print The BlockChain");
-            println!("5. Close your Auction");
+            println!("5. Close one of your Auctions");
             println!("6. Exit");
 
             let choice = self.get_user_input("Enter your choice: ");
             match choice.trim() {
-                "1" => self.open_auction(),
+                "1" => self.open_auction().await,
                 "2" => self.place_bid(),
                 "3" => self.search_auctions(),
                 "4" => self.print_bc(),
@@ -186,79 +231,95 @@ impl Auction {
     pub async fn new() -> Self {
         let node = Node::new("127.0.0.1".to_string(), auxi::get_port().await as u32);
         let (client, server) = Peer::new(&node.unwrap(), false);
-        let _ = server.init_server().await;
+        let wallet = Wallet::load(client.clone());
+        let _ = server.init_auction_server(wallet.clone()).await;
+        tokio::spawn(Self::print_events(wallet.events.subscribe()));
+        tokio::spawn(EventTracker::watch(wallet.clone()));
         client.boot().await;
 
-        let id = client.id.0.iter()
-            .map(|byte| format!("{:02x}",byte))
-            .collect::<Vec<String>>()
-            .join("");
-
-        let mut slash = "/";
-        if env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string()) == "windows" {
-            slash = "\\";
-        }
-        let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
-        let pem = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt"))).expect("Failed to read server.crt");
-        let pkey = auxi::get_public_key(pem);
-        //let mut rng = rand::thread_rng();
-        //let bits = 2048;
-        //let skey = RsaPrivateKey::new(&mut rng, bits).expect("failed to generate a key");
-        let skey = SigningKey::read_pkcs8_pem_file(data_dir.join(format!("cert{slash}server.key"))).expect("Failed to read server.key");
-
+        let book = AuctionBook::new(wallet.id.clone());
         Auction {
-            client,
-            id,
-            pkey,
-            skey,
-            open: HashMap::new(),
-            your_bids: HashMap::new(),
-            all_bids: HashMap::new(),
-            my_auction: "".to_string(),
-            my_auction_highest: Bid::new("".to_string(),"".to_string(),"".to_string(),0.0),
+            wallet,
+            book,
+            book_blocks: 0,
+            book_changes: u64::MAX,
+            sealed_bids: HashMap::new(),
         }
     }
 
     // Acquire information from the stdin to populate the Marco object
 
     // This will broadcast the Marco through the network
-    // self.client.send_marco(marco).await;
-    pub fn open_auction(&mut self) {
-        let mut entries: Vec<_> = self.open.iter().collect();
-        entries.sort_by(|a, b| a.0.cmp(b.0));
-        for (_, m) in entries {
-            match &m.data {
-                Data::CreateAuction(a)=> {
-                    if a.seller_id == self.id {
-                        println!("You can only have 1 open auction at the same time\n");
-                        return;
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        let value :f64;
+    // self.wallet.peer.send_marco(marco).await;
+    pub async fn open_auction(&mut self) {
+        let value :Amount;
         loop {
             let x = self.get_user_input("How many coins do you want to auction?\n");
-            let result = x.trim().parse::<f64>();
+            let result = Amount::from_str(&x);
 
             match result {
                 Ok(number) => {
                     value = number;
                     break;
                 }
-                Err(e) => println!("A float is needed: {}", e),
+                Err(e) => println!("A decimal amount is needed: {}", e),
             }
         }
-        let mut m = Marco::from_auction(MarcoAuction::new(self.id.clone(), value));
-        self.my_auction = m.get_hash();
-        self.add_and_broadcast(m.borrow_mut());
+        let blocks = self.get_block_count("For how many blocks should the auction take bids?\n");
+        let auction_type = self.get_auction_type();
+        let sealed = match auction_type {
+            AuctionType::English => self.get_user_input("Should the bids be sealed? (y/n)\n").trim().eq_ignore_ascii_case("y"),
+            AuctionType::Dutch(_) => false,
+            AuctionType::Vickrey => true,
+        };
+        let terms = if sealed {
+            let reveal_blocks = self.get_block_count("For how many blocks after that can bids be revealed?\n");
+            let deposit :Amount;
+            loop {
+                let x = self.get_user_input("What deposit do bidders need to commit?\n");
+                match Amount::from_str(&x) {
+                    Ok(number) => {
+                        deposit = number;
+                        break;
+                    }
+                    Err(e) => println!("A decimal amount is needed: {}", e),
+                }
+            }
+            Some((reveal_blocks, deposit))
+        } else {
+            None
+        };
+        let prices = PriceTerms {
+            reserve: self.get_optional_amount("Lowest bid that can win (empty for none)?\n"),
+            increment: self.get_optional_amount("Minimum step between bids (empty for the default)?\n"),
+            buy_now: if auction_type == AuctionType::English && !sealed {
+                self.get_optional_amount("Buy-now price (empty for none)?\n")
+            } else {
+                None
+            },
+        };
+        let item = match self.get_item() {
+            None => None,
+            Some(item) => match self.wallet.publish_item(&item).await {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    println!("There was an issue with the item: {}", e);
+                    return;
+                }
+            },
+        };
+        match self.wallet.open_auction(value, blocks, terms, prices, auction_type, item) {
+            Ok(m) => println!("Opened auction {}", m.get_hash()),
+            Err(e) => println!("There was an issue with the generated auction: {}", e),
+        }
     }
 
     pub fn place_bid(&mut self) {
         self.search_auctions();
-        let mut entries: Vec<_> = self.open.iter().collect();
+        let (height, timestamp) = self.wallet.peer.blockchain.lock().unwrap().next_position();
+        let entries: Vec<(String, bool)> = self.book.biddable(height, timestamp).into_iter()
+            .map(|a| (a.id().clone(), a.auction.data.get_sealed().is_some()))
+            .collect();
         if entries.len() == 0 {
             println!("No Auctions Found!");
             return;
@@ -281,115 +342,125 @@ impl Auction {
                 Err(e) => println!("A positive integer is needed: {}", e),
             }
         }
-        let value :f64;
+        let value :Amount;
         loop {
             let x = self.get_user_input("How many euros do you want to bid?\n");
-            let result = x.trim().parse::<f64>();
+            let result = Amount::from_str(&x);
 
             match result {
                 Ok(number) => {
                     value = number;
                     break;
                 }
-                Err(e) => println!("A float is needed: {}", e),
+                Err(e) => println!("A decimal amount is needed: {}", e),
             }
         }
 
         //ir buscar auction
-        entries.sort_by(|a, b| a.0.cmp(b.0));
-        if  auction_id >= entries.len() {
-            return ;
-        }
-        let auction :& Marco = entries[auction_id].1;
-
-        match &auction.data {
-            Data::CreateAuction(a) => {
-
-                let mut m = Marco::from_bid(Bid::new(auction.get_hash(),self.id.clone(), a.seller_id.clone(), value.clone() ));
-                self.your_bids.insert(auction.get_hash(), value);
-                self.add_and_broadcast(m.borrow_mut());
-            }
-            _ => {}
-        }
-    }
-
-    pub fn check_winner(&self) {
-        //buscar hash do meu auction
-        //ir buscar highest bid
-        //
-        let mut entries: Vec<_> = self.open.iter().collect();
-        entries.sort_by(|a, b| a.0.cmp(b.0));
-        for (_, value) in entries {
-            match &value.data {
-                Data::CreateAuction(a) => {
-                    if a.seller_id != self.id {
-                        continue;
-                    }
-                    let mut m = Marco::from_winner(
-                        Winner::new(value.get_hash(), 
-                            self.my_auction_highest.amount,
-                            self.id.clone(),
-                            self.my_auction_highest.buyer_id.clone()));
-                    self.add_and_broadcast(m.borrow_mut());
-                }
-                _ => {}
-            }
+        let (auction_hash, sealed) = entries[auction_id].clone();
+        let res = if sealed {
+            // The amount of sealed bids stays secret until the reveal phase
+            self.wallet.commit_bid(&auction_hash, value)
+                .map(|(_, salt)| { self.sealed_bids.insert(auction_hash.clone(), (value, salt)); })
+        } else {
+            self.wallet.place_bid(&auction_hash, value).map(|_| ())
+        };
+        if let Err(e) = res {
+            println!("There was an issue with the bid: {}", e);
         }
     }
 
     pub fn search_auctions(&mut self) {
-        self.update_open_auctions();
+        self.update_book();
 
         //print auction map
         println!("Printing List of Auction");
-        let mut entries: Vec<_> = self.open.iter().collect();
-        entries.sort_by(|a, b| a.0.cmp(b.0));
-        let mut i = 0;
-        for (_, value) in entries {
-            let amount: f64;
-            match &value.data {
-                Data::CreateAuction(a) => {
-                    amount = a.amount;
-                    if a.seller_id == self.id {
-                        continue;
-                    }
-                }
-                _ => {continue;}
-            }
-
-            println!("Auction {}: Auctioning {} {}ubiously {}nsecure {}oin {}eeper(s)",i, amount,
+        let (auctions, (height, timestamp)) = {
+            let bc = self.wallet.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        for (i, entry) in self.book.biddable(height, timestamp).into_iter().enumerate() {
+            let a = &entry.auction.data;
+            println!("Auction {}: Auctioning {} {}ubiously {}nsecure {}oin {}eeper(s)",i, a.amount,
                 "D".bold().bright_red(),
                 "I".bold().bright_yellow(),
                 "C".bold().bright_blue(),
                 "K".bold().bright_magenta());
-            
-            let res = self.all_bids.get(&value.to_hash());
-            //println!("Res: {:?}", res);
-            match res  {
+            println!("\t {} auction, ends {}", a.get_type(), a.get_end());
+            if let (AuctionType::Dutch(_), Some(state)) = (a.get_type(), auctions.get(entry.id())) {
+                println!("\t The price is now {}, the first bid wins", state.minimum_bid(height));
+            }
+            let prices = a.get_terms();
+            if let Some(reserve) = prices.reserve {
+                println!("\t Reserve price of {}", reserve);
+            }
+            if let Some(buy_now) = prices.buy_now {
+                println!("\t Buy it now for {}", buy_now);
+            }
+            if let Some(item) = a.get_item() {
+                println!("\t Sold with item {}", item);
+            }
+
+            if a.get_sealed().is_some() {
+                println!("\t Sealed auction with {} bid(s)", entry.commits.len());
+                continue;
+            }
+            match entry.highest_bid() {
+                None => println!("\t Currently, this auction does not have a bid"),
+                Some(b) => println!("\thighest bid is {} euros", b.data.amount),
+            }
+            if let Some(amount) = entry.bid_of(&self.wallet.id) {
+                println!("\tyour bid is {} euros", amount);
+            }
+        }
+
+        let mine = self.book.selling();
+        if !mine.is_empty() {
+            println!("Your Auctions");
+        }
+        for (i, entry) in mine.into_iter().enumerate() {
+            let a = &entry.auction.data;
+            println!("Your Auction {}: Auctioning {}, {} auction, ends {}", i, a.amount, a.get_type(), a.get_end());
+            if a.get_sealed().is_some() {
+                println!("\t {} sealed bid(s)", entry.commits.len());
+                continue;
+            }
+            match entry.highest_bid() {
                 None => println!("\t Currently, this auction does not have a bid"),
-                Some(b) => println!("\thighest bid is {} euros", b),
+                Some(b) => println!("\thighest bid is {} euros by {}", b.data.amount, b.data.buyer_id),
             }
-            
-            i+=1;
         }
     }
 
-    pub fn close_auction(&self) {
-        let b;
-        loop {
-            let x = self.get_user_input("Do you actually want to finish your auction early?\n");
-            let result = x.trim().parse::<bool>();
+    /// closes one of your auctions, the winner is derived from the chain like every other node does
+    pub fn close_auction(&mut self) {
+        self.update_book();
+        let mine: Vec<String> = self.book.selling().into_iter().map(|a| a.id().clone()).collect();
+        if mine.is_empty() {
+            println!("You have no open auctions");
+            return;
+        }
+        for (i, id) in mine.iter().enumerate() {
+            println!("Your Auction {}: {}", i, id);
+        }
 
-            match result {
-                Ok(ob) => {
-                    b = ob;
+        let index :usize;
+        loop {
+            let x = self.get_user_input("What auction do you want to finish?\n");
+            match x.trim().parse::<usize>() {
+                Ok(number) if number < mine.len() => {
+                    index = number;
                     break;
                 }
+                Ok(_) => println!("A positive integer, between 0 and {}, is needed", mine.len() - 1),
                 Err(e) => println!("A positive integer is needed: {}", e),
             }
         }
-        if b {
-            self.check_winner();
+        let answer = self.get_user_input("Do you actually want to finish your auction early? (y/n)\n");
+        if answer.trim().eq_ignore_ascii_case("y") {
+            if let Err(e) = self.wallet.close_auction(&mine[index]) {
+                println!("Could not close auction {}: {}", mine[index], e);
+            }
         }
     }
 }
@@ -0,0 +1,356 @@
+#[doc(inline)]
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use crate::ledger::auctions::AuctionState;
+use crate::marco::amount::Amount;
+use crate::marco::auction::Auction;
+use crate::marco::bid::Bid;
+use crate::marco::marco::{Data, Marco};
+use crate::marco::sealed_bid::BidCommit;
+use crate::marco::winner::Winner;
+
+/// ## Recorded
+/// Data of a Marco the book has seen, `confirmed` once it is in a block of the main branch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded<T> {
+    pub hash: String,
+    pub timestamp: SystemTime,
+    pub confirmed: bool,
+    pub data: T,
+}
+
+impl<T> Recorded<T> {
+    fn of(m: &Marco, confirmed: bool, data: T) -> Self {
+        Recorded { hash: m.get_hash(), timestamp: m.timestamp, confirmed, data }
+    }
+}
+
+/// ## BookAuction
+/// One auction with its bid history, ordered by timestamp (then hash)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookAuction {
+    pub auction: Recorded<Auction>,
+    /// open bids, and the revealed bids of sealed auctions
+    pub bids: Vec<Recorded<Bid>>,
+    pub commits: Vec<Recorded<BidCommit>>,
+    pub winner: Option<Recorded<Winner>>,
+}
+
+impl BookAuction {
+    /// the auction id, the hash of the Marco that created it
+    pub fn id(&self) -> &String {
+        &self.auction.hash
+    }
+
+    /// the auction as the ledger sees it, from the Marcos the book has seen.
+    ///
+    /// The book does not know the block that opened the auction, `opened_at` is 0
+    pub fn state(&self) -> AuctionState {
+        AuctionState {
+            id: self.auction.hash.clone(),
+            auction: self.auction.data.clone(),
+            opened_at: 0,
+            bids: self.bids.iter().map(|b| b.data.clone()).collect(),
+            commits: self.commits.iter().map(|c| c.data.clone()).collect(),
+            winner: self.winner.as_ref().map(|w| w.data.clone()),
+        }
+    }
+
+    /// returns true if a bid at `height` with `timestamp` would still be accepted, see [AuctionState::is_open]
+    pub fn is_open(&self, height: u64, timestamp: u64) -> bool {
+        self.state().is_open(height, timestamp)
+    }
+
+    /// highest bid that meets the reserve price, see [AuctionState::highest_bid]
+    pub fn highest_bid(&self) -> Option<&Recorded<Bid>> {
+        let state = self.state();
+        let highest = state.highest_bid()?;
+        self.bids.iter().find(|b| &b.data == highest)
+    }
+
+    /// highest bid of `buyer_id`
+    pub fn bid_of(&self, buyer_id: &String) -> Option<Amount> {
+        self.bids.iter()
+            .filter(|b| &b.data.buyer_id == buyer_id)
+            .map(|b| b.data.amount)
+            .max()
+    }
+}
+
+/// ## BookEvent
+/// Changes the owner of an [AuctionBook] cares about
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookEvent {
+    /// someone else opened an auction
+    AuctionOpened { auction_id: String, amount: Amount },
+    /// a bid on one of our auctions
+    BidReceived { auction_id: String, amount: Amount },
+    /// someone outbid us on an auction we bid on
+    Outbid { auction_id: String, amount: Amount },
+    /// an auction we sell or bid on has a winner
+    Closed { auction_id: String, winner: Winner },
+}
+
+/// ## AuctionBook
+/// Auctions, bids and winners seen by `owner`, keyed by auction hash.
+///
+/// It is updated incrementally with [AuctionBook::update], every Marco is only applied once,
+/// so the same Marcos give the same book whatever order the node received them in
+#[derive(Debug, Clone, Default)]
+pub struct AuctionBook {
+    owner: String,
+    auctions: BTreeMap<String, BookAuction>,
+    /// auction id of every Marco already applied
+    seen: HashMap<String, String>,
+    /// Marcos of auctions not seen yet, and whether they are confirmed, by auction id and hash
+    orphans: HashMap<String, HashMap<String, (Marco, bool)>>,
+}
+
+impl AuctionBook {
+    pub fn new(owner: String) -> Self {
+        AuctionBook { owner, auctions: BTreeMap::new(), seen: HashMap::new(), orphans: HashMap::new() }
+    }
+
+    pub fn get(&self, auction_id: &String) -> Option<&BookAuction> {
+        self.auctions.get(auction_id)
+    }
+
+    /// every auction, ordered by id
+    pub fn iter(&self) -> impl Iterator<Item = &BookAuction> {
+        self.auctions.values()
+    }
+
+    /// open auctions of other sellers ordered by id, the list the user picks from
+    pub fn biddable(&self, height: u64, timestamp: u64) -> Vec<&BookAuction> {
+        self.iter()
+            .filter(|a| a.auction.data.seller_id != self.owner && a.is_open(height, timestamp))
+            .collect()
+    }
+
+    /// our auctions that have no winner yet, ordered by id
+    pub fn selling(&self) -> Vec<&BookAuction> {
+        self.iter()
+            .filter(|a| a.auction.data.seller_id == self.owner && a.winner.is_none())
+            .collect()
+    }
+
+    /// applies the Marcos of the blocks that became final since the last update and of the blocks of the
+    /// main branch that are not final yet (both in chain order), then the pending ones, ordered by timestamp and hash.
+    ///
+    /// `main` and `pending` are given in full on every update, so a Marco that a reorganization took out
+    /// of the main branch is unconfirmed again.
+    /// Marcos of auctions not seen yet are kept, and applied once their auction is
+    ///
+    /// **outputs:**
+    /// the events caused by the new Marcos
+    pub fn update<'a>(&mut self, finals: impl IntoIterator<Item = &'a Marco>, main: impl IntoIterator<Item = &'a Marco>,
+                      pending: impl IntoIterator<Item = &'a Marco>) -> Vec<BookEvent> {
+        let mut pending: Vec<&Marco> = pending.into_iter().collect();
+        pending.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.hash.cmp(&b.hash)));
+        let mut events = Vec::new();
+        for m in finals.into_iter().chain(main) {
+            events.extend(self.insert(m, true));
+        }
+        for m in pending {
+            events.extend(self.insert(m, false));
+        }
+        events
+    }
+
+    /// applies one Marco, or only updates whether it is confirmed if it was already applied.
+    /// A Marco of an unknown auction is kept until the auction is applied
+    ///
+    /// **outputs:**
+    /// the events caused by the Marco, and by the kept Marcos of the auction it creates
+    pub fn insert(&mut self, m: &Marco, confirmed: bool) -> Vec<BookEvent> {
+        let hash = m.get_hash();
+        if let Some(auction_id) = self.seen.get(&hash) {
+            self.set_confirmed(&auction_id.clone(), &hash, confirmed);
+            return Vec::new();
+        }
+        let auction_id = match &m.data {
+            Data::Bid(b) => &b.auction_id,
+            Data::BidReveal(r) => &r.auction_id,
+            Data::BidCommit(c) => &c.auction_id,
+            Data::Winner(w) => &w.auction,
+            Data::CreateAuction(_) => {
+                let mut events: Vec<BookEvent> = self.apply(m, confirmed).into_iter().collect();
+                let mut orphans: Vec<(Marco, bool)> = self.orphans.remove(&hash).unwrap_or_default().into_values().collect();
+                orphans.sort_by(|(a, _), (b, _)| a.timestamp.cmp(&b.timestamp).then(a.hash.cmp(&b.hash)));
+                for (orphan, confirmed) in orphans {
+                    events.extend(self.insert(&orphan, confirmed));
+                }
+                return events;
+            }
+            _ => return Vec::new(),
+        };
+        if !self.auctions.contains_key(auction_id) {
+            self.orphans.entry(auction_id.clone()).or_default().insert(hash, (m.clone(), confirmed));
+            return Vec::new();
+        }
+        self.apply(m, confirmed).into_iter().collect()
+    }
+
+    /// applies a Marco that was not applied yet, whose auction (if any) is known
+    fn apply(&mut self, m: &Marco, confirmed: bool) -> Option<BookEvent> {
+        let hash = m.get_hash();
+        let (auction_id, event) = match &m.data {
+            Data::CreateAuction(a) => {
+                self.auctions.insert(hash.clone(), BookAuction {
+                    auction: Recorded::of(m, confirmed, a.clone()),
+                    bids: Vec::new(),
+                    commits: Vec::new(),
+                    winner: None,
+                });
+                let event = (a.seller_id != self.owner)
+                    .then(|| BookEvent::AuctionOpened { auction_id: hash.clone(), amount: a.amount });
+                (hash.clone(), event)
+            }
+            Data::Bid(b) => (b.auction_id.clone(), self.insert_bid(Recorded::of(m, confirmed, b.clone()))?),
+            Data::BidReveal(r) => {
+                let seller_id = self.auctions.get(&r.auction_id)?.auction.data.seller_id.clone();
+                let b = Bid::new(r.auction_id.clone(), r.buyer_id.clone(), seller_id, r.amount);
+                (r.auction_id.clone(), self.insert_bid(Recorded::of(m, confirmed, b))?)
+            }
+            Data::BidCommit(c) => {
+                let commits = &mut self.auctions.get_mut(&c.auction_id)?.commits;
+                let i = commits.partition_point(|other| (other.timestamp, &other.hash) <= (m.timestamp, &hash));
+                commits.insert(i, Recorded::of(m, confirmed, c.clone()));
+                (c.auction_id.clone(), None)
+            }
+            Data::Winner(w) => {
+                let owner = &self.owner;
+                let entry = self.auctions.get_mut(&w.auction)?;
+                let involved = entry.auction.data.seller_id == *owner || entry.bid_of(owner).is_some()
+                    || entry.commits.iter().any(|c| c.data.buyer_id == *owner);
+                entry.winner = Some(Recorded::of(m, confirmed, w.clone()));
+                let event = involved.then(|| BookEvent::Closed { auction_id: w.auction.clone(), winner: w.clone() });
+                (w.auction.clone(), event)
+            }
+            _ => return None,
+        };
+        self.seen.insert(hash, auction_id);
+        event
+    }
+
+    /// adds the bid to the history of its auction
+    ///
+    /// **outputs:**
+    /// None if the auction is unknown, otherwise the event for the owner, if any
+    fn insert_bid(&mut self, b: Recorded<Bid>) -> Option<Option<BookEvent>> {
+        let owner = &self.owner;
+        let entry = self.auctions.get_mut(&b.data.auction_id)?;
+        let ours = entry.bid_of(owner);
+        let event = if entry.auction.data.seller_id == *owner {
+            Some(BookEvent::BidReceived { auction_id: b.data.auction_id.clone(), amount: b.data.amount })
+        } else if b.data.buyer_id != *owner && ours.is_some_and(|amount| b.data.amount > amount) {
+            Some(BookEvent::Outbid { auction_id: b.data.auction_id.clone(), amount: b.data.amount })
+        } else {
+            None
+        };
+        let i = entry.bids.partition_point(|other| (other.timestamp, &other.hash) <= (b.timestamp, &b.hash));
+        entry.bids.insert(i, b);
+        Some(event)
+    }
+
+    fn set_confirmed(&mut self, auction_id: &String, hash: &String, confirmed: bool) {
+        let Some(entry) = self.auctions.get_mut(auction_id) else { return };
+        if &entry.auction.hash == hash {
+            entry.auction.confirmed = confirmed;
+        }
+        for b in entry.bids.iter_mut().filter(|b| &b.hash == hash) {
+            b.confirmed = confirmed;
+        }
+        for c in entry.commits.iter_mut().filter(|c| &c.hash == hash) {
+            c.confirmed = confirmed;
+        }
+        if let Some(w) = entry.winner.as_mut().filter(|w| &w.hash == hash) {
+            w.confirmed = confirmed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::auction::auction_book::*;
+    use crate::marco::auction::{AuctionType, DutchTerms, EndCondition};
+
+    fn coins(n: u64) -> Amount {
+        Amount::from_coins(n).unwrap()
+    }
+
+    /// Marco created `secs` seconds after the epoch, so the order does not depend on the clock
+    fn at(mut m: Marco, secs: u64) -> Marco {
+        m.timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        m.hash = m.to_hash();
+        m
+    }
+
+    fn bid(auction: &Marco, buyer: &str, n: u64, secs: u64) -> Marco {
+        at(Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(), "seller".to_string(), coins(n)), n), secs)
+    }
+
+    #[test]
+    fn test_order_does_not_matter() {
+        let a = at(Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10)), 0), 1);
+        let b1 = bid(&a, "alice", 2, 2);
+        let b2 = bid(&a, "bob", 3, 3);
+        let b3 = bid(&a, "alice", 4, 4);
+
+        let mut in_order = AuctionBook::new("alice".to_string());
+        in_order.update([&a], [], [&b1, &b2, &b3]);
+        // The auction arrives after its bids, they are kept and applied once it is known
+        let mut shuffled = AuctionBook::new("alice".to_string());
+        assert!(shuffled.update([], [], [&b3, &b1]).is_empty());
+        shuffled.update([&a], [], [&b2]);
+
+        let entry = in_order.get(&a.get_hash()).unwrap();
+        assert_eq!(entry, shuffled.get(&a.get_hash()).unwrap());
+        assert_eq!(entry.bids.iter().map(|b| b.data.amount).collect::<Vec<_>>(), vec![coins(2), coins(3), coins(4)]);
+        assert_eq!(entry.highest_bid().unwrap().data.buyer_id, "alice");
+        assert!(entry.auction.confirmed && !entry.bids[0].confirmed);
+
+        // Already applied Marcos are only confirmed
+        assert!(in_order.update([], [&b1], [&b2, &b3]).is_empty());
+        assert!(in_order.get(&a.get_hash()).unwrap().bids[0].confirmed);
+        assert_eq!(in_order.get(&a.get_hash()).unwrap().bids.len(), 3);
+        // A reorganization takes the block out of the main branch, its Marcos are pending again
+        assert!(in_order.update([], [], [&b1, &b2, &b3]).is_empty());
+        assert!(!in_order.get(&a.get_hash()).unwrap().bids[0].confirmed);
+    }
+
+    #[test]
+    fn test_events() {
+        let a = at(Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10)), 0), 1);
+        let mut alice = AuctionBook::new("alice".to_string());
+        let mut seller = AuctionBook::new("seller".to_string());
+        let id = a.get_hash();
+
+        assert_eq!(alice.update([&a], [], []), vec![BookEvent::AuctionOpened { auction_id: id.clone(), amount: coins(5) }]);
+        assert!(seller.update([&a], [], []).is_empty());
+        assert_eq!(alice.biddable(1, 0).len(), 1);
+        assert!(seller.biddable(1, 0).is_empty());
+
+        let (b1, b2) = (bid(&a, "alice", 2, 2), bid(&a, "bob", 3, 3));
+        assert!(alice.update([], [], [&b1]).is_empty());
+        assert_eq!(alice.update([], [], [&b2]), vec![BookEvent::Outbid { auction_id: id.clone(), amount: coins(3) }]);
+        assert_eq!(seller.update([], [], [&b1, &b2]).len(), 2);
+
+        let w = at(Marco::from_winner(Winner::new(id.clone(), coins(3), "seller".to_string(), "bob".to_string()), 0), 4);
+        assert!(matches!(alice.update([&w], [], []).as_slice(), [BookEvent::Closed { .. }]));
+        assert!(!alice.get(&id).unwrap().is_open(1, 0));
+        assert!(alice.biddable(1, 0).is_empty());
+
+        // The first bid closes a Dutch auction
+        let dutch = DutchTerms { start_price: coins(5), decrement: coins(1), floor: coins(1) };
+        let d = at(Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10))
+            .with_type(AuctionType::Dutch(dutch)), 1), 5);
+        alice.update([&d], [], []);
+        assert!(alice.get(&d.get_hash()).unwrap().is_open(1, 0));
+        alice.update([], [], [&bid(&d, "bob", 5, 6)]);
+        assert!(!alice.get(&d.get_hash()).unwrap().is_open(1, 0));
+    }
+}
@@ -0,0 +1,317 @@
+#[doc(inline)]
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures::Stream;
+use log::debug;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+
+use crate::auction::events::{self, EventKind, FINAL_DEPTH};
+use crate::auction::item::ItemDescriptor;
+use crate::auction::report::{History, ReportFilter, ReportFormat, ReportKind};
+use crate::auction::wallet::Wallet;
+use crate::auxi;
+use crate::ledger::auctions::AuctionState;
+use crate::marco::amount::Amount;
+use crate::marco::auction::{AuctionType, PriceTerms};
+use crate::marco::marco::{Data, Marco};
+use crate::marco::payload::registry;
+use crate::proto;
+use crate::proto::{AuctionEvent, AuctionEventKind, AuctionInfo, CloseAuctionRequest, GetAuctionRequest, ItemDescriptor as ProtoItemDescriptor, ListAuctionsRequest, ListAuctionsResponse,
+    MarcoReply, OpenAuctionRequest, PlaceBidRequest, ReportRequest, ReportResponse, SubscribeEventsRequest};
+use crate::proto::auction_service_server::AuctionService;
+use crate::proto::data::DataType as ProtoDataType;
+use crate::proto::sealed_terms::RevealEnd;
+
+/// How often [AuctionService::watch_auction] checks the ledger for changes,
+/// the auctions are only rebuilt when [Blockchain::changes](crate::ledger::blockchain::Blockchain::changes) moved
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// an auction and the [changes](crate::ledger::blockchain::Blockchain::changes) of the ledger it was read at
+type WatchedAuction = (u64, AuctionState);
+
+#[tonic::async_trait]
+impl AuctionService for Wallet {
+    type WatchAuctionStream = Pin<Box<dyn Stream<Item = Result<AuctionInfo, Status>> + Send>>;
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<AuctionEvent, Status>> + Send>>;
+
+    async fn open_auction(&self, request: Request<OpenAuctionRequest>) -> Result<Response<MarcoReply>, Status> {
+        let input = request.get_ref();
+        let sealed = match &input.sealed {
+            None => None,
+            Some(terms) => match terms.reveal_end {
+                Some(RevealEnd::RevealEndHeight(blocks)) => Some((blocks, Amount::from_units(terms.deposit))),
+                _ => return Err(Status::invalid_argument("The reveal phase is a number of blocks")),
+            },
+        };
+        let terms = input.terms.as_ref().map(PriceTerms::from_proto).unwrap_or_default();
+        let auction_type = AuctionType::from_proto(input.auction_type, input.dutch.as_ref())
+            .ok_or(Status::invalid_argument("Dutch auctions need their terms"))?;
+        let item = match &input.item {
+            None => None,
+            Some(item) => Some(self.publish_item(&ItemDescriptor::from_proto(item)).await.map_err(Self::rejected)?),
+        };
+        self.open_auction(Amount::from_units(input.amount), input.blocks, sealed, terms, auction_type, item).map(Self::reply).map_err(Self::rejected)
+    }
+
+    async fn place_bid(&self, request: Request<PlaceBidRequest>) -> Result<Response<MarcoReply>, Status> {
+        let input = request.get_ref();
+        self.place_bid(&input.auction_id, Amount::from_units(input.amount)).map(Self::reply).map_err(Self::rejected)
+    }
+
+    async fn close_auction(&self, request: Request<CloseAuctionRequest>) -> Result<Response<MarcoReply>, Status> {
+        self.close_auction(&request.get_ref().auction_id).map(Self::reply).map_err(Self::rejected)
+    }
+
+    async fn list_auctions(&self, request: Request<ListAuctionsRequest>) -> Result<Response<ListAuctionsResponse>, Status> {
+        let (auctions, (height, timestamp)) = {
+            let bc = self.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        let mut list: Vec<&AuctionState> = auctions.iter()
+            .filter(|a| !request.get_ref().open_only || a.is_open(height, timestamp))
+            .collect();
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        let auctions = list.into_iter()
+            .map(|a| Self::auction_info(a, height, timestamp))
+            .collect();
+        Ok(Response::new(ListAuctionsResponse { auctions }))
+    }
+
+    async fn get_auction(&self, request: Request<GetAuctionRequest>) -> Result<Response<AuctionInfo>, Status> {
+        let auction_id = &request.get_ref().auction_id;
+        match self.find_auction_info(auction_id) {
+            None => Err(Status::not_found(format!("Auction {} was not found", auction_id))),
+            Some(info) => Ok(Response::new(info)),
+        }
+    }
+
+    /// sends the auction as it is now and again every time it changes (a bid, its end, its winner),
+    /// the stream ends once the winner was sent
+    async fn watch_auction(&self, request: Request<GetAuctionRequest>) -> Result<Response<Self::WatchAuctionStream>, Status> {
+        let auction_id = request.get_ref().auction_id.clone();
+        if self.find_auction_info(&auction_id).is_none() {
+            return Err(Status::not_found(format!("Auction {} was not found", auction_id)));
+        }
+        let state: (Wallet, String, Option<AuctionInfo>, Option<WatchedAuction>) = (self.clone(), auction_id, None, None);
+        let stream = futures::stream::unfold(state, |(wallet, auction_id, last, cached)| async move {
+            if last.as_ref().is_some_and(|info| info.winner.is_some()) {
+                return None;
+            }
+            let mut cached = cached;
+            loop {
+                // Only a reorganization can make a known auction disappear
+                let (watched, (height, timestamp)) = wallet.watched_auction(&auction_id, cached)?;
+                let info = Self::auction_info(&watched.1, height, timestamp);
+                if last.as_ref() != Some(&info) {
+                    return Some((Ok(info.clone()), (wallet, auction_id, Some(info), Some(watched))));
+                }
+                cached = Some(watched);
+                tokio::time::sleep(WATCH_INTERVAL).await;
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// sends each event of the watched auctions once, as soon as it has `min_depth` confirmations
+    async fn subscribe_events(&self, request: Request<SubscribeEventsRequest>) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let input = request.into_inner();
+        let watched: HashSet<String> = input.auction_ids.into_iter().collect();
+        // Events are not sent again past the final depth
+        let min_depth = input.min_depth.min(FINAL_DEPTH);
+        // Keys of the events sent that may come again deeper, by auction
+        let sent: HashMap<String, HashSet<String>> = HashMap::new();
+        let state = (self.events.subscribe(), watched, sent);
+        let stream = futures::stream::unfold(state, move |(mut receiver, watched, mut sent)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(e) => {
+                        let wanted = if watched.is_empty() { e.mine } else { watched.contains(&e.auction_id) };
+                        if !wanted || e.depth < min_depth {
+                            continue;
+                        }
+                        let known = sent.get_mut(&e.auction_id).is_some_and(|keys| keys.remove(&e.key()));
+                        // A final event is not sent again, and the final winner ends the auction
+                        if e.depth < FINAL_DEPTH {
+                            sent.entry(e.auction_id.clone()).or_default().insert(e.key());
+                        } else if e.kind == EventKind::WinnerDeclared {
+                            sent.remove(&e.auction_id);
+                        }
+                        if !known {
+                            return Some((Ok(Self::event_proto(&e)), (receiver, watched, sent)));
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => debug!("DEBUG AUCTION_RPC_SERVER::SUBSCRIBE_EVENTS => {} events were missed", missed),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_item(&self, request: Request<GetAuctionRequest>) -> Result<Response<ProtoItemDescriptor>, Status> {
+        let auction_id = &request.get_ref().auction_id;
+        if self.find_auction_info(auction_id).is_none() {
+            return Err(Status::not_found(format!("Auction {} was not found", auction_id)));
+        }
+        self.fetch_item(auction_id).await
+            .map(|item| Response::new(item.to_proto()))
+            .map_err(Status::not_found)
+    }
+
+    async fn get_report(&self, request: Request<ReportRequest>) -> Result<Response<ReportResponse>, Status> {
+        let input = request.into_inner();
+        let kind = match proto::ReportKind::try_from(input.kind) {
+            Ok(proto::ReportKind::Auctions) => ReportKind::Auctions,
+            Ok(proto::ReportKind::Bids) => ReportKind::Bids,
+            Ok(proto::ReportKind::Sellers) => ReportKind::Sellers,
+            Ok(proto::ReportKind::Bidders) => ReportKind::Bidders,
+            Err(_) => return Err(Status::invalid_argument("Unknown report")),
+        };
+        let format = match proto::ReportFormat::try_from(input.format) {
+            Ok(proto::ReportFormat::Csv) => ReportFormat::Csv,
+            Ok(proto::ReportFormat::Json) => ReportFormat::Json,
+            Err(_) => return Err(Status::invalid_argument("Unknown report format")),
+        };
+        let filter = ReportFilter {
+            seller: (!input.seller.is_empty()).then_some(input.seller),
+            from: (input.from != 0).then_some(input.from),
+            to: (input.to != 0).then_some(input.to),
+            auctions: input.auction_ids.into_iter().collect(),
+        };
+        let blocks = self.peer.blockchain.lock().unwrap().chain.clone();
+        let content = History::from_blocks(&blocks).report(kind, &filter).render(format);
+        Ok(Response::new(ReportResponse { content }))
+    }
+}
+
+impl Wallet {
+    fn reply(m: Marco) -> Response<MarcoReply> {
+        Response::new(MarcoReply { marco: Some(auxi::transform_marco_to_proto(&m)) })
+    }
+
+    fn rejected(e: String) -> Status {
+        debug!("DEBUG AUCTION_RPC_SERVER => {}", e);
+        Status::failed_precondition(e)
+    }
+
+    fn find_auction_info(&self, auction_id: &String) -> Option<AuctionInfo> {
+        let (auctions, (height, timestamp)) = {
+            let bc = self.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        auctions.get(auction_id).map(|a| Self::auction_info(a, height, timestamp))
+    }
+
+    /// the auction as of the latest [changes](crate::ledger::blockchain::Blockchain::changes) of the ledger,
+    /// `cached` is returned as it is when nothing changed since, instead of replaying the chain again
+    ///
+    /// **outputs:**
+    /// the auction and the position of the next block, None if the auction is not in the ledger
+    fn watched_auction(&self, auction_id: &String, cached: Option<WatchedAuction>) -> Option<(WatchedAuction, (u64, u64))> {
+        let bc = self.peer.blockchain.lock().unwrap();
+        let watched = match cached {
+            Some((changes, state)) if changes == bc.changes => (changes, state),
+            _ => (bc.changes, bc.auctions().get(auction_id)?.clone()),
+        };
+        Some((watched, bc.next_position()))
+    }
+
+    fn event_proto(e: &events::AuctionEvent) -> AuctionEvent {
+        let kind = match e.kind {
+            EventKind::NewBid => AuctionEventKind::NewBid,
+            EventKind::Outbid => AuctionEventKind::Outbid,
+            EventKind::Closed => AuctionEventKind::Closed,
+            EventKind::WinnerDeclared => AuctionEventKind::WinnerDeclared,
+        };
+        AuctionEvent {
+            auction_id: e.auction_id.clone(),
+            kind: kind as i32,
+            buyer_id: e.buyer_id.clone(),
+            amount: e.amount.units(),
+            marco: e.marco.clone(),
+            depth: e.depth,
+        }
+    }
+
+    /// proto view of the auction at the position of the next block,
+    /// the Auction, Bid and Winner messages come from the payload [registry]
+    fn auction_info(state: &AuctionState, height: u64, timestamp: u64) -> AuctionInfo {
+        let encode = |data: Data| registry().encode(&data).data_type;
+        AuctionInfo {
+            id: state.id.clone(),
+            auction: match encode(Data::CreateAuction(state.auction.clone())) {
+                Some(ProtoDataType::CreateAuction(a)) => Some(a),
+                _ => None,
+            },
+            bids: state.bids.iter()
+                .filter_map(|b| match encode(Data::Bid(b.clone())) {
+                    Some(ProtoDataType::Bid(b)) => Some(b),
+                    _ => None,
+                })
+                .collect(),
+            winner: state.winner.clone().and_then(|w| match encode(Data::Winner(w)) {
+                Some(ProtoDataType::Winner(w)) => Some(w),
+                _ => None,
+            }),
+            open: state.is_open(height, timestamp),
+            minimum_bid: state.minimum_bid(height).units(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tonic::{Code, Request};
+
+    use crate::auction::wallet::Wallet;
+    use crate::kademlia::node::Node;
+    use crate::ledger::block::Block;
+    use crate::ledger::blockchain::Blockchain;
+    use crate::marco::amount::Amount;
+    use crate::marco::auction::{AuctionType, PriceTerms};
+    use crate::p2p::peer::Peer;
+    use crate::proto::GetAuctionRequest;
+    use crate::proto::auction_service_server::AuctionService;
+
+    /// wallet of a node that is not connected to anyone, with one mining reward to spend
+    fn funded_wallet(port: u32) -> Wallet {
+        let (_, client) = Peer::new(&Node::new("127.0.0.1".to_string(), port).unwrap(), false);
+        let wallet = Wallet::load(client);
+        let mut bc = wallet.peer.blockchain.lock().unwrap();
+        let h = bc.get_head();
+        let mut b = Block::new(h.index + 1, h.hash, bc.difficulty, wallet.id.clone(), Blockchain::MINING_REWARD);
+        b.mine();
+        assert!(bc.add_block(b));
+        drop(bc);
+        wallet
+    }
+
+    fn request(auction_id: &str) -> Request<GetAuctionRequest> {
+        Request::new(GetAuctionRequest { auction_id: auction_id.to_string() })
+    }
+
+    #[tokio::test]
+    async fn test_get_and_watch_auction() {
+        let wallet = funded_wallet(7300);
+        let amount = Amount::from_units(Blockchain::MINING_REWARD.units() / 2);
+        let auction_id = wallet.open_auction(amount, 10, None, PriceTerms::default(), AuctionType::English, None).unwrap().get_hash();
+
+        let info = wallet.get_auction(request(&auction_id)).await.unwrap().into_inner();
+        assert_eq!((info.id.as_str(), info.open, info.winner.is_some()), (auction_id.as_str(), true, false));
+        assert_eq!(wallet.get_auction(request("unknown")).await.unwrap_err().code(), Code::NotFound);
+        assert_eq!(wallet.watch_auction(request("unknown")).await.err().unwrap().code(), Code::NotFound);
+
+        // The auction as it is now, then once more when it is closed, and the stream ends
+        let mut stream = wallet.watch_auction(request(&auction_id)).await.unwrap().into_inner();
+        assert_eq!(stream.next().await.unwrap().unwrap(), info);
+        wallet.close_auction(&auction_id).unwrap();
+        let closed = stream.next().await.unwrap().unwrap();
+        assert!(closed.winner.is_some() && !closed.open);
+        assert!(stream.next().await.is_none());
+    }
+}
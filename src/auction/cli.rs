@@ -0,0 +1,460 @@
+#[doc(inline)]
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, ErrorKind};
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+
+use crate::marco::amount::Amount;
+use crate::marco::auction::{AuctionType, DutchTerms, PriceTerms};
+use crate::p2p::peer::Peer;
+use crate::proto;
+use crate::proto::auction::End;
+use crate::proto::auction_service_client::AuctionServiceClient;
+use crate::proto::packet_sending_client::PacketSendingClient;
+use crate::proto::sealed_terms::RevealEnd;
+
+/// Port of the node started with `cargo node`, used when `--node` is not given
+pub const DEFAULT_NODE_PORT: u32 = 8636;
+
+const USAGE: &str = "Usage:
+    auction open --amount <coins> [--blocks <n>] [--reveal-blocks <n> --deposit <coins>]
+                 [--reserve <coins>] [--increment <coins>] [--buy-now <coins>]
+                 [--type english|vickrey] [--type dutch --start-price <coins> --decrement <coins> --floor <coins>]
+                 [--title <text> [--description <text>] [--image <url>] [--terms <text>]] [--node <ip:port>]
+    auction bid --auction <hash> --amount <coins> [--node <ip:port>]
+    auction list [--open] [--node <ip:port>]
+    auction close --auction <hash> [--node <ip:port>]
+    auction show --auction <hash> [--node <ip:port>]
+    auction item --auction <hash> [--node <ip:port>]
+    auction watch --auction <hash> [--node <ip:port>]
+    auction events [--auction <hash>,<hash>...] [--depth <n>] [--node <ip:port>]
+    report auctions|bids|sellers|bidders [--format csv|json] [--seller <id>] [--from <timestamp>] [--to <timestamp>]
+                 [--auction <hash>,<hash>...] [--node <ip:port>]
+    chain show [--node <ip:port>]";
+
+/// Blocks an auction takes bids for when `--blocks` is not given
+const DEFAULT_AUCTION_BLOCKS: u64 = 10;
+
+/// ## Command
+/// Subcommand and `--flag value` pairs, flags without a value are "true"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub words: Vec<String>,
+    pub flags: HashMap<String, String>,
+}
+
+impl Command {
+    pub fn parse(args: &[String]) -> Command {
+        let mut words = Vec::new();
+        let mut flags = HashMap::new();
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].strip_prefix("--") {
+                Some(flag) => {
+                    match args.get(i + 1).filter(|v| !v.starts_with("--")) {
+                        Some(value) => {
+                            flags.insert(flag.to_string(), value.clone());
+                            i += 1;
+                        }
+                        None => { flags.insert(flag.to_string(), "true".to_string()); }
+                    }
+                }
+                None => words.push(args[i].clone()),
+            }
+            i += 1;
+        }
+        Command { words, flags }
+    }
+
+    fn flag(&self, name: &str) -> Result<&String, String> {
+        self.flags.get(name).ok_or(format!("--{} is required", name))
+    }
+
+    fn amount(&self, name: &str) -> Result<Amount, String> {
+        Amount::from_str(self.flag(name)?).map_err(|e| format!("--{}: {}", name, e))
+    }
+
+    /// the item descriptor, only given with `--title`
+    fn item(&self) -> Option<proto::ItemDescriptor> {
+        let text = |name: &str| self.flags.get(name).cloned().unwrap_or_default();
+        self.flags.get("title").map(|title| proto::ItemDescriptor {
+            title: title.clone(),
+            description: text("description"),
+            image: text("image"),
+            terms: text("terms"),
+        })
+    }
+
+    fn optional_amount(&self, name: &str) -> Result<Option<Amount>, String> {
+        match self.flags.get(name) {
+            None => Ok(None),
+            Some(_) => self.amount(name).map(Some),
+        }
+    }
+
+    fn auction_type(&self) -> Result<AuctionType, String> {
+        match self.flags.get("type").map(|t| t.as_str()) {
+            None | Some("english") => Ok(AuctionType::English),
+            Some("vickrey") => Ok(AuctionType::Vickrey),
+            Some("dutch") => Ok(AuctionType::Dutch(DutchTerms {
+                start_price: self.amount("start-price")?,
+                decrement: self.amount("decrement")?,
+                floor: self.amount("floor")?,
+            })),
+            Some(other) => Err(format!("--type: unknown auction type {}", other)),
+        }
+    }
+
+    fn number(&self, name: &str, default: u64) -> Result<u64, String> {
+        match self.flags.get(name) {
+            None => Ok(default),
+            Some(v) => v.parse::<u64>().map_err(|e| format!("--{}: {}", name, e)),
+        }
+    }
+
+    /// comma separated values of the flag, empty without it
+    fn list(&self, name: &str) -> Vec<String> {
+        self.flags.get(name).map(|values| values.split(',').map(|v| v.to_string()).collect()).unwrap_or_default()
+    }
+
+    fn node(&self) -> String {
+        self.flags.get("node").cloned().unwrap_or_else(|| format!("127.0.0.1:{}", DEFAULT_NODE_PORT))
+    }
+
+    /// address of the AuctionService of the node, at its [auction port](Peer::auction_port).
+    /// It is only served on localhost, so the node must run on this machine
+    fn auction_node(&self) -> Result<String, String> {
+        let node = self.node();
+        let (host, port) = node.rsplit_once(':').and_then(|(host, port)| Some((host, port.parse::<u32>().ok()?)))
+            .ok_or(format!("--node: {} is not <ip:port>", node))?;
+        Ok(format!("{}:{}", host, Peer::auction_port(port)))
+    }
+}
+
+/// First words of the subcommands handled by [run] instead of starting a node
+const SUBCOMMANDS: [&str; 3] = ["auction", "chain", "report"];
+
+/// # is_subcommand
+/// ### Returns
+/// True if the process arguments (without the program name) are a subcommand for [run]
+pub fn is_subcommand(args: &[String]) -> bool {
+    args.first().is_some_and(|word| SUBCOMMANDS.contains(&word.as_str()))
+}
+
+/// # run
+/// Runs a subcommand against the node given by `--node` and prints the result as JSON (or CSV for reports).
+/// Streaming commands (`auction watch`, `auction events`) print one JSON object per line as they come, and nothing after.
+/// Errors are printed as `{"error": ...}` to stderr
+///
+/// ### Returns
+/// The exit code of the process
+pub async fn run(args: &[String]) -> i32 {
+    let command = Command::parse(args);
+    match execute(&command).await {
+        Ok(out) => {
+            print!("{}", render(&out));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", json!({ "error": e }));
+            1
+        }
+    }
+}
+
+/// # render
+/// ### Returns
+/// The text [run] prints for a result, empty for the streaming commands (which return [Value::Null])
+fn render(out: &Value) -> String {
+    match out {
+        Value::Null => "".to_string(),
+        // CSV reports are the only plain text results
+        Value::String(text) => text.clone(),
+        _ => format!("{}\n", serde_json::to_string_pretty(out).unwrap_or_default()),
+    }
+}
+
+async fn execute(command: &Command) -> Result<Value, String> {
+    let words: Vec<&str> = command.words.iter().map(|w| w.as_str()).collect();
+    match words.as_slice() {
+        ["auction", "open"] => {
+            let reveal_blocks = command.number("reveal-blocks", 0)?;
+            let sealed = if reveal_blocks > 0 || command.flags.contains_key("deposit") {
+                Some(proto::SealedTerms {
+                    reveal_end: Some(RevealEnd::RevealEndHeight(reveal_blocks)),
+                    deposit: command.amount("deposit")?.units(),
+                })
+            } else {
+                None
+            };
+            let terms = PriceTerms {
+                reserve: command.optional_amount("reserve")?,
+                increment: command.optional_amount("increment")?,
+                buy_now: command.optional_amount("buy-now")?,
+            };
+            let (auction_type, dutch) = command.auction_type()?.to_proto();
+            let req = proto::OpenAuctionRequest {
+                amount: command.amount("amount")?.units(),
+                blocks: command.number("blocks", DEFAULT_AUCTION_BLOCKS)?,
+                sealed,
+                terms: Some(terms.to_proto()),
+                auction_type: auction_type as i32,
+                dutch,
+                item: command.item(),
+            };
+            let res = auction_client(command).await?.open_auction(req).await.map_err(|s| s.message().to_string())?;
+            Ok(marco_json(res.get_ref()))
+        }
+        ["auction", "bid"] => {
+            let req = proto::PlaceBidRequest {
+                auction_id: command.flag("auction")?.clone(),
+                amount: command.amount("amount")?.units(),
+            };
+            let res = auction_client(command).await?.place_bid(req).await.map_err(|s| s.message().to_string())?;
+            Ok(marco_json(res.get_ref()))
+        }
+        ["auction", "close"] => {
+            let req = proto::CloseAuctionRequest { auction_id: command.flag("auction")?.clone() };
+            let res = auction_client(command).await?.close_auction(req).await.map_err(|s| s.message().to_string())?;
+            Ok(marco_json(res.get_ref()))
+        }
+        ["auction", "list"] => {
+            let req = proto::ListAuctionsRequest { open_only: command.flags.contains_key("open") };
+            let res = auction_client(command).await?.list_auctions(req).await.map_err(|s| s.message().to_string())?;
+            Ok(Value::Array(res.get_ref().auctions.iter().map(auction_json).collect()))
+        }
+        ["auction", "show"] => {
+            let req = proto::GetAuctionRequest { auction_id: command.flag("auction")?.clone() };
+            let res = auction_client(command).await?.get_auction(req).await.map_err(|s| s.message().to_string())?;
+            Ok(auction_json(res.get_ref()))
+        }
+        ["auction", "item"] => {
+            let req = proto::GetAuctionRequest { auction_id: command.flag("auction")?.clone() };
+            let res = auction_client(command).await?.get_item(req).await.map_err(|s| s.message().to_string())?;
+            let item = res.get_ref();
+            Ok(json!({
+                "title": item.title,
+                "description": item.description,
+                "image": item.image,
+                "terms": item.terms,
+            }))
+        }
+        ["auction", "watch"] => {
+            // One JSON line per change, until the auction has a winner
+            let req = proto::GetAuctionRequest { auction_id: command.flag("auction")?.clone() };
+            let mut stream = auction_client(command).await?.watch_auction(req).await.map_err(|s| s.message().to_string())?.into_inner();
+            while let Some(info) = stream.message().await.map_err(|s| s.message().to_string())? {
+                println!("{}", auction_json(&info));
+            }
+            Ok(Value::Null)
+        }
+        ["auction", "events"] => {
+            // One JSON line per event, until the node goes away
+            let req = proto::SubscribeEventsRequest {
+                auction_ids: command.list("auction"),
+                min_depth: command.number("depth", 0)?,
+            };
+            let mut stream = auction_client(command).await?.subscribe_events(req).await.map_err(|s| s.message().to_string())?.into_inner();
+            while let Some(e) = stream.message().await.map_err(|s| s.message().to_string())? {
+                println!("{}", event_json(&e));
+            }
+            Ok(Value::Null)
+        }
+        ["report", kind] => {
+            let kind = proto::ReportKind::from_str_name(&kind.to_uppercase()).ok_or(USAGE.to_string())?;
+            let format = match command.flags.get("format").map(|f| f.as_str()) {
+                None | Some("json") => proto::ReportFormat::Json,
+                Some("csv") => proto::ReportFormat::Csv,
+                Some(other) => return Err(format!("--format: unknown format {}", other)),
+            };
+            let req = proto::ReportRequest {
+                kind: kind as i32,
+                format: format as i32,
+                seller: command.flags.get("seller").cloned().unwrap_or_default(),
+                from: command.number("from", 0)?,
+                to: command.number("to", 0)?,
+                auction_ids: command.list("auction"),
+            };
+            let res = auction_client(command).await?.get_report(req).await.map_err(|s| s.message().to_string())?;
+            let content = res.into_inner().content;
+            match format {
+                // Printed as it is by [run]
+                proto::ReportFormat::Csv => Ok(Value::String(content)),
+                proto::ReportFormat::Json => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            }
+        }
+        ["chain", "show"] => {
+            let channel = connect(&command.node()).await.map_err(|e| e.to_string())?;
+            let res = PacketSendingClient::new(channel).get_chain(proto::GetChainRequest {}).await.map_err(|s| s.message().to_string())?;
+            Ok(Value::Array(res.get_ref().blocks.iter().map(block_json).collect()))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+async fn auction_client(command: &Command) -> Result<AuctionServiceClient<Channel>, String> {
+    let channel = connect(&command.auction_node()?).await.map_err(|e| e.to_string())?;
+    Ok(AuctionServiceClient::new(channel))
+}
+
+/// opens a TLS channel to the node, with the same certificates the peers use
+async fn connect(node: &str) -> Result<Channel, io::Error> {
+    if std::env!("TLS").to_string() != "1" {
+        // Un-Encrypted communication is no longer supported
+        return Err(io::Error::new(ErrorKind::ConnectionAborted, "Un-encrypted communication no longer supported, aborting ....".to_string()));
+    }
+    let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
+    let mut slash = "\\";
+    if env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string()) == "linux" {
+        slash = "/";
+    }
+    let pem = std::fs::read_to_string(data_dir.join(format!("cert{slash}ca.crt")))?;
+    let client_cert = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt")))?;
+    let client_key = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.key")))?;
+
+    let tls = ClientTlsConfig::new()
+        .domain_name("example.com")
+        .ca_certificate(Certificate::from_pem(pem))
+        .identity(Identity::from_pem(client_cert, client_key));
+
+    Channel::from_shared(format!("https://{}", node))
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e.to_string()))?
+        .tls_config(tls)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e.to_string()))?
+        .connect()
+        .await
+        .map_err(|e| io::Error::new(ErrorKind::ConnectionRefused, format!("Could not reach {}: {}", node, e)))
+}
+
+fn amount_json(units: u64) -> Value {
+    json!(Amount::from_units(units).to_string())
+}
+
+fn marco_json(reply: &proto::MarcoReply) -> Value {
+    match &reply.marco {
+        None => Value::Null,
+        Some(m) => json!({
+            "hash": m.hash,
+            "kind": m.data.as_ref().map(|d| d.kind.clone()).unwrap_or_default(),
+            "nonce": m.nonce,
+        }),
+    }
+}
+
+fn auction_json(info: &proto::AuctionInfo) -> Value {
+    let auction = info.auction.clone().unwrap_or_default();
+    let end = match auction.end {
+        Some(End::EndHeight(h)) => json!({ "height": h }),
+        Some(End::EndTimestamp(t)) => json!({ "timestamp": t }),
+        None => Value::Null,
+    };
+    let sealed = auction.sealed.map(|terms| json!({
+        "reveal_end": match terms.reveal_end {
+            Some(RevealEnd::RevealEndHeight(h)) => json!({ "height": h }),
+            Some(RevealEnd::RevealEndTimestamp(t)) => json!({ "timestamp": t }),
+            None => Value::Null,
+        },
+        "deposit": amount_json(terms.deposit),
+    }));
+    let prices = auction.terms.unwrap_or_default();
+    let price = |units: u64| if units == 0 { Value::Null } else { amount_json(units) };
+    let auction_type = match proto::AuctionType::try_from(auction.auction_type) {
+        Ok(proto::AuctionType::Dutch) => json!({
+            "dutch": auction.dutch.map(|d| json!({
+                "start_price": amount_json(d.start_price),
+                "decrement": amount_json(d.decrement),
+                "floor": amount_json(d.floor),
+            })),
+        }),
+        Ok(t) => json!(t.as_str_name().to_lowercase()),
+        Err(_) => Value::Null,
+    };
+    json!({
+        "id": info.id,
+        "seller": auction.seller_id,
+        "amount": amount_json(auction.amount),
+        "end": end,
+        "type": auction_type,
+        "sealed": sealed,
+        "reserve": price(prices.reserve),
+        "increment": price(prices.increment),
+        "buy_now": price(prices.buy_now),
+        "item": if auction.item.is_empty() { Value::Null } else { json!(auction.item) },
+        "open": info.open,
+        "minimum_bid": amount_json(info.minimum_bid),
+        "bids": info.bids.iter().map(|b| json!({ "buyer": b.buyer_id, "amount": amount_json(b.amount) })).collect::<Vec<Value>>(),
+        "winner": info.winner.as_ref().map(|w| json!({ "buyer": w.to, "amount": amount_json(w.amount) })),
+    })
+}
+
+fn event_json(e: &proto::AuctionEvent) -> Value {
+    json!({
+        "auction": e.auction_id,
+        "kind": proto::AuctionEventKind::try_from(e.kind).map(|k| k.as_str_name().to_lowercase()).unwrap_or_default(),
+        "buyer": e.buyer_id,
+        "amount": amount_json(e.amount),
+        "marco": e.marco,
+        "depth": e.depth,
+    })
+}
+
+fn block_json(block: &proto::Block) -> Value {
+    json!({
+        "index": block.index,
+        "hash": block.hash,
+        "prev_hash": block.prev_hash,
+        "timestamp": block.timestamp,
+        "miner": block.miner_id,
+        "marcos": block.transactions.iter()
+            .map(|m| json!({ "hash": m.hash, "kind": m.data.as_ref().map(|d| d.kind.clone()).unwrap_or_default() }))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auction::cli::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let command = Command::parse(&args("auction bid --auction abc --amount 1.5 --open --node 127.0.0.1:9000"));
+        assert_eq!(command.words, vec!["auction".to_string(), "bid".to_string()]);
+        assert_eq!(command.flag("auction"), Ok(&"abc".to_string()));
+        assert_eq!(command.amount("amount"), Ok(Amount::from_str("1.5").unwrap()));
+        assert_eq!(command.flags.get("open"), Some(&"true".to_string()));
+        assert_eq!(command.node(), "127.0.0.1:9000");
+        assert_eq!(command.auction_node(), Ok(format!("127.0.0.1:{}", Peer::auction_port(9000))));
+        assert_eq!(command.number("blocks", 10), Ok(10));
+        assert!(command.flag("deposit").is_err());
+        assert!(Command::parse(&args("auction bid --amount ten")).amount("amount").is_err());
+    }
+
+    #[test]
+    fn test_subcommand_dispatch() {
+        for line in ["auction list", "chain show", "report bids --format csv"] {
+            assert!(is_subcommand(&args(line)));
+        }
+        assert!(!is_subcommand(&args("NODE --port 9000")));
+        assert!(!is_subcommand(&[]));
+
+        // Invalid commands fail before connecting to a node
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(runtime.block_on(run(&args("report everything"))), 1);
+        assert_eq!(runtime.block_on(run(&args("auction"))), 1);
+    }
+
+    #[test]
+    fn test_render() {
+        // Streaming commands already printed their lines
+        assert_eq!(render(&Value::Null), "");
+        assert_eq!(render(&Value::String("id,amount\n".to_string())), "id,amount\n");
+        assert_eq!(render(&json!({"height": 1})), "{\n  \"height\": 1\n}\n");
+    }
+}
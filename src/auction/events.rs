@@ -0,0 +1,297 @@
+#[doc(inline)]
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::auction::wallet::Wallet;
+use crate::ledger::auctions::{AuctionState, Auctions};
+use crate::ledger::block::Block;
+use crate::marco::amount::Amount;
+use crate::marco::auction::EndCondition;
+use crate::marco::marco::{Data, Marco};
+
+/// Confirmations after which an event is final and no longer sent again
+pub const FINAL_DEPTH: u64 = 6;
+
+/// How often [EventTracker::watch] checks the ledger for new events
+pub const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events waiting in the in-process channel before slow subscribers start losing them
+pub const EVENT_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// a bid (or a revealed sealed bid) on the auction
+    NewBid,
+    /// a bid beat the highest bid of the wallet
+    Outbid,
+    /// the auction stopped taking bids (and reveals)
+    Closed,
+    /// the Winner of the auction was published
+    WinnerDeclared,
+}
+
+/// ## AuctionEvent
+/// Something that happened to an auction, with its confirmation depth:
+/// 0 while the Marco is pending, 1 once it is in the last block of the main branch, and so on.
+///
+/// An event is sent again every time its depth grows, until [FINAL_DEPTH]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionEvent {
+    pub auction_id: String,
+    pub kind: EventKind,
+    /// buyer of the bid or winner, empty for [EventKind::Closed]
+    pub buyer_id: String,
+    pub amount: Amount,
+    /// hash of the Marco behind the event, empty for [EventKind::Closed]
+    pub marco: String,
+    pub depth: u64,
+    /// true if the wallet sells the auction or bid on it
+    pub mine: bool,
+}
+
+impl AuctionEvent {
+    /// identifies the event regardless of its depth
+    pub fn key(&self) -> String {
+        format!("{:?}:{}:{}", self.kind, self.auction_id, self.marco)
+    }
+
+    /// [AuctionEvent::key] of the [EventKind::Closed] event of `auction_id`
+    fn closed_key(auction_id: &String) -> String {
+        format!("{:?}:{}:", EventKind::Closed, auction_id)
+    }
+}
+
+/// ## EventTracker
+/// Derives the [AuctionEvent]s of the main branch for the wallet `owner`
+/// and remembers the depth each one was last sent with.
+///
+/// Once a block is [FINAL_DEPTH] deep its events were sent for the last time:
+/// the block is not read again and its events are forgotten, as are the auctions whose end is final
+#[derive(Debug, Clone, Default)]
+pub struct EventTracker {
+    owner: String,
+    /// depth each event not final yet was last sent with
+    sent: HashMap<String, u64>,
+    /// index of the first block that is not final
+    first_block: u64,
+    /// highest bid of the owner on each auction, in the final blocks
+    ours: HashMap<String, Amount>,
+    /// auctions whose [EventKind::Closed] event was sent for the last time
+    closed: HashSet<String>,
+}
+
+impl EventTracker {
+    pub fn new(owner: String) -> Self {
+        EventTracker { owner, sent: HashMap::new(), first_block: 0, ours: HashMap::new(), closed: HashSet::new() }
+    }
+
+    /// sends the events of the ledger of the wallet to [Wallet::events], every [EVENT_INTERVAL].
+    /// The ledger is only read again when it changed, and only from the first block that is not final
+    pub async fn watch(wallet: Wallet) {
+        let mut tracker = EventTracker::new(wallet.id.clone());
+        let mut interval = tokio::time::interval(EVENT_INTERVAL);
+        let (mut changes, mut blocks, mut pending, mut auctions) = (None, Vec::new(), Vec::new(), Auctions::new());
+        loop {
+            interval.tick().await;
+            let (height, timestamp) = {
+                let bc = wallet.peer.blockchain.lock().unwrap();
+                if changes != Some(bc.changes) {
+                    changes = Some(bc.changes);
+                    let first = bc.chain.partition_point(|b| (b.index as u64) < tracker.first_block);
+                    blocks = bc.chain[first..].to_vec();
+                    blocks.extend(bc.heads.get_main());
+                    pending = bc.pending_marcos();
+                    auctions = bc.auctions();
+                }
+                bc.next_position()
+            };
+            for e in tracker.update(&blocks, &pending, &auctions, height, timestamp) {
+                // Nobody subscribed is not an error
+                let _ = wallet.events.send(e);
+            }
+        }
+    }
+
+    /// events that are new or deeper than when they were last sent
+    ///
+    /// `blocks` is the main branch (oldest first), `pending` the Marcos not in it yet
+    /// and (`height`, `timestamp`) the position of the next block.
+    /// The blocks that are already final are skipped, so `blocks` may start at any of them
+    pub fn update(&mut self, blocks: &[Block], pending: &[Marco], auctions: &Auctions, height: u64, timestamp: u64) -> Vec<AuctionEvent> {
+        let blocks = &blocks[blocks.partition_point(|b| (b.index as u64) < self.first_block)..];
+        let mut events = Vec::new();
+        for e in self.derive(blocks, pending, auctions, height, timestamp) {
+            let depth = e.depth.min(FINAL_DEPTH);
+            if self.sent.get(&e.key()).is_some_and(|sent| *sent >= depth) {
+                continue;
+            }
+            self.sent.insert(e.key(), depth);
+            events.push(AuctionEvent { depth, ..e });
+        }
+        self.forget_final(blocks, auctions, height);
+        events
+    }
+
+    /// forgets the events that were sent for the last time: the ones of the blocks [FINAL_DEPTH] deep
+    /// and the end of the auctions that ended [FINAL_DEPTH] blocks ago, or not at a block height
+    fn forget_final(&mut self, blocks: &[Block], auctions: &Auctions, height: u64) {
+        let first_block = height.saturating_sub(FINAL_DEPTH - 1);
+        for b in blocks.iter().filter(|b| (b.index as u64) < first_block) {
+            for m in &b.transactions {
+                match &m.data {
+                    Data::Bid(bid) if bid.buyer_id == self.owner => self.record_ours(&bid.auction_id, bid.amount),
+                    Data::BidReveal(r) if r.buyer_id == self.owner => self.record_ours(&r.auction_id, r.amount),
+                    _ => {}
+                }
+            }
+        }
+        self.first_block = self.first_block.max(first_block);
+
+        let tip = height.saturating_sub(1);
+        for a in auctions.iter() {
+            let key = AuctionEvent::closed_key(&a.id);
+            if !self.closed.contains(&a.id) && self.sent.get(&key).is_some_and(|depth| *depth >= FINAL_DEPTH || Self::closed_depth(a, tip).is_none()) {
+                self.sent.remove(&key);
+                self.ours.remove(&a.id);
+                self.closed.insert(a.id.clone());
+            }
+        }
+        self.sent.retain(|_, depth| *depth < FINAL_DEPTH);
+    }
+
+    fn record_ours(&mut self, auction_id: &str, amount: Amount) {
+        let highest = self.ours.entry(auction_id.to_string()).or_default();
+        *highest = amount.max(*highest);
+    }
+
+    /// blocks mined since the auction stopped taking bids, None if its end does not depend on the height
+    fn closed_depth(a: &AuctionState, tip: u64) -> Option<u64> {
+        let last = a.auction.sealed.map_or(a.auction.end, |terms| terms.reveal_end);
+        match last {
+            EndCondition::BlockHeight(h) if !a.bought() => Some(tip.saturating_sub(h)),
+            _ => None,
+        }
+    }
+
+    /// every event of the chain with its current depth
+    fn derive(&self, blocks: &[Block], pending: &[Marco], auctions: &Auctions, height: u64, timestamp: u64) -> Vec<AuctionEvent> {
+        let tip = height.saturating_sub(1);
+        let mut pending: Vec<&Marco> = pending.iter().collect();
+        pending.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.hash.cmp(&b.hash)));
+        let marcos = blocks.iter()
+            .flat_map(|b| b.transactions.iter().map(move |m| ((tip + 1).saturating_sub(b.index as u64), m)))
+            .chain(pending.into_iter().map(|m| (0, m)));
+
+        let mine = |auction_id: &String| auctions.get(auction_id).is_some_and(|a| {
+            a.auction.seller_id == self.owner
+                || a.bids.iter().any(|b| b.buyer_id == self.owner)
+                || a.commits.iter().any(|c| c.buyer_id == self.owner)
+        });
+        let event = |auction_id: &String, kind, buyer_id: &String, amount, marco: &Marco, depth| AuctionEvent {
+            auction_id: auction_id.clone(),
+            kind,
+            buyer_id: buyer_id.clone(),
+            amount,
+            marco: marco.get_hash(),
+            depth,
+            mine: mine(auction_id),
+        };
+
+        let mut events = Vec::new();
+        // Highest bid of the owner on each auction, in chain order
+        let mut ours = self.ours.clone();
+        for (depth, m) in marcos {
+            let (auction_id, buyer_id, amount) = match &m.data {
+                Data::Bid(b) => (&b.auction_id, &b.buyer_id, b.amount),
+                Data::BidReveal(r) => (&r.auction_id, &r.buyer_id, r.amount),
+                Data::Winner(w) => {
+                    events.push(event(&w.auction, EventKind::WinnerDeclared, &w.to, w.amount, m, depth));
+                    continue;
+                }
+                _ => continue,
+            };
+            events.push(event(auction_id, EventKind::NewBid, buyer_id, amount, m, depth));
+            if *buyer_id == self.owner {
+                let highest = ours.entry(auction_id.clone()).or_default();
+                *highest = amount.max(*highest);
+            } else if ours.get(auction_id).is_some_and(|highest| amount > *highest) {
+                events.push(event(auction_id, EventKind::Outbid, buyer_id, amount, m, depth));
+            }
+        }
+
+        let mut closed: Vec<_> = auctions.iter()
+            .filter(|a| !self.closed.contains(&a.id) && a.bidding_over(height, timestamp))
+            .collect();
+        closed.sort_by(|a, b| a.id.cmp(&b.id));
+        for a in closed {
+            // Ends by timestamp (or buy-now) stay at 0
+            let depth = Self::closed_depth(a, tip).unwrap_or(0);
+            events.push(AuctionEvent {
+                auction_id: a.id.clone(),
+                kind: EventKind::Closed,
+                buyer_id: "".to_string(),
+                amount: Amount::ZERO,
+                marco: "".to_string(),
+                depth,
+                mine: mine(&a.id),
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auction::events::*;
+    use crate::marco::auction::Auction;
+    use crate::marco::bid::Bid;
+    use crate::marco::winner::Winner;
+
+    fn block(index: usize, transactions: Vec<Marco>) -> Block {
+        let mut b = Block::new(index, "".to_string(), 0, "miner".to_string(), Amount::ZERO);
+        b.transactions = transactions;
+        b
+    }
+
+    fn bid(auction: &Marco, buyer: &str, coins: u64) -> Marco {
+        Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(), "seller".to_string(), Amount::from_coins(coins).unwrap()), coins)
+    }
+
+    #[test]
+    fn test_events_and_depth() {
+        let a = Marco::from_auction(Auction::new("seller".to_string(), Amount::from_units(10), EndCondition::BlockHeight(2)), 0);
+        let (ours, theirs) = (bid(&a, "alice", 1), bid(&a, "bob", 2));
+        let mut blocks = vec![block(1, vec![a.clone(), ours.clone()])];
+        let mut auctions = Auctions::from_blocks(&blocks);
+        assert!(auctions.apply(&theirs, 2, 0));
+
+        let mut tracker = EventTracker::new("alice".to_string());
+        let events = tracker.update(&blocks, &[theirs.clone()], &auctions, 2, 0);
+        let kinds: Vec<_> = events.iter().map(|e| (e.kind, e.depth, e.mine)).collect();
+        assert_eq!(kinds, vec![(EventKind::NewBid, 1, true), (EventKind::NewBid, 0, true), (EventKind::Outbid, 0, true)]);
+        // Nothing changed, nothing is sent
+        assert!(tracker.update(&blocks, &[theirs.clone()], &auctions, 2, 0).is_empty());
+
+        // Once in a block the bids get deeper, and the auction closes after block 2
+        blocks.push(block(2, vec![theirs.clone()]));
+        let events = tracker.update(&blocks, &[], &auctions, 3, 0);
+        let kinds: Vec<_> = events.iter().map(|e| (e.kind, e.depth)).collect();
+        assert_eq!(kinds, vec![(EventKind::NewBid, 2), (EventKind::NewBid, 1), (EventKind::Outbid, 1), (EventKind::Closed, 0)]);
+
+        let w = Marco::from_winner(Winner::new(a.get_hash(), Amount::from_coins(2).unwrap(), "seller".to_string(), "bob".to_string()), 0);
+        let events = tracker.update(&blocks, &[w], &auctions, 3, 0);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].kind, events[0].buyer_id.as_str()), (EventKind::WinnerDeclared, "bob"));
+
+        // Blocks FINAL_DEPTH deep are sent one last time and forgotten, and so is the end of the auction
+        let height = 3 + FINAL_DEPTH;
+        let events = tracker.update(&blocks, &[], &auctions, height, 0);
+        assert!(events.iter().all(|e| e.depth == FINAL_DEPTH));
+        assert_eq!(events.len(), 4);
+        assert!(tracker.sent.keys().all(|key| key.starts_with("WinnerDeclared")));
+        assert!(tracker.closed.contains(&a.get_hash()));
+        // Only the blocks that are not final are read
+        assert!(tracker.update(&blocks[2..], &[], &auctions, height + 1, 0).is_empty());
+        assert!(tracker.update(&blocks, &[], &auctions, height + 1, 0).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+#[doc(inline)]
+use serde_json::{json, Value};
+use sha2::{Digest, Sha512};
+
+use crate::auxi;
+use crate::kademlia::node::Identifier;
+use crate::proto::ItemDescriptor as ProtoItemDescriptor;
+
+/// ## ItemDescriptor
+/// What a seller offers in an auction, besides the coins.
+///
+/// The descriptor is stored in the DHT as JSON, keyed by its content hash,
+/// and only the hash is committed in the [Auction](crate::marco::auction::Auction) Marco.
+/// Anyone fetching it checks the value against that hash
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemDescriptor {
+    pub title: String,
+    pub description: String,
+    /// link to a picture of the item
+    pub image: String,
+    /// delivery, returns or any other conditions of the sale
+    pub terms: String,
+}
+
+impl ItemDescriptor {
+    pub fn new(title: String, description: String, image: String, terms: String) -> Self {
+        ItemDescriptor { title, description, image, terms }
+    }
+
+    /// the value stored in the DHT, the keys are always in the same order
+    /// so the same descriptor always has the same hash
+    pub fn to_json(&self) -> String {
+        json!({
+            "title": self.title,
+            "description": self.description,
+            "image": self.image,
+            "terms": self.terms,
+        }).to_string()
+    }
+
+    /// **outputs:**
+    /// the descriptor, or None if the value is not a descriptor with a title
+    pub fn from_json(value: &str) -> Option<ItemDescriptor> {
+        let v: Value = serde_json::from_str(value).ok()?;
+        let field = |name: &str| v.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+        let item = ItemDescriptor::new(field("title"), field("description"), field("image"), field("terms"));
+        if item.title.is_empty() {
+            return None;
+        }
+        Some(item)
+    }
+
+    /// hash committed in the Auction Marco
+    pub fn hash(&self) -> String {
+        content_hash(&self.to_json())
+    }
+
+    pub fn to_proto(&self) -> ProtoItemDescriptor {
+        ProtoItemDescriptor {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            image: self.image.clone(),
+            terms: self.terms.clone(),
+        }
+    }
+
+    pub fn from_proto(item: &ProtoItemDescriptor) -> ItemDescriptor {
+        ItemDescriptor::new(item.title.clone(), item.description.clone(), item.image.clone(), item.terms.clone())
+    }
+}
+
+/// Sha512 (hex) of a value stored in the DHT
+pub fn content_hash(value: &str) -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(value.as_bytes());
+    hasher.finalize().iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+/// DHT key of the descriptor with this content hash
+pub fn item_key(hash: &str) -> Identifier {
+    auxi::gen_id(hash.to_string())
+}
+
+/// checks a value fetched from the DHT against the hash committed in the auction
+///
+/// **outputs:**
+/// the descriptor, or None if the value was tampered with or is not a descriptor
+pub fn verify(hash: &str, value: &str) -> Option<ItemDescriptor> {
+    if content_hash(value) != hash {
+        return None;
+    }
+    ItemDescriptor::from_json(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auction::item::*;
+
+    #[test]
+    fn test_item_verify() {
+        let item = ItemDescriptor::new("Bike".to_string(), "Red, barely used".to_string(), "".to_string(), "Pick up only".to_string());
+        let value = item.to_json();
+        assert_eq!(item.hash(), content_hash(&value));
+        assert_eq!(verify(&item.hash(), &value), Some(item.clone()));
+        assert_eq!(item_key(&item.hash()), item_key(&item.clone().hash()));
+
+        // A value that does not match the committed hash is rejected
+        let forged = ItemDescriptor { description: "Brand new".to_string(), ..item.clone() };
+        assert_eq!(verify(&item.hash(), &forged.to_json()), None);
+        // So is a value without a title, even with the right hash
+        let empty = ItemDescriptor::default().to_json();
+        assert_eq!(verify(&content_hash(&empty), &empty), None);
+    }
+}
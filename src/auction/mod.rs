@@ -1 +1,8 @@
-pub mod auction;
\ No newline at end of file
+pub mod auction;
+pub mod auction_book;
+pub mod events;
+pub mod item;
+pub mod report;
+pub mod wallet;
+pub mod cli;
+pub(crate) mod auction_rpc_server;
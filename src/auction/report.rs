@@ -0,0 +1,396 @@
+#[doc(inline)]
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_json::{json, Map, Value};
+
+use crate::ledger::block::Block;
+use crate::marco::amount::Amount;
+use crate::marco::auction::AuctionType;
+use crate::marco::marco::Data;
+
+/// What a [Report] is about, one row per:
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportKind {
+    /// auction, with its final price and winner
+    Auctions,
+    /// bid, in chain order (the timeline of each auction)
+    Bids,
+    /// seller, with how many of their auctions were closed and sold
+    Sellers,
+    /// bidder, with how many of the auctions they bid on they won
+    Bidders,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+/// ## ReportFilter
+/// Which auctions a report covers, every condition that is set must hold.
+///
+/// The time range (seconds since the epoch, `to` excluded) is matched against
+/// the timestamp of the block that opened the auction
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportFilter {
+    pub seller: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// empty for every auction
+    pub auctions: HashSet<String>,
+}
+
+impl ReportFilter {
+    fn matches(&self, a: &AuctionRecord) -> bool {
+        self.seller.as_ref().is_none_or(|s| *s == a.seller_id)
+            && self.from.is_none_or(|from| a.timestamp >= from)
+            && self.to.is_none_or(|to| a.timestamp < to)
+            && (self.auctions.is_empty() || self.auctions.contains(&a.id))
+    }
+}
+
+/// a bid (or revealed sealed bid) of the confirmed chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidRecord {
+    pub buyer_id: String,
+    pub amount: Amount,
+    pub height: u64,
+    pub timestamp: u64,
+    pub marco: String,
+}
+
+/// the Winner of an auction, the buyer is empty when nobody won
+#[derive(Debug, Clone, PartialEq)]
+pub struct WinnerRecord {
+    pub buyer_id: String,
+    pub price: Amount,
+    /// empty when the seller closed the auction
+    pub closed_by: String,
+    pub height: u64,
+    pub timestamp: u64,
+}
+
+/// ## AuctionRecord
+/// An auction of the confirmed chain with its bids and Winner,
+/// `height` and `timestamp` are the ones of the block that opened it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionRecord {
+    pub id: String,
+    pub seller_id: String,
+    pub amount: Amount,
+    pub auction_type: AuctionType,
+    pub sealed: bool,
+    pub height: u64,
+    pub timestamp: u64,
+    pub bids: Vec<BidRecord>,
+    pub winner: Option<WinnerRecord>,
+}
+
+impl AuctionRecord {
+    fn highest_bid(&self) -> Option<&BidRecord> {
+        self.bids.iter().max_by_key(|b| b.amount)
+    }
+
+    /// true if the winner was this buyer
+    fn won_by(&self, buyer_id: &String) -> bool {
+        self.winner.as_ref().is_some_and(|w| w.buyer_id == *buyer_id)
+    }
+}
+
+/// ## Report
+/// Table with named columns, rendered as CSV or as a JSON array with an object per row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Report {
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Csv => self.to_csv(),
+            ReportFormat::Json => self.to_json().to_string(),
+        }
+    }
+
+    /// header line followed by a line per row, empty values for null
+    pub fn to_csv(&self) -> String {
+        let mut csv = self.columns.join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().map(|v| match v {
+                Value::Null => "".to_string(),
+                Value::String(s) => csv_escape(s),
+                other => csv_escape(&other.to_string()),
+            }).collect();
+            csv.push_str(&cells.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.rows.iter().map(|row| {
+            let object: Map<String, Value> = self.columns.iter()
+                .map(|c| c.to_string())
+                .zip(row.iter().cloned())
+                .collect();
+            Value::Object(object)
+        }).collect())
+    }
+}
+
+/// quotes the cell if it holds a separator, a quote or a line break
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", cell.replace('"', "\"\""));
+    }
+    cell.to_string()
+}
+
+/// ratio rounded to 4 decimals, 0 when there is nothing to divide
+fn rate(part: usize, total: usize) -> Value {
+    if total == 0 {
+        return json!(0.0);
+    }
+    json!((part as f64 / total as f64 * 10000.0).round() / 10000.0)
+}
+
+fn amount(a: Amount) -> Value {
+    json!(a.to_string())
+}
+
+fn type_name(auction_type: &AuctionType) -> &'static str {
+    match auction_type {
+        AuctionType::English => "english",
+        AuctionType::Dutch(_) => "dutch",
+        AuctionType::Vickrey => "vickrey",
+    }
+}
+
+/// ## History
+/// The Auction, Bid, BidReveal and Winner Marcos of the confirmed chain, grouped by auction
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// in the order they were opened
+    auctions: Vec<AuctionRecord>,
+}
+
+impl History {
+    /// walks the blocks in chain order,
+    /// bids and Winners of auctions that are not in the blocks are left out
+    pub fn from_blocks(blocks: &[Block]) -> History {
+        let mut auctions: Vec<AuctionRecord> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for b in blocks {
+            let (height, timestamp) = (b.index as u64, b.timestamp);
+            for m in &b.transactions {
+                match &m.data {
+                    Data::CreateAuction(a) => {
+                        index.insert(m.get_hash(), auctions.len());
+                        auctions.push(AuctionRecord {
+                            id: m.get_hash(),
+                            seller_id: a.seller_id.clone(),
+                            amount: a.amount,
+                            auction_type: a.get_type(),
+                            sealed: a.get_sealed().is_some(),
+                            height,
+                            timestamp,
+                            bids: Vec::new(),
+                            winner: None,
+                        });
+                    }
+                    Data::Bid(bid) => if let Some(i) = index.get(&bid.auction_id) {
+                        auctions[*i].bids.push(BidRecord { buyer_id: bid.buyer_id.clone(), amount: bid.amount, height, timestamp, marco: m.get_hash() });
+                    },
+                    Data::BidReveal(r) => if let Some(i) = index.get(&r.auction_id) {
+                        auctions[*i].bids.push(BidRecord { buyer_id: r.buyer_id.clone(), amount: r.amount, height, timestamp, marco: m.get_hash() });
+                    },
+                    Data::Winner(w) => if let Some(i) = index.get(&w.auction) {
+                        auctions[*i].winner = Some(WinnerRecord { buyer_id: w.to.clone(), price: w.amount, closed_by: w.closed_by.clone(), height, timestamp });
+                    },
+                    _ => {}
+                }
+            }
+        }
+        History { auctions }
+    }
+
+    /// auctions that pass the filter, in the order they were opened
+    pub fn auctions(&self, filter: &ReportFilter) -> Vec<&AuctionRecord> {
+        self.auctions.iter().filter(|a| filter.matches(a)).collect()
+    }
+
+    pub fn report(&self, kind: ReportKind, filter: &ReportFilter) -> Report {
+        let auctions = self.auctions(filter);
+        match kind {
+            ReportKind::Auctions => Self::auctions_report(&auctions),
+            ReportKind::Bids => Self::bids_report(&auctions),
+            ReportKind::Sellers => Self::sellers_report(&auctions),
+            ReportKind::Bidders => Self::bidders_report(&auctions),
+        }
+    }
+
+    fn auctions_report(auctions: &[&AuctionRecord]) -> Report {
+        let rows = auctions.iter().map(|a| {
+            let winner = a.winner.as_ref();
+            vec![
+                json!(a.id),
+                json!(a.seller_id),
+                amount(a.amount),
+                json!(type_name(&a.auction_type)),
+                json!(a.sealed),
+                json!(a.height),
+                json!(a.timestamp),
+                json!(a.bids.len()),
+                a.highest_bid().map_or(Value::Null, |b| amount(b.amount)),
+                winner.filter(|w| !w.buyer_id.is_empty()).map_or(Value::Null, |w| json!(w.buyer_id)),
+                winner.filter(|w| !w.buyer_id.is_empty()).map_or(Value::Null, |w| amount(w.price)),
+                winner.map_or(Value::Null, |w| json!(w.height)),
+                winner.map_or(Value::Null, |w| json!(if w.closed_by.is_empty() { &a.seller_id } else { &w.closed_by })),
+            ]
+        }).collect();
+        Report {
+            columns: vec!["auction", "seller", "amount", "type", "sealed", "opened_height", "opened_at", "bids",
+                "highest_bid", "winner", "final_price", "closed_height", "closed_by"],
+            rows,
+        }
+    }
+
+    fn bids_report(auctions: &[&AuctionRecord]) -> Report {
+        let mut bids: Vec<(&AuctionRecord, &BidRecord)> = auctions.iter()
+            .flat_map(|a| a.bids.iter().map(move |b| (*a, b)))
+            .collect();
+        // Chain order, the sort is stable so bids of the same block keep their order
+        bids.sort_by_key(|(_, b)| b.height);
+        let rows = bids.into_iter().map(|(a, b)| vec![
+            json!(a.id),
+            json!(b.height),
+            json!(b.timestamp),
+            json!(b.buyer_id),
+            amount(b.amount),
+            json!(b.marco),
+        ]).collect();
+        Report { columns: vec!["auction", "height", "timestamp", "buyer", "amount", "marco"], rows }
+    }
+
+    /// how often each seller's auctions were closed, by the seller or after their end by another node, and sold
+    fn sellers_report(auctions: &[&AuctionRecord]) -> Report {
+        let mut sellers: BTreeMap<&String, Vec<&AuctionRecord>> = BTreeMap::new();
+        for a in auctions {
+            sellers.entry(&a.seller_id).or_default().push(a);
+        }
+        let rows = sellers.into_iter().map(|(seller, list)| {
+            let closed = list.iter().filter(|a| a.winner.is_some()).count();
+            let by_seller = list.iter().filter(|a| a.winner.as_ref().is_some_and(|w| w.closed_by.is_empty())).count();
+            let sold: Vec<&WinnerRecord> = list.iter()
+                .filter_map(|a| a.winner.as_ref())
+                .filter(|w| !w.buyer_id.is_empty())
+                .collect();
+            let revenue = sold.iter().fold(Amount::ZERO, |total, w| total.checked_add(w.price).unwrap_or(Amount::MAX));
+            vec![
+                json!(seller),
+                json!(list.len()),
+                json!(closed),
+                json!(by_seller),
+                json!(sold.len()),
+                rate(closed, list.len()),
+                amount(revenue),
+            ]
+        }).collect();
+        Report { columns: vec!["seller", "auctions", "closed", "closed_by_seller", "sold", "completion_rate", "revenue"], rows }
+    }
+
+    /// win rate of each bidder over the closed auctions they bid on
+    fn bidders_report(auctions: &[&AuctionRecord]) -> Report {
+        let mut bidders: BTreeMap<&String, Vec<&AuctionRecord>> = BTreeMap::new();
+        for a in auctions {
+            let buyers: HashSet<&String> = a.bids.iter().map(|b| &b.buyer_id).collect();
+            for buyer in buyers {
+                bidders.entry(buyer).or_default().push(a);
+            }
+        }
+        let rows = bidders.into_iter().map(|(buyer, list)| {
+            let bids: usize = list.iter().map(|a| a.bids.iter().filter(|b| b.buyer_id == *buyer).count()).sum();
+            let closed = list.iter().filter(|a| a.winner.is_some()).count();
+            let won: Vec<&&AuctionRecord> = list.iter().filter(|a| a.won_by(buyer)).collect();
+            let spent = won.iter()
+                .filter_map(|a| a.winner.as_ref())
+                .fold(Amount::ZERO, |total, w| total.checked_add(w.price).unwrap_or(Amount::MAX));
+            vec![
+                json!(buyer),
+                json!(list.len()),
+                json!(bids),
+                json!(closed),
+                json!(won.len()),
+                rate(won.len(), closed),
+                amount(spent),
+            ]
+        }).collect();
+        Report { columns: vec!["bidder", "auctions", "bids", "closed", "won", "win_rate", "spent"], rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auction::report::*;
+    use crate::marco::auction::{Auction, EndCondition};
+    use crate::marco::bid::Bid;
+    use crate::marco::marco::Marco;
+    use crate::marco::winner::Winner;
+
+    fn block(index: usize, timestamp: u64, transactions: Vec<Marco>) -> Block {
+        let mut b = Block::new(index, "".to_string(), 0, "miner".to_string(), Amount::ZERO);
+        b.timestamp = timestamp;
+        b.transactions = transactions;
+        b
+    }
+
+    fn coins(n: u64) -> Amount {
+        Amount::from_coins(n).unwrap()
+    }
+
+    #[test]
+    fn test_reports() {
+        let a = Marco::from_auction(Auction::new("alice".to_string(), coins(5), EndCondition::BlockHeight(3)), 0);
+        let b = Marco::from_auction(Auction::new("bob".to_string(), coins(1), EndCondition::BlockHeight(3)), 0);
+        let bid = |auction: &Marco, buyer: &str, seller: &str, n: u64| Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(), seller.to_string(), coins(n)), n);
+        let blocks = vec![
+            block(1, 100, vec![a.clone()]),
+            block(2, 200, vec![b.clone(), bid(&a, "carol", "alice", 1), bid(&a, "dave", "alice", 2)]),
+            block(3, 300, vec![bid(&a, "carol", "alice", 3), bid(&b, "dave", "bob", 1)]),
+            block(4, 400, vec![Marco::from_winner(Winner::new(a.get_hash(), coins(3), "alice".to_string(), "carol".to_string()), 0)]),
+        ];
+        let history = History::from_blocks(&blocks);
+        let all = ReportFilter::default();
+
+        let auctions = history.report(ReportKind::Auctions, &all);
+        assert_eq!(auctions.rows.len(), 2);
+        let first = &auctions.to_json()[0];
+        assert_eq!((first["winner"].as_str(), first["final_price"].as_str(), first["bids"].as_u64()), (Some("carol"), Some("3"), Some(3)));
+        assert!(auctions.to_json()[1]["winner"].is_null());
+
+        let sellers = history.report(ReportKind::Sellers, &all).to_csv();
+        assert_eq!(sellers.lines().collect::<Vec<_>>(), vec![
+            "seller,auctions,closed,closed_by_seller,sold,completion_rate,revenue",
+            "alice,1,1,1,1,1.0,3",
+            "bob,1,0,0,0,0.0,0",
+        ]);
+        let bidders = history.report(ReportKind::Bidders, &all).to_csv();
+        assert_eq!(bidders.lines().nth(1), Some("carol,1,2,1,1,1.0,3"));
+        assert_eq!(bidders.lines().nth(2), Some("dave,2,2,1,0,0.0,0"));
+
+        // Filters: by seller, by the time the auction was opened and by auction
+        let bob = ReportFilter { seller: Some("bob".to_string()), ..Default::default() };
+        assert_eq!(history.report(ReportKind::Bids, &bob).rows.len(), 1);
+        let late = ReportFilter { from: Some(150), to: Some(300), ..Default::default() };
+        assert_eq!(history.auctions(&late).iter().map(|a| a.id.clone()).collect::<Vec<_>>(), vec![b.get_hash()]);
+        let only_a = ReportFilter { auctions: [a.get_hash()].into_iter().collect(), ..Default::default() };
+        let timeline = history.report(ReportKind::Bids, &only_a);
+        let heights: Vec<_> = timeline.rows.iter().map(|r| r[1].as_u64().unwrap()).collect();
+        assert_eq!(heights, vec![2, 2, 3]);
+
+        assert_eq!(csv_escape("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
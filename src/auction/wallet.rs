@@ -0,0 +1,242 @@
+#[doc(inline)]
+use std::env;
+use std::str::FromStr;
+
+use rsa::{pkcs1v15::SigningKey, pkcs8::DecodePrivateKey};
+use tokio::sync::broadcast;
+
+use crate::auction::events::{AuctionEvent, EVENT_CHANNEL_SIZE};
+use crate::auction::item::{self, ItemDescriptor};
+use crate::auxi;
+use crate::marco::amount::Amount;
+use crate::marco::auction::{Auction, AuctionType, EndCondition, PriceTerms, SealedTerms};
+use crate::marco::bid::Bid;
+use crate::marco::marco::Marco;
+use crate::marco::sealed_bid::BidCommit;
+use crate::marco::signature::{PublicKey, SecretKey, SignatureAlgorithm};
+use crate::p2p::peer::Peer;
+
+/// ## Wallet
+/// Identity and keys a node uses to sign its auction Marcos.
+///
+/// Every action signs a Marco, adds it to the blockchain of the node and broadcasts it,
+/// nothing here reads from stdin so it is shared by the terminal UI and the [AuctionService](crate::proto::auction_service_server::AuctionService)
+#[derive(Debug, Clone)]
+pub struct Wallet {
+    pub peer: Peer,
+    pub id: String,
+    pub pkey: PublicKey,
+    pub skey: SecretKey,
+    /// in-process channel of the [AuctionEvent]s of the ledger,
+    /// filled by [EventTracker::watch](crate::auction::events::EventTracker::watch)
+    pub events: broadcast::Sender<AuctionEvent>,
+}
+
+impl Wallet {
+    /// creates the wallet of the node, the id is the [id of its public key](PublicKey::id),
+    /// which also receives the rewards of the blocks the node mines.
+    ///
+    /// The key is an Ed25519 key of the node, one per port so nodes sharing a folder have their own account.
+    /// With `SIGNATURE_ALG` set to "rsa" the keys of the certificate are used, which every node of the folder shares
+    pub fn load(peer: Peer) -> Wallet {
+        let mut slash = "/";
+        if env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string()) == "windows" {
+            slash = "\\";
+        }
+        let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
+        let algorithm = SignatureAlgorithm::from_str(&env::var("SIGNATURE_ALG").unwrap_or_else(|_| "ed25519".to_string()))
+            .expect("Invalid SIGNATURE_ALG");
+        let (pkey, skey) = match algorithm {
+            SignatureAlgorithm::Rsa => {
+                let pem = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt"))).expect("Failed to read server.crt");
+                let pkey = auxi::get_public_key(pem);
+                let skey = SigningKey::read_pkcs8_pem_file(data_dir.join(format!("cert{slash}server.key"))).expect("Failed to read server.key");
+                (PublicKey::Rsa(pkey), SecretKey::Rsa(skey))
+            }
+            SignatureAlgorithm::Ed25519 => {
+                let skey = Self::load_ed25519(&data_dir.join(format!("cert{slash}ed25519_{}.key", peer.node.port)));
+                (skey.public_key(), skey)
+            }
+        };
+
+        let id = pkey.id();
+        peer.blockchain.lock().unwrap().set_miner_id(id.clone());
+
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
+        Wallet { peer, id, pkey, skey, events }
+    }
+
+    /// reads the Ed25519 key of the node saved next to the certificate,
+    /// a new key is generated and saved on the first run so the wallet id survives restarts
+    fn load_ed25519(path: &std::path::Path) -> SecretKey {
+        if let Some(skey) = std::fs::read_to_string(path).ok().and_then(|s| SecretKey::decode(&s)) {
+            return skey;
+        }
+        let skey = SecretKey::generate_ed25519();
+        std::fs::write(path, skey.encode().expect("Ed25519 keys can be encoded")).expect("Failed to save the Ed25519 key");
+        skey
+    }
+
+    /// next unused nonce for the Marcos we sign
+    pub fn next_nonce(&self) -> u64 {
+        self.peer.blockchain.lock().unwrap().next_nonce(&self.id)
+    }
+
+    /// signs the Marco, adds it to the blockchain and broadcasts it,
+    /// or the block it completed
+    ///
+    /// **outputs:**
+    /// the signed Marco, or an error if the blockchain rejected it
+    pub fn submit(&self, mut m: Marco) -> Result<Marco, String> {
+        m.calc_hash();
+        m.sign(&self.skey);
+        let (res, ob) = self.peer.blockchain.lock().unwrap().add_marco(m.clone(), self.pkey.clone());
+        if !res {
+            return Err(format!("The {} was rejected by the blockchain", m.data.kind()));
+        }
+        let local_client = self.peer.clone();
+        match ob {
+            None => {
+                let m_clone = m.clone();
+                tokio::spawn(async move {
+                    local_client.send_marco(m_clone).await
+                });
+            },
+            Some(b) => {
+                tokio::spawn(async move {
+                    local_client.send_block(b).await
+                });
+            },
+        }
+        Ok(m)
+    }
+
+    /// opens an auction of `amount` coins that takes bids for the next `blocks` blocks
+    ///
+    /// `sealed` holds the number of blocks, after the bids, to reveal them and the deposit of each bid,
+    /// `terms` the optional reserve, increment and buy-now prices,
+    /// `auction_type` how the winner is decided and `item` the hash returned by [Wallet::publish_item]
+    pub fn open_auction(&self, amount: Amount, blocks: u64, sealed: Option<(u64, Amount)>, terms: PriceTerms, auction_type: AuctionType, item: Option<String>) -> Result<Marco, String> {
+        if blocks == 0 {
+            return Err("At least 1 block is needed".to_string());
+        }
+        let (height, _) = self.peer.blockchain.lock().unwrap().next_position();
+        let end = EndCondition::BlockHeight(height + blocks - 1);
+        let auction = match sealed {
+            None => Auction::new(self.id.clone(), amount, end),
+            Some((0, _)) => return Err("At least 1 block is needed to reveal the bids".to_string()),
+            Some((reveal_blocks, deposit)) => {
+                let terms = SealedTerms { reveal_end: EndCondition::BlockHeight(height + blocks + reveal_blocks - 1), deposit };
+                Auction::sealed(self.id.clone(), amount, end, terms)
+            }
+        };
+        if !terms.is_valid(sealed.is_some()) {
+            return Err("The buy-now price needs an open auction and must not be below the reserve, the increment cannot be 0".to_string());
+        }
+        if !auction_type.is_valid(sealed.is_some(), &terms) {
+            return Err(format!("The terms do not fit the auction type ({}): Dutch auctions are open and without buy-now, Vickrey auctions are sealed", auction_type));
+        }
+        let auction = auction.with_terms(terms).with_type(auction_type);
+        let auction = match item {
+            None => auction,
+            Some(hash) => auction.with_item(hash),
+        };
+        self.submit(Marco::from_auction(auction, self.next_nonce()))
+    }
+
+    /// stores the descriptor in the DHT, keyed by its content hash, and keeps a copy in our own node
+    ///
+    /// **outputs:**
+    /// the hash to commit in the auction, or an error if fewer than [Peer::write_quorum](crate::p2p::peer::Peer::write_quorum)
+    /// nodes stored it (our node still keeps and republishes it)
+    pub async fn publish_item(&self, item: &ItemDescriptor) -> Result<String, String> {
+        if item.title.is_empty() {
+            return Err("The item needs a title".to_string());
+        }
+        let (hash, value) = (item.hash(), item.to_json());
+        // Kept (and republished) by our node even if the quorum is not reached
+        match self.peer.store(item::item_key(&hash), value).await {
+            Ok(report) if report.reached_quorum() => Ok(hash),
+            Ok(report) => Err(format!("Only {} of {} nodes stored the item {}", report.acknowledged.len(), report.quorum, hash)),
+            Err(e) => Err(format!("The item {} was only stored by our node: {}", hash, e)),
+        }
+    }
+
+    /// fetches the item of the auction from the DHT and checks it against the hash in the auction
+    pub async fn fetch_item(&self, auction_id: &String) -> Result<ItemDescriptor, String> {
+        let auction = self.find_auction(auction_id)?;
+        let hash = auction.get_item().ok_or(format!("Auction {} has no item", auction_id))?.clone();
+        let local = self.peer.kademlia.lock().unwrap().get_value(item::item_key(&hash)).cloned();
+        if let Some(item) = local.and_then(|value| item::verify(&hash, &value)) {
+            return Ok(item);
+        }
+        let value = self.peer.find_value(item::item_key(&hash)).await
+            .map_err(|e| format!("The item {} was not found: {}", hash, e))?;
+        item::verify(&hash, &value).ok_or(format!("The item stored for {} does not match its hash", hash))
+    }
+
+    /// bids on an open (not sealed) auction,
+    /// a bid that ends the auction (buy-now, or any bid on a Dutch auction) also publishes the Winner
+    pub fn place_bid(&self, auction_id: &String, amount: Amount) -> Result<Marco, String> {
+        let auction = self.find_auction(auction_id)?;
+        if auction.sealed.is_some() {
+            return Err(format!("Auction {} only takes sealed bids", auction_id));
+        }
+        let m = self.submit(Marco::from_bid(Bid::new(auction_id.clone(), self.id.clone(), auction.seller_id, amount), self.next_nonce()))?;
+        let state = self.peer.blockchain.lock().unwrap().auctions().get(auction_id).cloned();
+        if let Some(state) = state.filter(|s| s.bought() && s.winner.is_none()) {
+            self.submit(Marco::from_winner(state.derive_winner(self.id.clone()), self.next_nonce()))?;
+        }
+        Ok(m)
+    }
+
+    /// commits a sealed bid, the salt is needed to reveal it later
+    pub fn commit_bid(&self, auction_id: &String, amount: Amount) -> Result<(Marco, String), String> {
+        let auction = self.find_auction(auction_id)?;
+        let terms = auction.sealed.ok_or(format!("Auction {} does not take sealed bids", auction_id))?;
+        let salt: String = (0..16).map(|_| format!("{:02x}", rand::random::<u8>())).collect();
+        let commit = BidCommit::new(auction_id.clone(), self.id.clone(), auction.seller_id, amount, &salt, terms.deposit);
+        let m = self.submit(Marco::from_bid_commit(commit, self.next_nonce()))?;
+        Ok((m, salt))
+    }
+
+    /// closes one of our auctions with the winner derived from the chain,
+    /// sealed auctions can only be closed once every bid had the chance to be revealed
+    pub fn close_auction(&self, auction_id: &String) -> Result<Marco, String> {
+        let (auctions, (height, timestamp)) = {
+            let bc = self.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        let state = auctions.get(auction_id).ok_or(format!("Auction {} was not found", auction_id))?;
+        if state.auction.seller_id != self.id {
+            return Err(format!("Auction {} is not ours", auction_id));
+        }
+        if state.winner.is_some() {
+            return Err(format!("Auction {} is already closed", auction_id));
+        }
+        if state.is_sealed() && !state.bidding_over(height, timestamp) {
+            return Err(format!("The bids of auction {} are not all revealed yet", auction_id));
+        }
+        self.submit(Marco::from_winner(state.derive_winner("".to_string()), self.next_nonce()))
+    }
+
+    /// publishes the Winner of every auction that ended without one,
+    /// so auctions close even when their seller is offline
+    pub fn publish_ended_auctions(&self) {
+        let (auctions, (height, timestamp)) = {
+            let bc = self.peer.blockchain.lock().unwrap();
+            (bc.auctions(), bc.next_position())
+        };
+        for state in auctions.awaiting_winner(height, timestamp) {
+            let closed_by = if state.auction.seller_id == self.id { "".to_string() } else { self.id.clone() };
+            let _ = self.submit(Marco::from_winner(state.derive_winner(closed_by), self.next_nonce()));
+        }
+    }
+
+    fn find_auction(&self, auction_id: &String) -> Result<Auction, String> {
+        let auctions = self.peer.blockchain.lock().unwrap().auctions();
+        auctions.get(auction_id)
+            .map(|state| state.auction.clone())
+            .ok_or(format!("Auction {} was not found", auction_id))
+    }
+}
@@ -5,23 +5,19 @@ use sha3::{Digest, Sha3_256};
 use tokio::net::TcpListener;
 use x509_certificate::X509Certificate;
 
-use proto::{Auction as ProtoAuction, Bid as ProtoBid, Data as ProtoData, Marco as ProtoMarco, Transaction as ProtoTransaction, Winner as ProtoWinner};
-use proto::data::DataType as ProtoDataType;
+use proto::Marco as ProtoMarco;
 
-use crate::kademlia::node::ID_LEN;
 // Auxiliary functions
 #[doc(inline)]
-use crate::kademlia::node::Identifier;
-use crate::marco::auction::Auction;
-use crate::marco::bid::Bid;
-use crate::marco::marco::{Data, Marco};
-use crate::marco::transaction::Transaction;
-use crate::marco::winner::Winner;
+use crate::kademlia::node::{Distance, Identifier};
+use crate::ledger::block::Block;
+use crate::marco::marco::Marco;
+use crate::marco::payload::registry;
 use crate::proto;
 use crate::proto::DstAddress;
 use crate::proto::SrcAddress;
 
-/// Converts a node identifier (Vec<u8>) into a string, after hashing
+/// Converts a node identifier into a string, after hashing
 pub fn convert_node_id_to_string (node_id: &Identifier) -> String{
     let mut hasher = Sha3_256::new();
     hasher.update(node_id.0);
@@ -32,65 +28,19 @@ pub fn convert_node_id_to_string (node_id: &Identifier) -> String{
     return string;
 }
 
-/// Converts an Identifier (`Vec<u8>`) into a String
-pub fn vec_u8_to_string (v: Identifier) -> String {
-    let mut string_result: String = "".parse().unwrap();
-    for x in &v.0 {
-        string_result += &*x.to_string();
-    }
-    return string_result;
-}
-
-// Applies XOR to each element of the Vector
-// and returns the XORed elements inside another Vector
 /// Calculates the distance between two Identifiers using xor
-pub fn xor_distance(id1: &Identifier, id2: &Identifier) -> usize {
-    let mut res: [u8; ID_LEN] = [0; ID_LEN];
-    for i in 0..ID_LEN {
-        res[i] = id1.0[i] ^ id2.0[i];
-    }
-
-    let leading = get_leading(res) as usize;
-    if leading == 0 {
-        return 1;
-    }
-    return leading;
-}
-
-// Return the number of leading zeros (aka number of bits different between the two nodes)
-/// Gets the number of leading zeros, i.e., the number of consecutive zeros on the left
-fn get_leading(v: [u8; ID_LEN]) -> u32 {
-    let mut count = 0;
-    for i in v {
-        if i == 0 {
-            count += 1;
-        } else {
-            return count;
-        }
-    }
-    return count;
+pub fn xor_distance(id1: &Identifier, id2: &Identifier) -> Distance {
+    id1.distance(id2)
 }
 
+/// Identifier of a string: its SHA3_256
 pub fn gen_id (str: String) -> Identifier {
     let mut hasher = Sha3_256::new();
     hasher.update(str.as_bytes());
 
     // we know that the hash output is going to be 256 bits = 32 bytes
     let result = hasher.finalize();
-
-    let mut hash = [0; ID_LEN];
-    let mut bin_str = "".to_string();
-    for b in result {
-        bin_str += &format!("{:0>8b}", b); // Force the representation to pad with 0's in case there aren't 8 bits
-    }
-
-    let mut i = 0;
-    assert_eq!(bin_str.len(), ID_LEN); // assert that we indeed have ID_LEN bits
-    for b in bin_str.chars() {
-        hash[i] = b.to_digit(2).unwrap() as u8;
-        i += 1;
-    }
-    Identifier::new(hash)
+    Identifier::new(result.into())
 }
 
 pub fn gen_address_src(id: Identifier,ip: String, port: u32) -> Option<SrcAddress> {
@@ -149,83 +99,50 @@ pub fn get_private_key(private_key: String) -> RsaPrivateKey {
     rsa_priv
 }
 
-pub fn transform_proto_to_marco(proto_marco: &ProtoMarco) -> Marco {
-    let data = match proto_marco.data.as_ref().expect("Data is missing").data_type.as_ref().expect("Data type is missing") {
-        ProtoDataType::Transaction(t) => Data::Transaction(Transaction {
-            from: t.from.clone(),
-            to: t.to.clone(),
-            amount_in: t.amount_in,
-            amount_out: t.amount_out,
-            miner_fee: t.miner_fee,
-        }),
-        ProtoDataType::CreateAuction(a) => Data::CreateAuction(Auction {
-            auction_id: a.auction_id,
-            seller_id: a.seller_id.clone(),
-            amount: a.amount,
-        }),
-        ProtoDataType::Bid(b) => Data::Bid(Bid {
-            auction_id: b.auction_id.clone(),
-            buyer_id: b.buyer_id.clone(),
-            seller_id: b.seller_id.clone(),
-            amount: b.amount,
-        }),
-        ProtoDataType::Winner(win) => Data::Winner( Winner {
-            auction: win.auction.clone(),
-            from: win.from.clone(),
-            to: win.to.clone(),
-            amount: win.amount.clone(),
-        }),
-    };
-
-    Marco {
+/// Converts a proto Marco into a [Marco], the data is decoded by the payload [registry]
+///
+/// **outputs:**
+/// - None if the Marco has no data or no timestamp
+pub fn transform_proto_to_marco(proto_marco: &ProtoMarco) -> Option<Marco> {
+    let data = registry().decode(proto_marco.data.as_ref()?)?;
+
+    Some(Marco {
         hash: proto_marco.hash.clone(),
         signature: proto_marco.signature.clone(),
+        public_key: proto_marco.public_key.clone(),
+        nonce: proto_marco.nonce,
+        chain_id: proto_marco.chain_id,
         data,
-        timestamp: timestamp_to_system_time(Some(&proto_marco.timestamp.clone().unwrap())).unwrap()
-    }
+        timestamp: timestamp_to_system_time(Some(proto_marco.timestamp.as_ref()?))?
+    })
 }
 
+/// Converts a [Marco] into a proto Marco, the data is encoded by the payload [registry]
 pub fn transform_marco_to_proto(marco: &Marco) -> ProtoMarco {
-    let data = match &marco.data {
-        Data::Transaction(t) => ProtoData {
-            data_type: Some(ProtoDataType::Transaction(ProtoTransaction {
-                from: t.from.clone(),
-                to: t.to.clone(),
-                amount_in: t.amount_in,
-                amount_out: t.amount_out,
-                miner_fee: t.miner_fee,
-            })),
-        },
-        Data::CreateAuction(a) => ProtoData {
-            data_type: Some(ProtoDataType::CreateAuction(ProtoAuction {
-                auction_id: a.auction_id,
-                seller_id: a.seller_id.clone(),
-                amount: a.amount,
-            })),
-        },
-        Data::Bid(b) => ProtoData {
-            data_type: Some(ProtoDataType::Bid(ProtoBid {
-                auction_id: b.auction_id.clone(),
-                buyer_id: b.buyer_id.clone(),
-                seller_id: b.seller_id.clone(),
-                amount: b.amount,
-            })),
-        },
-        Data::Winner(win) => ProtoData {
-            data_type: Some(ProtoDataType::Winner(ProtoWinner {
-                auction: win.auction.clone(),
-                from: win.from.clone(),
-                to: win.to.clone(),
-                amount: win.amount.clone(),
-            }))
-        }
-    };
-
     proto::Marco {
         hash: marco.hash.clone(),
         signature: marco.signature.clone(),
-        data: Some(data),
-        timestamp: system_time_to_timestamp(Some(marco.timestamp))
+        public_key: marco.public_key.clone(),
+        data: Some(registry().encode(&marco.data)),
+        timestamp: system_time_to_timestamp(Some(marco.timestamp)),
+        nonce: marco.nonce,
+        chain_id: marco.chain_id
+    }
+}
+
+/// Converts a [Block] into a proto Block
+pub fn transform_block_to_proto(block: &Block) -> proto::Block {
+    proto::Block {
+        hash: block.hash.clone(),
+        index: block.index as u64,
+        timestamp: block.timestamp,
+        prev_hash: block.prev_hash.clone(),
+        nonce: block.nonce,
+        difficulty: block.difficulty as u64,
+        miner_id: block.miner_id.clone(),
+        merkle_tree_root: block.merkle_tree_root.clone(),
+        confirmations: block.confirmations as u64,
+        transactions: block.transactions.iter().map(transform_marco_to_proto).collect(),
     }
 }
 
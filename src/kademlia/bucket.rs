@@ -1,16 +1,27 @@
 use std::borrow::BorrowMut;
 #[doc(inline)]
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
 
 use crate::kademlia::node::{Identifier, Node};
 use crate::kademlia::trust_score::TrustScore;
 
 /// Defines the maximum number of nodes allowed per bucket
 pub const K: usize = 20; // Max bucket size
+/// Candidates kept per bucket to take the place of contacts that stop answering
+pub const REPLACEMENT_CACHE: usize = K;
 #[derive(Clone, Debug, Default, PartialEq)]
 /// ## Bucket
 pub struct Bucket {
     pub map: VecDeque<(Node, TrustScore)>,
+    /// last time each node answered us or was added
+    pub last_seen: HashMap<Identifier, Instant>,
+    /// last time a node of the bucket was seen or the bucket was refreshed, [None] if never
+    pub last_activity: Option<Instant>,
+    /// nodes heard of while the bucket was full, most recently seen last
+    pub replacements: VecDeque<Node>,
+    /// top node being pinged before it is evicted, see [Bucket::contact_checked]
+    pub checking: Option<Identifier>,
 }
 
 impl Bucket {
@@ -23,29 +34,76 @@ impl Bucket {
     pub fn new() -> Self {
         Bucket {
             map: VecDeque::new(),
+            last_seen: HashMap::new(),
+            last_activity: None,
+            replacements: VecDeque::new(),
+            checking: None,
         }
     }
 
     /// # add
-    /// Add a new node to the [Bucket]
+    /// Add a new node to the [Bucket]. A node already in the bucket was just seen, so it is moved to the back.
+    /// If the bucket is full, the node goes to the replacement cache
+    /// and the top (least recently seen) node should be pinged, then passed to [Bucket::contact_checked]
     ///
     /// #### Returns
-    /// If the bucket is full, return the top [Node] otherwise return [None].
+    /// If the bucket is full, return the top [Node] to ping otherwise (or if it is already being pinged) return [None].
     pub fn add(&mut self, node: &Node) -> Option<Node> {
-        return if self.map.len() < K {
-            for i in self.map.iter() {
-                if i.0.id == node.id { // Sanity to check to make sure the node is not inside the bucket
-                    return None;
-                }
-            }
+        if let Some((known, _)) = self.map.iter().find(|(n, _)| n.id == node.id) {
+            self.send_back_specific_node(known.clone());
+            self.mark_seen(&node.id, Instant::now());
+            return None;
+        }
+        if self.map.len() < K {
             self.map.push_back((node.clone(), TrustScore::new())); // Add node to the back of the Vector
-            None
+            self.mark_seen(&node.id, Instant::now());
+            return None;
+        }
+
+        self.replacements.retain(|n| n.id != node.id);
+        self.replacements.push_back(node.clone());
+        if self.replacements.len() > REPLACEMENT_CACHE {
+            self.replacements.pop_front();
+        }
+        if self.checking.is_some() {
+            return None;
+        }
+        let top = self.map.front()?.0.clone();
+        self.checking = Some(top.id.clone());
+        Some(top)
+    }
+
+    /// # contact_checked
+    /// Result of pinging the node returned by [Bucket::add]: if it answered it is kept and moved to the back,
+    /// otherwise it is removed and the most recently seen replacement takes its place
+    pub fn contact_checked(&mut self, node: &Node, alive: bool) {
+        if self.checking.as_ref() == Some(&node.id) {
+            self.checking = None;
+        }
+        if alive {
+            self.send_back_specific_node(node.clone());
+            self.mark_seen(&node.id, Instant::now());
         } else {
-            let top_node = self.map.get(0);
-            if top_node.is_none() {
-                return None;
+            self.remove(node.id.clone());
+        }
+    }
+
+    /// # restore
+    /// Adds a node saved in a routing table snapshot, along with its [TrustScore]
+    ///
+    /// #### Returns
+    /// False if the bucket is full
+    pub fn restore(&mut self, node: &Node, trust: TrustScore) -> bool {
+        if self.map.len() >= K {
+            return false;
+        }
+        self.add(node);
+        match self.map.iter_mut().find(|(n, _)| n.id == node.id) {
+            Some(entry) => {
+                entry.1 = trust;
+                true
             }
-            Some(top_node.unwrap().clone().0)
+            None => false,
         }
     }
 
@@ -54,8 +112,32 @@ impl Bucket {
     /// Keep in mind that after removing the top node, the new node is added to
     /// the last position. In kademlia the nodes are stored from the older node contacted to the most recent (top to bottom).
     pub fn replace_node(&mut self, node: &Node){
-        self.map.pop_front();
+        if let Some((old, _)) = self.map.pop_front() {
+            self.last_seen.remove(&old.id);
+        }
+        self.checking = None;
+        self.replacements.retain(|n| n.id != node.id);
         self.map.push_back((node.clone(), TrustScore::new()));
+        self.mark_seen(&node.id, Instant::now());
+    }
+
+    /// # mark_seen
+    /// Records that the node answered us (or was just added) at `now`
+    pub fn mark_seen(&mut self, id: &Identifier, now: Instant) {
+        if self.map.iter().any(|(node, _)| node.id == *id) {
+            self.last_seen.insert(id.clone(), now);
+            self.last_activity = Some(now);
+        }
+    }
+
+    /// # stale_nodes
+    /// #### Returns
+    /// The nodes not seen since `since`, least recently contacted first
+    pub fn stale_nodes(&self, since: Instant) -> Vec<Node> {
+        self.map.iter()
+            .filter(|(node, _)| self.last_seen.get(&node.id).is_none_or(|seen| *seen < since))
+            .map(|(node, _)| node.clone())
+            .collect()
     }
 
     /// # send_back
@@ -96,15 +178,19 @@ impl Bucket {
 
 
     /// # remove
-    /// Attempts to remove a node according to the [id](Identifier) passed.
+    /// Attempts to remove a node according to the [id](Identifier) passed,
+    /// the most recently seen node of the replacement cache takes its place.
     pub fn remove(&mut self, id: Identifier){
-        let mut i = 0;
-        for node in self.map.iter() {
-            if node.0.id == id {
-                self.map.remove(i);
-                return;
+        if let Some(i) = self.map.iter().position(|node| node.0.id == id) {
+            self.map.remove(i);
+            self.last_seen.remove(&id);
+            if self.checking.as_ref() == Some(&id) {
+                self.checking = None;
+            }
+            if let Some(next) = self.replacements.pop_back() {
+                self.map.push_back((next.clone(), TrustScore::new()));
+                self.mark_seen(&next.id, Instant::now());
             }
-            i += 1;
         }
     }
 
@@ -156,7 +242,7 @@ impl Bucket {
 
 #[cfg(test)]
 mod tests {
-    use crate::kademlia::bucket::Bucket;
+    use crate::kademlia::bucket::{Bucket, K};
     use crate::kademlia::node::Node;
     use crate::kademlia::trust_score::TrustScore;
 
@@ -172,4 +258,36 @@ mod tests {
 
         assert!(bucket.map.contains(&(node, TrustScore::new())));
     }
+
+    #[test]
+    fn test_ping_before_evict() {
+        let node = |port: u32| Node::new("127.0.0.1".to_string(), port).unwrap();
+        let mut bucket = Bucket::new();
+        for port in 0..K as u32 {
+            assert!(bucket.add(&node(9000 + port)).is_none());
+        }
+
+        // Full: the top node has to be pinged, only once, and the new nodes wait in the cache
+        assert_eq!(bucket.add(&node(8000)), Some(node(9000)));
+        assert_eq!(bucket.add(&node(8001)), None);
+        assert_eq!(bucket.replacements, vec![node(8000), node(8001)]);
+
+        // A known node that contacts us again moves to the back
+        assert_eq!(bucket.add(&node(9001)), None);
+        assert_eq!(bucket.map.back().unwrap().0, node(9001));
+        assert_eq!(bucket.map.front().unwrap().0, node(9000));
+
+        // It answered, so it stays (now at the back)
+        bucket.contact_checked(&node(9000), true);
+        assert_eq!(bucket.map.back().unwrap().0, node(9000));
+        assert_eq!(bucket.map.len(), K);
+
+        // The next top node does not answer, the most recently seen candidate replaces it
+        assert_eq!(bucket.add(&node(8000)), Some(node(9002)));
+        bucket.contact_checked(&node(9002), false);
+        assert!(!bucket.map.iter().any(|(n, _)| *n == node(9002)));
+        assert_eq!(bucket.map.back().unwrap().0, node(8000));
+        assert_eq!(bucket.replacements, vec![node(8001)]);
+        assert_eq!(bucket.checking, None);
+    }
 }
\ No newline at end of file
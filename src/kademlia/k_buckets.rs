@@ -1,13 +1,15 @@
 use std::cmp::Ordering;
+use std::time::Instant;
+
+use rand::Rng;
 
-use crate::auxi;
 #[doc(inline)]
 use crate::kademlia::bucket::Bucket;
 use crate::kademlia::bucket::K;
-use crate::kademlia::node::{ID_LEN, Identifier, Node};
+use crate::kademlia::node::{ID_BITS, ID_LEN, Identifier, Node};
 use crate::kademlia::trust_score::TrustScore;
 
-pub const MAX_BUCKETS: usize = ID_LEN; // Max amount of Buckets (AKA amount of sub-tries)
+pub const MAX_BUCKETS: usize = ID_BITS; // Max amount of Buckets (AKA amount of sub-tries), one per bit of the distance
 pub const B: f64 = 0.65;
 
 /// ## Kbucket
@@ -44,12 +46,80 @@ impl KBucket {
         }
 
         // Get the index with relation to the buckets
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, &node.id);
+        let index = self.id.distance(&node.id).bucket_index();
         self.buckets[index].add(node)
         // None => Means we added the node to the vector
         // Node => Bucket was full, but we need to check if the latest contacted node is up, if not substitute
     }
 
+    /// # restore
+    /// Proxy for the [Bucket::restore] function.
+    ///
+    /// #### Returns
+    /// False if the node is ourselves or its bucket is full
+    pub fn restore(&mut self, node: &Node, trust: TrustScore) -> bool {
+        if node.id == self.id {
+            return false;
+        }
+        let index = self.id.distance(&node.id).bucket_index();
+        self.buckets[index].restore(node, trust)
+    }
+
+    /// # contact_checked
+    /// Proxy for the [Bucket::contact_checked] function.
+    pub fn contact_checked(&mut self, node: &Node, alive: bool) {
+        let index = self.id.distance(&node.id).bucket_index();
+        self.buckets[index].contact_checked(node, alive);
+    }
+
+    /// # mark_seen
+    /// Proxy for the [Bucket::mark_seen] function.
+    pub fn mark_seen(&mut self, id: &Identifier, now: Instant) {
+        let index = self.id.distance(id).bucket_index();
+        self.buckets[index].mark_seen(id, now);
+    }
+
+    /// # stale_nodes
+    /// Proxy for the [Bucket::stale_nodes] function, over every bucket.
+    pub fn stale_nodes(&self, since: Instant) -> Vec<Node> {
+        self.buckets.iter().flat_map(|bucket| bucket.stale_nodes(since)).collect()
+    }
+
+    /// # buckets_to_refresh
+    /// Buckets with no activity since `since`, they are marked as refreshed at `now`.
+    /// Buckets closer than our closest node are left out, with a random ID they almost always stay empty
+    ///
+    /// #### Returns
+    /// The indexes of the buckets to refresh
+    pub fn buckets_to_refresh(&mut self, since: Instant, now: Instant) -> Vec<usize> {
+        let closest = self.buckets.iter().position(|bucket| !bucket.map.is_empty()).unwrap_or(MAX_BUCKETS - 1);
+        let mut due = Vec::new();
+        for (index, bucket) in self.buckets.iter_mut().enumerate().skip(closest) {
+            if bucket.last_activity.is_none_or(|at| at < since) {
+                bucket.last_activity = Some(now);
+                due.push(index);
+            }
+        }
+        due
+    }
+
+    /// # random_id_in_bucket
+    /// #### Returns
+    /// A random [Identifier] that falls in the bucket `index`, the target of the lookup that refreshes it
+    pub fn random_id_in_bucket(&self, index: usize) -> Identifier {
+        let mut distance: [u8; ID_LEN] = rand::thread_rng().gen();
+        // The highest set bit of the distance is the bit `index`, counting from the least significant
+        let (byte, bit) = (ID_LEN - 1 - index / 8, index % 8);
+        distance[..byte].fill(0);
+        distance[byte] &= (1u8 << bit) - 1;
+        distance[byte] |= 1u8 << bit;
+        let mut id = self.id.0;
+        for (b, d) in id.iter_mut().zip(distance) {
+            *b ^= d;
+        }
+        Identifier::new(id)
+    }
+
     /// # replace_node
     /// Proxy for the [Bucket::replace_node] function.
     /// Attempts to replace the top node with the one passed as argument.
@@ -59,7 +129,7 @@ impl KBucket {
         // This function will be called if a certain bucket is full
         // but we received a packet from a node which would belong to that
         // bucket and the latest stored node of that particular bucket is down
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, &node.id);
+        let index = self.id.distance(&node.id).bucket_index();
         self.buckets[index].replace_node(node); // Remove the top node and push back the passed node
     }
 
@@ -69,12 +139,12 @@ impl KBucket {
     pub fn send_back(&mut self, node: &Node) {
         // This function will send the element on the top of the list to the back
         // Shifting upwards 1 every other node
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, &node.id);
+        let index = self.id.distance(&node.id).bucket_index();
         self.buckets[index].send_back();
     }
 
     pub fn send_back_specific_node(&mut self, node: &Node) {
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, &node.id);
+        let index = self.id.distance(&node.id).bucket_index();
         self.buckets[index].send_back_specific_node(node.clone());
     }
 
@@ -84,7 +154,7 @@ impl KBucket {
     /// #### Returns
     /// If successful, return the [Node] otherwise return [None].
     pub fn get (&self, id: &Identifier) -> Option<Node>{
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, id);
+        let index = self.id.distance(id).bucket_index();
         let bucket = &self.buckets[index];
 
         for i in bucket.map.iter() {
@@ -97,7 +167,7 @@ impl KBucket {
     }
 
     pub fn get_trust_score(&self, id: &Identifier) -> Option<TrustScore> {
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, id);
+        let index = self.id.distance(id).bucket_index();
         let bucket = &self.buckets[index];
 
         for i in bucket.map.iter() {
@@ -114,7 +184,7 @@ impl KBucket {
     /// Attempts to remove a node from its bucket.
     ///
     pub fn remove (&mut self, id: &Identifier){
-        let index = MAX_BUCKETS - auxi::xor_distance(&self.id, id);
+        let index = self.id.distance(id).bucket_index();
 
         if !Self::get(self, id).is_none() {
             let _ = &self.buckets[index].remove(id.clone());
@@ -145,45 +215,33 @@ impl KBucket {
     /// Get the n closest nodes from the given node
     ///
     /// #### Returns
-    /// Attempts to fetch at least `n` nodes (the closest to the passed [id](Identifier)). If none are found
-    /// return [None].
+    /// Attempts to fetch the `n` nodes with the smallest XOR distance to the passed [id](Identifier),
+    /// closest first. If none are found return [None].
     pub fn get_n_closest_nodes (&self, id: Identifier, n: usize) -> Option<Vec<Node>>{
+        if n == 0 {
+            return None;
+        }
+        let target_index = self.id.distance(&id).bucket_index();
+
+        // The distance of a node in bucket i to the target has its highest bit at:
+        //  - below target_index, for the nodes of the target's own bucket
+        //  - target_index, for the nodes of every bucket below it
+        //  - i, for the nodes of every bucket above it
+        // So the buckets are walked in that order, and each group of buckets is only
+        // sorted by the actual distance once it is known to hold some of the n closest nodes
+        let mut groups: Vec<Vec<usize>> = vec![vec![target_index], (0..target_index).collect()];
+        groups.extend((target_index + 1..MAX_BUCKETS).map(|i| vec![i]));
+
         let mut closest_nodes: Vec<Node> = Vec::new();
-        let given_node_index: i64 = (MAX_BUCKETS - auxi::xor_distance(&id, &self.id)) as i64;
-        let mut index;
-        let mut i = 0;
-        let mut j = 1;
-
-        // The overall concept is:
-        // 0 1 2 3 ... 90 target 92 93 94 ... MAX_BUCKETS
-        // The closest nodes will be all that are inside target, then all that are inside 90 and 92, then those inside 89 and 93 and so on
-        // What this for loop does is simply iterate on a zigzag pattern until it fills the vector
-        for _ in 0..MAX_BUCKETS {
-            if given_node_index + i < MAX_BUCKETS as i64 {
-                index = given_node_index + i;
-                if let Some(nodes) = self.get_nodes_from_bucket(index as usize) {
-                    for node in nodes {
-                        if closest_nodes.len() < n {
-                            closest_nodes.push(node);
-                        } else {
-                            return Some(closest_nodes);
-                        }
-                    }
-                }
-                i += 1;
-            }
-            if given_node_index - j >= 0 {
-                index = given_node_index - j;
-                if let Some(nodes) = self.get_nodes_from_bucket(index as usize) {
-                    for node in nodes {
-                        if closest_nodes.len() < n {
-                            closest_nodes.push(node);
-                        } else {
-                            return Some(closest_nodes);
-                        }
-                    }
-                }
-                j += 1;
+        for group in groups {
+            let mut nodes: Vec<Node> = group.into_iter()
+                .filter_map(|index| self.get_nodes_from_bucket(index))
+                .flatten()
+                .collect();
+            nodes.sort_by_key(|node| node.id.distance(&id));
+            closest_nodes.extend(nodes.into_iter().take(n - closest_nodes.len()));
+            if closest_nodes.len() == n {
+                break;
             }
         }
         // If at the end the vector is empty return None
@@ -191,34 +249,32 @@ impl KBucket {
             return None;
         }
 
-        // Otherwise it most likely means we weren't able to fill the vector
-        // So we return the nodes we were able to gather
+        // Otherwise we either filled the vector or returned every node we know
         Some(closest_nodes)
-
     }
 
     pub fn reputation_penalty(&mut self, identifier: Identifier) {
-        let given_node_index = (MAX_BUCKETS - auxi::xor_distance(&self.id, &identifier)) as usize;
+        let given_node_index = self.id.distance(&identifier).bucket_index();
         self.buckets[given_node_index].reputation_penalty(identifier);
     }
 
     pub fn reputation_reward(&mut self, identifier: Identifier) {
-        let given_node_index = (MAX_BUCKETS - auxi::xor_distance(&self.id, &identifier)) as usize;
+        let given_node_index = self.id.distance(&identifier).bucket_index();
         self.buckets[given_node_index].good_reputation(identifier);
     }
 
     pub fn risk_penalty(&mut self, identifier: Identifier) {
-        let given_node_index = (MAX_BUCKETS - auxi::xor_distance(&self.id, &identifier)) as usize;
+        let given_node_index = self.id.distance(&identifier).bucket_index();
         self.buckets[given_node_index].risk_penalty(identifier);
     }
 
     pub fn increment_interactions(&mut self, identifier: Identifier) {
-        let given_node_index = (MAX_BUCKETS - auxi::xor_distance(&self.id, &identifier)) as usize;
+        let given_node_index = self.id.distance(&identifier).bucket_index();
         self.buckets[given_node_index].increment_interactions(identifier);
     }
 
     pub fn increment_lookups(&mut self, identifier: Identifier) {
-        let given_node_index = (MAX_BUCKETS - auxi::xor_distance(&self.id, &identifier)) as usize;
+        let given_node_index = self.id.distance(&identifier).bucket_index();
         self.buckets[given_node_index].increment_lookups(identifier);
     }
     pub fn get_all_trust_scores(&mut self) -> Vec<(Node, TrustScore)> {
@@ -243,8 +299,8 @@ impl KBucket {
         }
         // Sort the nodes based in new_distance
         temp.sort_by(|a, b| {
-            let old_distance1 = MAX_BUCKETS - auxi::xor_distance(&self.id, &a.0.id);
-            let old_distance2 = MAX_BUCKETS - auxi::xor_distance(&self.id, &b.0.id);
+            let old_distance1 = self.id.distance(&a.0.id).bucket_index();
+            let old_distance2 = self.id.distance(&b.0.id).bucket_index();
             let score1 = a.1.clone().get_score();
             let score2 = b.1.clone().get_score();
             let new_distance1 = old_distance1 as f64 * B + (1f64 - B) * (1f64 / score1);
@@ -264,3 +320,79 @@ impl KBucket {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use proptest::prelude::*;
+
+    use crate::kademlia::k_buckets::{KBucket, MAX_BUCKETS};
+    use crate::kademlia::node::{ID_LEN, Identifier, Node};
+
+    /// Identifier sharing its first `shared` bytes with `base`, so buckets fill up unevenly
+    fn near(base: &[u8; ID_LEN], raw: [u8; ID_LEN], shared: usize) -> Identifier {
+        let mut id = raw;
+        id[..shared].copy_from_slice(&base[..shared]);
+        Identifier::new(id)
+    }
+
+    proptest! {
+        #[test]
+        fn test_n_closest_is_brute_force(own in any::<[u8; ID_LEN]>(),
+                                         target in any::<[u8; ID_LEN]>(),
+                                         target_shared in 0..ID_LEN,
+                                         ids in prop::collection::vec((any::<[u8; ID_LEN]>(), 0..4usize), 0..120),
+                                         n in 0..40usize) {
+            let mut kbucket = KBucket::new(Identifier::new(own));
+            for (i, (raw, shared)) in ids.into_iter().enumerate() {
+                let node = Node { id: near(&own, raw, shared), ip: "127.0.0.1".to_string(), port: i as u32 };
+                kbucket.add(&node);
+            }
+            let target = near(&own, target, target_shared);
+
+            // Brute force over every node the buckets kept
+            let mut expected: Vec<Node> = kbucket.buckets.iter()
+                .flat_map(|b| b.map.iter().map(|(node, _)| node.clone()))
+                .collect();
+            expected.sort_by_key(|node| node.id.distance(&target));
+            expected.truncate(n);
+
+            let found = kbucket.get_n_closest_nodes(target, n).unwrap_or_default();
+            let ids = |nodes: &Vec<Node>| nodes.iter().map(|node| node.id.clone()).collect::<Vec<_>>();
+            prop_assert_eq!(ids(&found), ids(&expected));
+        }
+    }
+
+    #[test]
+    fn test_refresh_buckets() {
+        let own = Node::new("127.0.0.1".to_string(), 8888).unwrap();
+        let mut kbucket = KBucket::new(own.id.clone());
+        for index in 0..MAX_BUCKETS {
+            let id = kbucket.random_id_in_bucket(index);
+            assert_eq!(kbucket.id.distance(&id).bucket_index(), index);
+        }
+
+        let start = Instant::now();
+        let nodes: Vec<Node> = (0..10).map(|port| Node::new("127.0.0.1".to_string(), 9000 + port).unwrap()).collect();
+        for node in &nodes {
+            kbucket.add(node);
+        }
+        let closest = kbucket.buckets.iter().position(|b| !b.map.is_empty()).unwrap();
+        let (now, later) = (Instant::now(), Instant::now() + Duration::from_secs(60));
+
+        // The buckets with nodes were just active, every other one from the closest node up is refreshed, once
+        let due = kbucket.buckets_to_refresh(start, now);
+        assert!(due.iter().all(|i| *i > closest && kbucket.buckets[*i].map.is_empty()));
+        let busy = kbucket.buckets.iter().filter(|b| !b.map.is_empty()).count();
+        assert_eq!(due.len(), MAX_BUCKETS - closest - busy);
+        assert!(kbucket.buckets_to_refresh(start, now).is_empty());
+
+        // Nodes that did not answer since are stale
+        kbucket.mark_seen(&nodes[0].id, later);
+        let stale = kbucket.stale_nodes(later);
+        assert_eq!(stale.len(), nodes.len() - 1);
+        assert!(!stale.contains(&nodes[0]));
+        assert_eq!(kbucket.buckets_to_refresh(later, later).len(), MAX_BUCKETS - closest - 1);
+    }
+}
@@ -1,11 +1,14 @@
 use std::collections::{BinaryHeap, HashMap};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
 
-use crate::auxi;
 use crate::kademlia::bucket::K;
-use crate::kademlia::k_buckets::{KBucket, MAX_BUCKETS};
-use crate::kademlia::node::{ID_LEN, Identifier};
+use crate::kademlia::k_buckets::{B, KBucket, MAX_BUCKETS};
+use crate::kademlia::node::Identifier;
 #[doc(inline)]
 use crate::kademlia::node::Node;
+use crate::kademlia::record::{DueRecords, Record, RECORD_EXPIRE, REPLICATE_INTERVAL};
 use crate::kademlia::trust_score::TrustScore;
 use crate::p2p::peer_modules::peer_rpc_client::NodeNewDistance;
 
@@ -15,7 +18,7 @@ pub struct Kademlia {
     // Struct holding the node's state, routing table, etc.
     node: Node,
     kbuckets: KBucket,
-    map: HashMap<Identifier, String>
+    map: HashMap<Identifier, Record>
 }
 
 impl Kademlia {
@@ -34,9 +37,33 @@ impl Kademlia {
     }
 
     /// # add_key
-    /// This function will add a new ([Key](Identifier), [Value](String)) to the [Kademlia] map.
+    /// This function will add a new ([Key](Identifier), [Value](String)) to the [Kademlia] map,
+    /// it expires after [RECORD_EXPIRE].
     pub fn add_key(&mut self, key: Identifier, value: String) {
-        let _ = self.map.insert(key, value);
+        self.store_record(key, value, RECORD_EXPIRE);
+    }
+
+    /// # store_record
+    /// Stores a value on behalf of another node for `lifetime` (at most [RECORD_EXPIRE]).
+    /// If the key was already stored, the record keeps its publisher and lives as long as the longest of both
+    pub fn store_record(&mut self, key: Identifier, value: String, lifetime: Duration) {
+        let record = Record::new(value, lifetime, Instant::now());
+        match self.map.get_mut(&key) {
+            Some(old) => {
+                old.value = record.value;
+                old.expires_at = old.expires_at.max(record.expires_at);
+            }
+            None => {
+                self.map.insert(key, record);
+            }
+        }
+    }
+
+    /// # publish_key
+    /// Stores a value this node publishes, it is stored again in the network every
+    /// [REPUBLISH_INTERVAL](crate::kademlia::record::REPUBLISH_INTERVAL) so it never expires while we are up
+    pub fn publish_key(&mut self, key: Identifier, value: String) {
+        self.map.insert(key, Record::published(value, Instant::now()));
     }
 
     /// # get_value
@@ -44,9 +71,11 @@ impl Kademlia {
     /// corresponding passed [Key](Identifier).
     ///
     /// #### Returns
-    /// If successful, return a [&String](String), otherwise return [None].
+    /// If successful, return a [&String](String), otherwise (or if the record expired) return [None].
     pub fn get_value(&self, key: Identifier) -> Option<&String> {
         self.map.get(&key)
+            .filter(|record| !record.is_expired(Instant::now()))
+            .map(|record| &record.value)
     }
 
     /// # remove_key
@@ -62,31 +91,83 @@ impl Kademlia {
         return true;
     }
 
-    /// # is_closest
-    /// This function will check if the [self.node](Kademlia) is the closest to a given [Key](Identifier)
+    /// # expire_records
+    /// Drops the records that expired by `now`
     ///
     /// #### Returns
-    /// If true, return [None] otherwise, return, up to [K], nearest nodes in a [Vec<Node>](Vec) as an Option.
-    pub fn is_closest(&self, key: &Identifier) -> Option<Vec<Node>>{
-        // Remember that the following function returns the amount of 0's to the left
-        // Meaning the higher the amount, the closest the 2 ids are
-        let own_distance = MAX_BUCKETS - auxi::xor_distance(&self.node.id, key);
-        let nodes = &self.kbuckets.get_n_closest_nodes(key.clone(), K);
-        // If no nodes stored in the bucket with the closest nodes
-        // Return None
-        if nodes.is_none() {
-            return None;
+    /// The number of records dropped
+    pub fn expire_records(&mut self, now: Instant) -> usize {
+        let before = self.map.len();
+        self.map.retain(|_, record| !record.is_expired(now));
+        before - self.map.len()
+    }
+
+    /// # records_to_republish
+    /// Records this node published that are due to be stored in the network again,
+    /// their lifetime starts over and their next republish is scheduled
+    pub fn records_to_republish(&mut self, now: Instant) -> Vec<(Identifier, String)> {
+        let mut due = Vec::new();
+        for (key, record) in self.map.iter_mut() {
+            if record.republish_at.is_some_and(|at| at <= now) {
+                *record = Record::published(record.value.clone(), now);
+                due.push((key.clone(), record.value.clone()));
+            }
         }
+        due
+    }
 
-        // For each node collected, check if any of them is closer to than ourselfs
-        // If any is, return them, otherwise return None
-        for i in <Option<Vec<Node>> as Clone>::clone(&nodes).unwrap() {
-            if (MAX_BUCKETS - auxi::xor_distance(&i.id, key)) < own_distance {
-                return nodes.clone();
+    /// # records_to_replicate
+    /// Records due to be sent to the [K] closest nodes we know, along with the lifetime they have left
+    /// and the nodes among those that did not get them yet (nodes that became closer since the last time).
+    /// Their next replication is scheduled
+    pub fn records_to_replicate(&mut self, now: Instant) -> Vec<(Identifier, String, Duration, Vec<Node>)> {
+        let mut due = Vec::new();
+        for (key, record) in self.map.iter_mut() {
+            if record.replicate_at > now || record.is_expired(now) {
+                continue;
+            }
+            record.replicate_at = now + REPLICATE_INTERVAL;
+            let nodes: Vec<Node> = self.kbuckets.get_n_closest_nodes(key.clone(), K).unwrap_or_default().into_iter()
+                .filter(|node| node.id != self.node.id && !record.replicated_to.contains(&node.id))
+                .collect();
+            if !nodes.is_empty() {
+                due.push((key.clone(), record.value.clone(), record.lifetime(now), nodes));
             }
         }
+        due
+    }
+
+    /// # due_records
+    /// One maintenance pass over the records: the ones we published are scheduled for republishing first,
+    /// then the expired ones are dropped and the rest are scheduled for replication
+    pub fn due_records(&mut self, now: Instant) -> DueRecords {
+        let republish = self.records_to_republish(now);
+        let expired = self.expire_records(now);
+        let replicate = self.records_to_replicate(now);
+        DueRecords { republish, expired, replicate }
+    }
+
+    /// # mark_replicated
+    /// Remembers that `node` holds the record, so it is not sent again until the record is republished
+    pub fn mark_replicated(&mut self, key: &Identifier, node: Identifier) {
+        if let Some(record) = self.map.get_mut(key) {
+            record.replicated_to.insert(node);
+        }
+    }
 
-        return None;
+    /// # is_closest
+    /// This function will check if the [self.node](Kademlia) is the closest to a given [Key](Identifier)
+    ///
+    /// #### Returns
+    /// If true, return [None] otherwise, return, up to [K], nearest nodes in a [Vec<Node>](Vec) as an Option.
+    pub fn is_closest(&self, key: &Identifier) -> Option<Vec<Node>>{
+        let own_distance = self.node.id.distance(key);
+        // The nodes come closest first, so we are the closest unless the first one beats us
+        let nodes = self.kbuckets.get_n_closest_nodes(key.clone(), K)?;
+        if nodes[0].id.distance(key) < own_distance {
+            return Some(nodes);
+        }
+        None
     }
 
     /// # add_node
@@ -94,11 +175,18 @@ impl Kademlia {
     /// Attempts to add a [Node] to its corresponding [Bucket](kademlia::bucket)
     ///
     /// ### Returns
-    /// If the bucket is full, return the top [Node] otherwise return [None].
+    /// If the bucket is full, return the top [Node] to ping, see [Kademlia::contact_checked], otherwise return [None].
     pub fn add_node (&mut self, node: &Node) -> Option<Node> {
         self.kbuckets.add(node)
     }
 
+    /// # contact_checked
+    /// Proxy for the [KBucket::contact_checked] function.
+    /// Keeps the node returned by [Kademlia::add_node] if it answered the ping, otherwise replaces it
+    pub fn contact_checked(&mut self, node: &Node, alive: bool) {
+        self.kbuckets.contact_checked(node, alive);
+    }
+
     /// # replace_node
     /// Proxy for the [KBucket::replace_node] function.
     /// Attempts to replace the top node with the one passed as argument.
@@ -149,9 +237,9 @@ impl Kademlia {
     /// It's typically used for broadcast purposes.
     ///
     /// #### Returns
-    /// Will return up to K * ID_LEN nodes (meaning all nodes in the kbucket). If none are found, return [None].
+    /// Will return up to K * MAX_BUCKETS nodes (meaning all nodes in the kbucket). If none are found, return [None].
     pub fn get_all_nodes(&self) -> Option<Vec<Node>> {
-        self.kbuckets.get_n_closest_nodes(self.node.id.clone(), K * ID_LEN)
+        self.kbuckets.get_n_closest_nodes(self.node.id.clone(), K * MAX_BUCKETS)
     }
 
     // This function will return K nodes based on the new distance
@@ -220,6 +308,77 @@ impl Kademlia {
         self.kbuckets.increment_lookups(identifier);
     }
 
+    /// # lookup_distance
+    /// Priority of a node in a lookup for `target`: the [log2](crate::kademlia::node::Distance::log2) of its full distance
+    /// to the target, so nodes of the same bucket are still ranked, weighted with the trust score of the node
+    /// like [KBucket::sort_by_new_distance].
+    ///
+    /// #### Returns
+    /// The lower, the sooner the node is asked
+    pub fn lookup_distance(&mut self, target: &Identifier, node: &Node) -> f64 {
+        let score = self.get_trust_score(node.id.clone()).get_score();
+        target.distance(&node.id).log2() * B + (1f64 - B) * (1f64 / score)
+    }
+
+    /// # mark_seen
+    /// Proxy for the [KBucket::mark_seen] function.
+    /// Records that the node answered us, so it is not one of the [stale nodes](Kademlia::stale_nodes)
+    pub fn mark_seen(&mut self, identifier: &Identifier) {
+        self.kbuckets.mark_seen(identifier, Instant::now());
+    }
+
+    /// # stale_nodes
+    /// Proxy for the [KBucket::stale_nodes] function.
+    ///
+    /// #### Returns
+    /// The nodes that did not answer us since `since`
+    pub fn stale_nodes(&self, since: Instant) -> Vec<Node> {
+        self.kbuckets.stale_nodes(since)
+    }
+
+    /// # buckets_to_refresh
+    /// Proxy for the [KBucket::buckets_to_refresh] function.
+    ///
+    /// #### Returns
+    /// A random [Identifier] in each bucket with no activity since `since`, to look up
+    pub fn buckets_to_refresh(&mut self, since: Instant) -> Vec<Identifier> {
+        self.kbuckets.buckets_to_refresh(since, Instant::now()).into_iter()
+            .map(|index| self.kbuckets.random_id_in_bucket(index))
+            .collect()
+    }
+
+    /// # snapshot
+    /// The routing table as JSON, with the [TrustScore] of every node, so it survives a restart.
+    /// Within each bucket the nodes keep their order, least recently contacted first
+    pub fn snapshot(&self) -> String {
+        let nodes: Vec<Value> = self.kbuckets.buckets.iter()
+            .flat_map(|bucket| bucket.map.iter())
+            .map(|(node, trust)| json!({"ip": node.ip, "port": node.port, "trust": trust.to_json()}))
+            .collect();
+        json!({"nodes": nodes}).to_string()
+    }
+
+    /// # parse_snapshot
+    /// #### Returns
+    /// The nodes saved by [Kademlia::snapshot] with their [TrustScore], or [None] if the snapshot is not valid
+    pub fn parse_snapshot(snapshot: &str) -> Option<Vec<(Node, TrustScore)>> {
+        let value: Value = serde_json::from_str(snapshot).ok()?;
+        value.get("nodes")?.as_array()?.iter()
+            .map(|n| {
+                let port = u32::try_from(n.get("port")?.as_u64()?).ok()?;
+                let node = Node::new(n.get("ip")?.as_str()?.to_string(), port)?;
+                Some((node, TrustScore::from_json(n.get("trust")?)?))
+            })
+            .collect()
+    }
+
+    /// # restore_node
+    /// Proxy for the [KBucket::restore] function.
+    /// Adds a node of a [snapshot](Kademlia::snapshot) back with the [TrustScore] it had
+    pub fn restore_node(&mut self, node: &Node, trust: TrustScore) -> bool {
+        self.kbuckets.restore(node, trust)
+    }
+
     pub fn get_trust_score(&mut self, identifier: Identifier) -> TrustScore {
         self.kbuckets.get_trust_score(&identifier).unwrap_or(TrustScore::new())
     }
@@ -235,9 +394,47 @@ impl Kademlia {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
+    use proptest::prelude::*;
+
     use crate::auxi;
     use crate::kademlia::kademlia::Kademlia;
-    use crate::kademlia::node::Node;
+    use crate::kademlia::node::{ID_LEN, Identifier, Node};
+    use crate::kademlia::record::{RECORD_EXPIRE, REPLICATE_INTERVAL, REPUBLISH_INTERVAL};
+
+    proptest! {
+        #[test]
+        fn test_is_closest_is_brute_force(own in any::<[u8; ID_LEN]>(),
+                                          key in any::<[u8; ID_LEN]>(),
+                                          ids in prop::collection::vec(any::<[u8; ID_LEN]>(), 0..20)) {
+            let own = Node { id: Identifier::new(own), ip: "127.0.0.1".to_string(), port: 0 };
+            let mut kademlia = Kademlia::new(own.clone());
+            // Fewer than K nodes, so no bucket is full and every node is kept
+            let nodes: Vec<Node> = ids.into_iter().enumerate()
+                .map(|(i, id)| Node { id: Identifier::new(id), ip: "127.0.0.1".to_string(), port: i as u32 + 1 })
+                .collect();
+            for node in &nodes {
+                kademlia.add_node(node);
+            }
+            let key = Identifier::new(key);
+            let closer = nodes.iter().any(|node| node.id.distance(&key) < own.id.distance(&key));
+            prop_assert_eq!(kademlia.is_closest(&key).is_some(), closer);
+        }
+
+        #[test]
+        fn test_lookup_distance_follows_distance(target in any::<[u8; ID_LEN]>(),
+                                                 a in any::<[u8; ID_LEN]>(),
+                                                 b in any::<[u8; ID_LEN]>()) {
+            let mut kademlia = Kademlia::new(Node::new("127.0.0.1".to_string(), 8888).unwrap());
+            let target = Identifier::new(target);
+            let (a, b) = (Node { id: Identifier::new(a), ip: "127.0.0.1".to_string(), port: 1 },
+                          Node { id: Identifier::new(b), ip: "127.0.0.1".to_string(), port: 2 });
+            let (closer, further) = if a.id.distance(&target) <= b.id.distance(&target) { (a, b) } else { (b, a) };
+            // Same trust, so the full distance decides, even within a bucket
+            prop_assert!(kademlia.lookup_distance(&target, &closer) <= kademlia.lookup_distance(&target, &further));
+        }
+    }
 
     #[test]
     fn test_get_key() {
@@ -264,6 +461,90 @@ mod tests {
         assert_eq!(kademlia.get_value(auxi::gen_id("Some Key".to_string())).is_none(), true)
     }
 
+    #[test]
+    fn test_record_lifecycle() {
+        let ip = "127.0.0.1".to_string();
+        let mut kademlia = Kademlia::new(Node::new(ip.clone(), 8888).unwrap());
+        let other = Node::new(ip.clone(), 8889).unwrap();
+        kademlia.add_node(&other);
+
+        let (ours, theirs) = (auxi::gen_id("Ours".to_string()), auxi::gen_id("Theirs".to_string()));
+        kademlia.publish_key(ours.clone(), "Published".to_string());
+        kademlia.store_record(theirs.clone(), "Stored".to_string(), Duration::from_secs(60));
+        let now = Instant::now();
+
+        // Nothing is due yet
+        assert!(kademlia.records_to_republish(now).is_empty());
+        assert!(kademlia.records_to_replicate(now).is_empty());
+
+        // After an hour both records go to the node that does not have them, but only once
+        let hour = now + REPLICATE_INTERVAL;
+        let due = kademlia.records_to_replicate(hour);
+        assert_eq!(due.len(), 1);
+        assert_eq!((&due[0].0, due[0].3.clone()), (&ours, vec![other.clone()]));
+        assert!(due[0].2 <= RECORD_EXPIRE);
+        kademlia.mark_replicated(&ours, other.id.clone());
+        assert!(kademlia.records_to_replicate(hour + REPLICATE_INTERVAL).is_empty());
+
+        // The record stored for someone else expired, ours is republished and lives on
+        assert_eq!(kademlia.expire_records(hour), 1);
+        assert!(kademlia.get_value(theirs).is_none());
+        let day = now + REPUBLISH_INTERVAL;
+        assert_eq!(kademlia.records_to_republish(day), vec![(ours.clone(), "Published".to_string())]);
+        assert_eq!(kademlia.expire_records(day + Duration::from_secs(1)), 0);
+        assert_eq!(kademlia.records_to_replicate(day + REPLICATE_INTERVAL).len(), 1);
+    }
+
+    #[test]
+    fn test_maintenance_pass() {
+        let ip = "127.0.0.1".to_string();
+        let mut kademlia = Kademlia::new(Node::new(ip.clone(), 8888).unwrap());
+        kademlia.add_node(&Node::new(ip.clone(), 8889).unwrap());
+        let key = auxi::gen_id("Ours".to_string());
+        kademlia.publish_key(key.clone(), "Published".to_string());
+        let now = Instant::now();
+        assert!(REPUBLISH_INTERVAL < RECORD_EXPIRE);
+
+        // Each pass republishes our record before anything expires, it is never dropped or missed
+        let mut republished = 0;
+        for pass in 1..=3 * RECORD_EXPIRE.as_secs() / REPLICATE_INTERVAL.as_secs() {
+            let at = now + REPLICATE_INTERVAL * pass as u32;
+            let due = kademlia.due_records(at);
+            assert_eq!(due.expired, 0);
+            republished += due.republish.len();
+            assert!(kademlia.get_value(key.clone()).is_some());
+        }
+        assert_eq!(republished, 3);
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let ip = "127.0.0.1".to_string();
+        let own = Node::new(ip.clone(), 8888).unwrap();
+        let mut kademlia = Kademlia::new(own.clone());
+        let nodes: Vec<Node> = (0..30).map(|port| Node::new(ip.clone(), 9000 + port).unwrap()).collect();
+        for node in &nodes {
+            kademlia.add_node(node);
+        }
+        // Some peers misbehaved, others answered lookups
+        for node in nodes.iter().step_by(3) {
+            kademlia.increment_interactions(node.id.clone());
+            kademlia.risk_penalty(node.id.clone());
+        }
+        for node in nodes.iter().step_by(4) {
+            kademlia.increment_lookups(node.id.clone());
+            kademlia.reputation_reward(node.id.clone());
+        }
+
+        let contacts = Kademlia::parse_snapshot(&kademlia.snapshot()).unwrap();
+        let mut restored = Kademlia::new(own);
+        for (node, trust) in contacts {
+            assert!(restored.restore_node(&node, trust));
+        }
+        assert_eq!(restored.get_all_trust_scores(), kademlia.get_all_trust_scores());
+        assert!(Kademlia::parse_snapshot("{\"nodes\": [{\"ip\": \"127.0.0.1\"}]}").is_none());
+    }
+
     #[test]
     fn test_get_node() {
         let ip = "127.0.0.1".to_string();
@@ -0,0 +1,151 @@
+#[doc(inline)]
+use std::collections::HashSet;
+
+use crate::kademlia::bucket::K;
+use crate::kademlia::node::{Identifier, Node};
+
+/// Nodes queried in parallel on each round of a lookup, `α` in the Kademlia paper
+pub const ALPHA: usize = 3;
+
+/// ## Lookup
+/// State of an iterative node lookup, as in the Kademlia paper: the [K] closest nodes heard of so far
+/// are queried, [ALPHA] at a time and closest first, and the nodes they answer with are merged in,
+/// until every one of the [K] closest nodes has either answered or failed.
+///
+/// The network calls are left to the caller, see [Peer::lookup_nodes](crate::p2p::peer::Peer::lookup_nodes)
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    target: Identifier,
+    own_id: Identifier,
+    /// every node heard of, closest to the target first
+    shortlist: Vec<Node>,
+    queried: HashSet<Identifier>,
+    responded: HashSet<Identifier>,
+    failed: HashSet<Identifier>,
+}
+
+impl Lookup {
+    /// # new
+    /// Starts a lookup of `target` from the nodes of our own routing table (`seeds`)
+    pub fn new(target: Identifier, own_id: Identifier, seeds: Vec<Node>) -> Self {
+        let mut lookup = Lookup {
+            target,
+            own_id,
+            shortlist: Vec::new(),
+            queried: HashSet::new(),
+            responded: HashSet::new(),
+            failed: HashSet::new(),
+        };
+        lookup.insert(seeds);
+        lookup
+    }
+
+    /// adds the nodes we did not know about, we are never part of the lookup
+    fn insert(&mut self, nodes: Vec<Node>) {
+        for node in nodes {
+            if node.id != self.own_id && !self.shortlist.iter().any(|n| n.id == node.id) {
+                self.shortlist.push(node);
+            }
+        }
+        let target = self.target.clone();
+        self.shortlist.sort_by_key(|n| n.id.distance(&target));
+    }
+
+    /// the [K] closest nodes that did not fail
+    fn candidates(&self) -> impl Iterator<Item = &Node> {
+        self.shortlist.iter().filter(|n| !self.failed.contains(&n.id)).take(K)
+    }
+
+    /// # next_batch
+    /// Up to `alpha` of the closest candidates that were not queried yet, they are marked as queried
+    ///
+    /// #### Returns
+    /// The nodes to query next, an empty [Vec] once the lookup is over
+    pub fn next_batch(&mut self, alpha: usize) -> Vec<Node> {
+        let batch: Vec<Node> = self.candidates()
+            .filter(|n| !self.queried.contains(&n.id))
+            .take(alpha)
+            .cloned()
+            .collect();
+        for node in &batch {
+            self.queried.insert(node.id.clone());
+        }
+        batch
+    }
+
+    /// # respond
+    /// `from` answered with the `nodes` it knows closest to the target
+    pub fn respond(&mut self, from: &Node, nodes: Vec<Node>) {
+        self.responded.insert(from.id.clone());
+        self.insert(nodes);
+    }
+
+    /// # fail
+    /// `node` did not answer, it is left out of the result
+    pub fn fail(&mut self, node: &Node) {
+        self.failed.insert(node.id.clone());
+    }
+
+    /// # is_done
+    /// True once every one of the [K] closest candidates was queried
+    pub fn is_done(&self) -> bool {
+        self.candidates().all(|n| self.queried.contains(&n.id))
+    }
+
+    /// # closest
+    /// #### Returns
+    /// Up to [K] nodes that answered, closest to the target first
+    pub fn closest(&self) -> Vec<Node> {
+        self.shortlist.iter().filter(|n| self.responded.contains(&n.id)).take(K).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::auxi;
+    use crate::kademlia::bucket::K;
+    use crate::kademlia::kademlia::Kademlia;
+    use crate::kademlia::lookup::{ALPHA, Lookup};
+    use crate::kademlia::node::{Identifier, Node};
+
+    #[test]
+    fn test_lookup_finds_k_closest() {
+        let nodes: Vec<Node> = (0..200).map(|port| Node::new("127.0.0.1".to_string(), 9000 + port).unwrap()).collect();
+        // Every node sees the whole network, but the full buckets only keep part of it
+        let mut tables: HashMap<Identifier, Kademlia> = HashMap::new();
+        for node in &nodes {
+            let mut kademlia = Kademlia::new(node.clone());
+            for other in &nodes {
+                kademlia.add_node(other);
+            }
+            tables.insert(node.id.clone(), kademlia);
+        }
+        // A few nodes are down
+        let down: Vec<Identifier> = nodes.iter().step_by(17).map(|n| n.id.clone()).collect();
+
+        let (own, target) = (&nodes[1], auxi::gen_id("Some Key".to_string()));
+        let seeds = tables[&own.id].get_k_nearest_to_node(target.clone()).unwrap();
+        let mut lookup = Lookup::new(target.clone(), own.id.clone(), seeds);
+        while !lookup.is_done() {
+            for node in lookup.next_batch(ALPHA) {
+                if down.contains(&node.id) {
+                    lookup.fail(&node);
+                } else {
+                    let answer = tables[&node.id].get_k_nearest_to_node(target.clone()).unwrap();
+                    lookup.respond(&node, answer);
+                }
+            }
+        }
+
+        let mut expected: Vec<Node> = nodes.iter()
+            .filter(|n| n.id != own.id && !down.contains(&n.id))
+            .cloned()
+            .collect();
+        expected.sort_by_key(|n| n.id.distance(&target));
+        expected.truncate(K);
+        assert_eq!(lookup.closest(), expected);
+        assert!(lookup.next_batch(ALPHA).is_empty());
+    }
+}
@@ -2,6 +2,8 @@
 pub mod kademlia;
 pub mod node;
 pub mod k_buckets;
+pub mod lookup;
 
 pub mod bucket;
+pub mod record;
 mod trust_score;
\ No newline at end of file
@@ -4,9 +4,13 @@ use std::net::IpAddr;
 
 use crate::auxi;
 
-pub const ID_LEN: usize = 256; // Size in bits of SHA3_256 output (This is the hashing algorithm defined in Kademlia's documentation)
+pub const ID_LEN: usize = 32; // Size in bytes of SHA3_256 output (This is the hashing algorithm defined in Kademlia's documentation)
+pub const ID_BITS: usize = ID_LEN * 8;
 /// Identifier Type
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// The 256 bits are stored big-endian, so comparing two Identifiers (or two [Distance]s)
+/// compares the numbers they represent
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct  Identifier(pub [u8; ID_LEN]); // hash of the ip:port of the node (can be changed later on to use the private certificates of the node)
 
 
@@ -16,12 +20,74 @@ impl Identifier {
         Self(id)
     }
 
+    /// # from_bytes
+    /// Builds an Identifier from the bytes of a packet
+    ///
+    /// #### Returns
+    /// The Identifier, or [None] if there are not exactly [ID_LEN] bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    /// # distance
+    /// XOR distance between two Identifiers
+    pub fn distance(&self, other: &Identifier) -> Distance {
+        let mut res = [0; ID_LEN];
+        for (i, byte) in res.iter_mut().enumerate() {
+            *byte = self.0[i] ^ other.0[i];
+        }
+        Distance(res)
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// ## Distance
+/// XOR of two [Identifier]s, a 256-bit number (big-endian) ordered like one
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Distance(pub [u8; ID_LEN]);
+
+impl Distance {
+    /// number of leading zero bits, [ID_BITS] for the distance of an Identifier to itself
+    pub fn leading_zeros(&self) -> usize {
+        let mut count = 0;
+        for byte in self.0 {
+            if byte != 0 {
+                return count + byte.leading_zeros() as usize;
+            }
+            count += 8;
+        }
+        count
+    }
+
+    /// # bucket_index
+    /// Index of the bucket a node at this distance belongs to: the position of the highest bit set,
+    /// 0 for the closest nodes up to ID_BITS - 1 for the furthest half of the network
+    pub fn bucket_index(&self) -> usize {
+        (ID_BITS - self.leading_zeros()).saturating_sub(1)
+    }
+
+    /// base 2 logarithm of the distance (0 for distances below 2), rounded but ordered like the distance itself.
+    /// Its integer part is the [bucket index](Distance::bucket_index)
+    pub fn log2(&self) -> f64 {
+        self.0.iter()
+            .fold(0f64, |acc, byte| acc * 256f64 + *byte as f64)
+            .log2()
+            .max(0f64)
+    }
 }
 
 /// ## Node
 #[derive(Debug, Clone, Eq, Hash)]
 pub struct Node {
-    pub id: Identifier, // Assuming Identifier is represented as a fixed-size array of ID_LEN bytes
+    pub id: Identifier, // 256 bits, see [Identifier]
     pub ip: String,
     pub port: u32
 }
@@ -30,9 +96,8 @@ pub struct Node {
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
-        let id_str = auxi::vec_u8_to_string(self.clone().id);
         // Customize the formatting of Node here
-        write!(f, "Node {{ id:{:?} (SHA3_256: {}), ip: {}, port: {} }}", id_str, auxi::convert_node_id_to_string(&self.id), self.ip, self.port)
+        write!(f, "Node {{ id:{} (SHA3_256: {}), ip: {}, port: {} }}", self.id, auxi::convert_node_id_to_string(&self.id), self.ip, self.port)
     }
 }
 
@@ -72,3 +137,43 @@ impl Node {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::kademlia::node::{Distance, ID_BITS, ID_LEN, Identifier};
+
+    #[test]
+    fn test_distance() {
+        let zero = Identifier::new([0; ID_LEN]);
+        let mut one = [0; ID_LEN];
+        one[ID_LEN - 1] = 1;
+        let one = Identifier::new(one);
+        let mut top = [0; ID_LEN];
+        top[0] = 0x80;
+        let top = Identifier::new(top);
+
+        // XOR is symmetric and an Identifier is at distance 0 of itself
+        assert_eq!(one.distance(&top), top.distance(&one));
+        assert_eq!(one.distance(&one), Distance([0; ID_LEN]));
+        assert_eq!(one.distance(&one).leading_zeros(), ID_BITS);
+        assert_eq!(zero.distance(&one).bucket_index(), 0);
+        assert_eq!(zero.distance(&top).bucket_index(), ID_BITS - 1);
+
+        // Distances are fully ordered, not only by their leading zeros
+        let mut three = [0; ID_LEN];
+        three[ID_LEN - 1] = 3;
+        let mut two = [0; ID_LEN];
+        two[ID_LEN - 1] = 2;
+        let (two, three) = (Identifier::new(two), Identifier::new(three));
+        assert_eq!(zero.distance(&two).bucket_index(), zero.distance(&three).bucket_index());
+        assert!(zero.distance(&two) < zero.distance(&three));
+        assert!(zero.distance(&two).log2() < zero.distance(&three).log2());
+        assert_eq!(zero.distance(&three).log2().floor() as usize, zero.distance(&three).bucket_index());
+        assert_eq!(one.distance(&one).log2(), 0f64);
+        assert!(zero.distance(&three) < zero.distance(&top));
+        assert!(two < three);
+
+        assert_eq!(Identifier::from_bytes(&[7; ID_LEN]), Some(Identifier::new([7; ID_LEN])));
+        assert_eq!(Identifier::from_bytes(&[7; ID_BITS]), None);
+    }
+}
@@ -0,0 +1,73 @@
+#[doc(inline)]
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::kademlia::node::{Identifier, Node};
+
+/// Lifetime of a record from the moment it was (re)published, `tExpire` in the Kademlia paper
+pub const RECORD_EXPIRE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often the original publisher stores its records again, `tRepublish` in the Kademlia paper.
+/// It is shorter than [RECORD_EXPIRE] so the copies on other nodes are refreshed before they expire
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(23 * 60 * 60);
+/// How often a node sends the records it holds to the closest nodes it knows, `tReplicate` in the Kademlia paper
+pub const REPLICATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// ## Record
+/// A value stored in the DHT, with the times it expires and is sent to other nodes again
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub value: String,
+    /// the record is no longer served (and is dropped) after this
+    pub expires_at: Instant,
+    /// only set on the node that published the record
+    pub republish_at: Option<Instant>,
+    pub replicate_at: Instant,
+    /// nodes that already got the record since it was last (re)published
+    pub replicated_to: HashSet<Identifier>,
+}
+
+impl Record {
+    /// # new
+    /// Record stored for another node, it lives for `lifetime`
+    pub fn new(value: String, lifetime: Duration, now: Instant) -> Self {
+        Record {
+            value,
+            expires_at: now + lifetime.min(RECORD_EXPIRE),
+            republish_at: None,
+            replicate_at: now + REPLICATE_INTERVAL,
+            replicated_to: HashSet::new(),
+        }
+    }
+
+    /// # published
+    /// Record of the node that published it, which republishes it every [REPUBLISH_INTERVAL]
+    pub fn published(value: String, now: Instant) -> Self {
+        Record {
+            republish_at: Some(now + REPUBLISH_INTERVAL),
+            ..Record::new(value, RECORD_EXPIRE, now)
+        }
+    }
+
+    /// records we published never expire, they are republished instead
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.republish_at.is_none() && now >= self.expires_at
+    }
+
+    /// # lifetime
+    /// Time the record has left, sent along with it to other nodes
+    pub fn lifetime(&self, now: Instant) -> Duration {
+        self.expires_at.saturating_duration_since(now)
+    }
+}
+
+/// ## DueRecords
+/// What a [maintenance pass](crate::kademlia::kademlia::Kademlia::due_records) over the records found to do
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DueRecords {
+    /// records we published, to store in the network again
+    pub republish: Vec<(Identifier, String)>,
+    /// number of records dropped
+    pub expired: usize,
+    /// records to send to the nodes that do not have them, with the lifetime they have left
+    pub replicate: Vec<(Identifier, String, Duration, Vec<Node>)>,
+}
@@ -1,3 +1,5 @@
+use serde_json::{json, Value};
+
 const WEIGHT_REPUTATION: f64 = 0.40;
 const WEIGHT_RISK: f64 = 0.60;
 
@@ -68,6 +70,33 @@ impl TrustScore {
         }
     }
 
+    /// # to_json
+    /// The counters saved with the routing table snapshot
+    pub fn to_json(&self) -> Value {
+        json!({
+            "reputation": self.reputation,
+            "risk": self.risk,
+            "score": self.score,
+            "total_interactions": self.total_interactions,
+            "total_lookups": self.total_lookups,
+            "bad_interactions": self.bad_interactions,
+        })
+    }
+
+    /// # from_json
+    /// #### Returns
+    /// The [TrustScore] saved by [TrustScore::to_json], or [None] if a counter is missing
+    pub fn from_json(value: &Value) -> Option<TrustScore> {
+        Some(TrustScore {
+            reputation: value.get("reputation")?.as_f64()?,
+            risk: value.get("risk")?.as_f64()?,
+            score: value.get("score")?.as_f64()?,
+            total_interactions: value.get("total_interactions")?.as_i64()?,
+            total_lookups: value.get("total_lookups")?.as_i64()?,
+            bad_interactions: value.get("bad_interactions")?.as_i64()?,
+        })
+    }
+
     pub fn get_score(&mut self) -> f64 {
         self.update_score();
         //println!("Values: Risk: {}, Reputation: {}, Total Interactions: {}, Bad Interactions: {}, Total Lookups: {}", self.risk, self.reputation, self.total_interactions, self.bad_interactions, self.total_lookups);
@@ -0,0 +1,571 @@
+#[doc(inline)]
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::ledger::block::Block;
+use crate::marco::amount::{Amount, UNITS_PER_COIN};
+use crate::ledger::winner_strategy::{self, WinnerStrategy};
+use crate::marco::auction::{Auction, AuctionType};
+use crate::marco::bid::Bid;
+use crate::marco::marco::{Data, Marco};
+use crate::marco::payload::Effect;
+use crate::marco::sealed_bid::BidCommit;
+use crate::marco::winner::Winner;
+
+/// Smallest amount a bid has to add to the highest bid (0.01 coins)
+pub const MIN_INCREMENT: Amount = Amount::from_units(UNITS_PER_COIN / 100);
+
+/// ## AuctionState
+/// What the chain says about one auction
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionState {
+    /// hash of the Marco that created the auction
+    pub id: String,
+    pub auction: Auction,
+    /// index of the block that created the auction, where the price of a Dutch auction starts
+    pub opened_at: u64,
+    /// valid bids, in the order they were included in the chain;
+    /// for sealed auctions these are the revealed bids
+    pub bids: Vec<Bid>,
+    /// commits of sealed auctions, in chain order
+    pub commits: Vec<BidCommit>,
+    pub winner: Option<Winner>,
+}
+
+impl AuctionState {
+    /// returns true if a bid included at `height` with `timestamp` would still be accepted
+    pub fn is_open(&self, height: u64, timestamp: u64) -> bool {
+        self.winner.is_none() && !self.bought() && !self.auction.end.has_passed(height, timestamp)
+    }
+
+    /// returns true once a bid ended the auction: the first bid of a Dutch auction,
+    /// or a bid that reached the buy-now price
+    pub fn bought(&self) -> bool {
+        if let AuctionType::Dutch(_) = self.auction.auction_type {
+            return !self.bids.is_empty();
+        }
+        match self.auction.terms.buy_now {
+            None => false,
+            Some(price) => self.highest_bid().is_some_and(|h| h.amount >= price),
+        }
+    }
+
+    /// decides the winner and the price they pay
+    pub fn strategy(&self) -> &'static dyn WinnerStrategy {
+        winner_strategy::strategy(&self.auction.auction_type)
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.auction.sealed.is_some()
+    }
+
+    /// returns true if sealed bids can be revealed at the given position
+    pub fn in_reveal_phase(&self, height: u64, timestamp: u64) -> bool {
+        match self.auction.sealed {
+            None => false,
+            Some(terms) => self.winner.is_none()
+                && self.auction.end.has_passed(height, timestamp)
+                && !terms.reveal_end.has_passed(height, timestamp),
+        }
+    }
+
+    /// returns true once no more bids (or reveals) are accepted
+    pub fn bidding_over(&self, height: u64, timestamp: u64) -> bool {
+        match self.auction.sealed {
+            None => self.bought() || self.auction.end.has_passed(height, timestamp),
+            Some(terms) => terms.reveal_end.has_passed(height, timestamp),
+        }
+    }
+
+    /// returns true if bidding is over and nobody published the winner yet
+    pub fn awaits_winner(&self, height: u64, timestamp: u64) -> bool {
+        self.winner.is_none() && self.bidding_over(height, timestamp)
+    }
+
+    /// commits that were not revealed (yet), their deposit is forfeited once bidding is over
+    pub fn unrevealed_commits(&self) -> Vec<&BidCommit> {
+        self.commits.iter()
+            .filter(|c| !self.bids.iter().any(|b| b.buyer_id == c.buyer_id))
+            .collect()
+    }
+
+    /// highest bid that meets the reserve price, ties go to the bid that reached the chain first
+    pub fn highest_bid(&self) -> Option<&Bid> {
+        let reserve = self.auction.terms.reserve.unwrap_or(Amount::ZERO);
+        let mut highest: Option<&Bid> = None;
+        for b in self.bids.iter().filter(|b| b.amount >= reserve) {
+            if highest.is_none_or(|h| b.amount > h.amount) {
+                highest = Some(b);
+            }
+        }
+        highest
+    }
+
+    /// lowest amount a bid included at `height` can have: the reserve price (or the increment) for the first bid,
+    /// then the highest bid plus the increment. Dutch auctions take their current price
+    pub fn minimum_bid(&self, height: u64) -> Amount {
+        let terms = self.auction.terms;
+        if let AuctionType::Dutch(dutch) = self.auction.auction_type {
+            let price = dutch.price_after(height.saturating_sub(self.opened_at));
+            return terms.reserve.map_or(price, |reserve| reserve.max(price));
+        }
+        let increment = terms.increment.unwrap_or(MIN_INCREMENT);
+        match self.highest_bid() {
+            None => terms.reserve.map_or(increment, |reserve| reserve.max(increment)),
+            Some(b) => b.amount.checked_add(increment).unwrap_or(Amount::MAX),
+        }
+    }
+
+    /// checks the bid against the auction, for a bid included at `height`
+    pub fn accepts_bid(&self, b: &Bid, height: u64) -> bool {
+        if self.is_sealed() {
+            debug!("DEBUG AUCTIONS::ACCEPTS_BID => Auction {} only takes sealed bids", self.id);
+            return false;
+        }
+        if b.buyer_id == self.auction.seller_id || b.seller_id != self.auction.seller_id {
+            debug!("DEBUG AUCTIONS::ACCEPTS_BID => {} cannot bid on an auction of {}", b.buyer_id, self.auction.seller_id);
+            return false;
+        }
+        if b.amount < self.minimum_bid(height) {
+            debug!("DEBUG AUCTIONS::ACCEPTS_BID => Bid of {} is below {}", b.amount, self.minimum_bid(height));
+            return false;
+        }
+        true
+    }
+
+    /// checks a sealed bid against the auction, regardless of its position in the chain
+    pub fn accepts_commit(&self, c: &BidCommit) -> bool {
+        match self.auction.sealed {
+            None => false,
+            Some(terms) => c.buyer_id != self.auction.seller_id
+                && c.seller_id == self.auction.seller_id
+                && c.deposit >= terms.deposit
+                && !self.commits.iter().any(|other| other.buyer_id == c.buyer_id),
+        }
+    }
+
+    /// checks the Winner names the highest valid bid
+    pub fn accepts_winner(&self, w: &Winner) -> bool {
+        let expected = self.derive_winner(w.closed_by.clone());
+        w.from == expected.from && w.to == expected.to && w.amount == expected.amount
+    }
+
+    /// balance changes that close the auction with the derived winner,
+    /// the winner pays the price of the [WinnerStrategy] and gets back the rest of its bid
+    pub fn settlement(&self) -> Vec<Effect> {
+        let seller = &self.auction.seller_id;
+        let mut effects = Vec::new();
+        let winner = self.strategy().winner(self);
+        match (&winner, self.highest_bid()) {
+            (Some((buyer, price)), Some(h)) => {
+                effects.push(Effect::Spend { account: buyer.clone(), amount: *price });
+                effects.push(Effect::Credit { account: seller.clone(), amount: *price });
+                if let Some(change) = h.amount.checked_sub(*price).filter(|c| !c.is_zero()) {
+                    effects.push(Effect::Release { account: buyer.clone(), amount: change });
+                }
+                effects.push(Effect::Spend { account: seller.clone(), amount: self.auction.amount });
+                effects.push(Effect::Credit { account: buyer.clone(), amount: self.auction.amount });
+            }
+            _ => effects.push(Effect::Release { account: seller.clone(), amount: self.auction.amount }),
+        }
+        if self.is_sealed() {
+            // Every revealed bid stays locked until the end, only the open auctions release outbid bids
+            let winner = winner.map(|(buyer, _)| buyer);
+            for b in self.bids.iter().filter(|b| Some(&b.buyer_id) != winner.as_ref()) {
+                effects.push(Effect::Release { account: b.buyer_id.clone(), amount: b.amount });
+            }
+            for c in self.unrevealed_commits() {
+                effects.push(Effect::Spend { account: c.buyer_id.clone(), amount: c.deposit });
+                effects.push(Effect::Credit { account: seller.clone(), amount: c.deposit });
+            }
+        }
+        effects
+    }
+
+    /// the Winner every node derives from the chain,
+    /// `to` is empty if the auction got no bids
+    ///
+    /// `closed_by` is the node publishing it, empty for the seller
+    pub fn derive_winner(&self, closed_by: String) -> Winner {
+        let (buyer, amount) = self.strategy().winner(self)
+            .unwrap_or_else(|| ("".to_string(), Default::default()));
+        Winner::closed_by(self.id.clone(), amount, self.auction.seller_id.clone(), buyer, closed_by)
+    }
+}
+
+/// ## Auctions
+/// State of every auction, built by replaying Marcos in chain order.
+///
+/// The position (`height` and `timestamp`) of a Marco is the index and
+/// timestamp of the block that includes it
+#[derive(Debug, Clone, Default)]
+pub struct Auctions {
+    auctions: HashMap<String, AuctionState>,
+}
+
+impl Auctions {
+    pub fn new() -> Self {
+        Auctions { auctions: HashMap::new() }
+    }
+
+    /// replays the blocks in order, Marcos that break the rules are ignored
+    pub fn from_blocks<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut auctions = Self::new();
+        for b in blocks {
+            auctions.apply_block(b);
+        }
+        auctions
+    }
+
+    pub fn apply_block(&mut self, b: &Block) {
+        for m in &b.transactions {
+            self.apply(m, b.index as u64, b.timestamp);
+        }
+    }
+
+    pub fn get(&self, id: &String) -> Option<&AuctionState> {
+        self.auctions.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AuctionState> {
+        self.auctions.values()
+    }
+
+    /// auctions whose end condition passed at the given position without a winner
+    pub fn awaiting_winner(&self, height: u64, timestamp: u64) -> Vec<&AuctionState> {
+        let mut ended: Vec<&AuctionState> = self.auctions.values()
+            .filter(|a| a.awaits_winner(height, timestamp))
+            .collect();
+        ended.sort_by(|a, b| a.id.cmp(&b.id));
+        ended
+    }
+
+    /// checks if the Marco can be included at the given position
+    ///
+    /// - auctions cannot be created already ended
+    /// - bids need an auction that exists and is still open, cannot come from the seller,
+    ///   must reach the reserve price and beat the highest bid by the increment of the auction
+    ///   ([MIN_INCREMENT] by default); a bid that reaches the buy-now price ends the auction
+    /// - sealed auctions take one commit per buyer, with at least the deposit of the auction,
+    ///   and each commit can be revealed once, after the auction ends and before the reveal phase ends
+    /// - only the seller can close an open auction early, anyone can once bidding is over,
+    ///   an auction has a single winner and it must be the highest valid (or revealed) bid
+    pub fn check(&self, m: &Marco, height: u64, timestamp: u64) -> bool {
+        match &m.data {
+            Data::CreateAuction(a) => {
+                !self.auctions.contains_key(&m.get_hash()) && !a.end.has_passed(height, timestamp)
+            }
+            Data::Bid(b) => {
+                match self.auctions.get(&b.auction_id) {
+                    None => {
+                        debug!("DEBUG AUCTIONS::CHECK => Bid for unknown auction {}", b.auction_id);
+                        false
+                    }
+                    Some(state) => state.is_open(height, timestamp) && state.accepts_bid(b, height),
+                }
+            }
+            Data::Winner(w) => {
+                match self.auctions.get(&w.auction) {
+                    None => false,
+                    Some(state) => {
+                        let early_close = w.closed_by.is_empty() && !state.is_sealed();
+                        state.winner.is_none()
+                            && (early_close || state.bidding_over(height, timestamp))
+                            && state.accepts_winner(w)
+                    }
+                }
+            }
+            Data::BidCommit(c) => {
+                match self.auctions.get(&c.auction_id) {
+                    None => false,
+                    Some(state) => state.is_open(height, timestamp) && state.accepts_commit(c),
+                }
+            }
+            Data::BidReveal(r) => {
+                match self.auctions.get(&r.auction_id) {
+                    None => false,
+                    Some(state) => {
+                        state.in_reveal_phase(height, timestamp)
+                            && !state.bids.iter().any(|b| b.buyer_id == r.buyer_id)
+                            && state.commits.iter().any(|c| c.is_opened_by(r))
+                    }
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// balance changes needed to include the Marco, computed before it is applied.
+    /// Expects a Marco accepted by [Auctions::check]
+    ///
+    /// - auctions lock the auctioned coins of the seller
+    /// - bids lock their amount and release the bid they outbid
+    /// - commits lock the deposit, reveals swap the deposit for the bid amount
+    /// - the Winner settles: the winning bid goes to the seller, the auctioned coins to the buyer,
+    ///   the other revealed bids are released and unrevealed deposits go to the seller
+    pub fn escrow(&self, m: &Marco) -> Vec<Effect> {
+        match &m.data {
+            Data::CreateAuction(a) => {
+                vec![Effect::Lock { account: a.seller_id.clone(), amount: a.amount }]
+            }
+            Data::Bid(b) => {
+                let mut effects = Vec::new();
+                if let Some(h) = self.auctions.get(&b.auction_id).and_then(|s| s.highest_bid()) {
+                    effects.push(Effect::Release { account: h.buyer_id.clone(), amount: h.amount });
+                }
+                effects.push(Effect::Lock { account: b.buyer_id.clone(), amount: b.amount });
+                effects
+            }
+            Data::BidCommit(c) => {
+                vec![Effect::Lock { account: c.buyer_id.clone(), amount: c.deposit }]
+            }
+            Data::BidReveal(r) => {
+                let deposit = self.auctions.get(&r.auction_id)
+                    .and_then(|s| s.commits.iter().find(|c| c.buyer_id == r.buyer_id))
+                    .map(|c| c.deposit)
+                    .unwrap_or_default();
+                vec![
+                    Effect::Release { account: r.buyer_id.clone(), amount: deposit },
+                    Effect::Lock { account: r.buyer_id.clone(), amount: r.amount },
+                ]
+            }
+            Data::Winner(w) => {
+                match self.auctions.get(&w.auction) {
+                    None => Vec::new(),
+                    Some(state) => state.settlement(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// records the Marco if [Auctions::check] accepts it
+    ///
+    /// **outputs:**
+    /// true if the Marco was accepted
+    pub fn apply(&mut self, m: &Marco, height: u64, timestamp: u64) -> bool {
+        if !self.check(m, height, timestamp) {
+            return false;
+        }
+        match &m.data {
+            Data::CreateAuction(a) => {
+                self.auctions.insert(m.get_hash(), AuctionState {
+                    id: m.get_hash(),
+                    auction: a.clone(),
+                    opened_at: height,
+                    bids: Vec::new(),
+                    commits: Vec::new(),
+                    winner: None,
+                });
+            }
+            Data::Bid(b) => {
+                if let Some(state) = self.auctions.get_mut(&b.auction_id) {
+                    state.bids.push(b.clone());
+                }
+            }
+            Data::Winner(w) => {
+                if let Some(state) = self.auctions.get_mut(&w.auction) {
+                    state.winner = Some(w.clone());
+                }
+            }
+            Data::BidCommit(c) => {
+                if let Some(state) = self.auctions.get_mut(&c.auction_id) {
+                    state.commits.push(c.clone());
+                }
+            }
+            Data::BidReveal(r) => {
+                if let Some(state) = self.auctions.get_mut(&r.auction_id) {
+                    let seller_id = state.auction.seller_id.clone();
+                    state.bids.push(Bid::new(r.auction_id.clone(), r.buyer_id.clone(), seller_id, r.amount));
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::auctions::*;
+    use crate::marco::amount::Amount;
+    use crate::marco::auction::{DutchTerms, EndCondition, PriceTerms, SealedTerms};
+    use crate::marco::payload::registry;
+    use crate::marco::sealed_bid::BidReveal;
+
+    fn create(end: EndCondition) -> Marco {
+        Marco::from_auction(Auction::new("seller".to_string(), Amount::from_units(10), end), 0)
+    }
+
+    fn bid(auction: &Marco, buyer: &str, units: u64) -> Marco {
+        Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(), "seller".to_string(), Amount::from_units(units)), units)
+    }
+
+    #[test]
+    fn test_block_height_end() {
+        let mut auctions = Auctions::new();
+        let a = create(EndCondition::BlockHeight(5));
+        assert!(auctions.apply(&a, 1, 0));
+        assert!(auctions.apply(&bid(&a, "alice", 20 * UNITS_PER_COIN), 2, 0));
+        assert!(auctions.apply(&bid(&a, "bob", 30 * UNITS_PER_COIN), 5, 0));
+        // Bids after the last block are too late
+        assert!(!auctions.apply(&bid(&a, "dave", 100 * UNITS_PER_COIN), 6, 0));
+
+        let state = auctions.get(&a.get_hash()).unwrap();
+        assert!(state.awaits_winner(6, 0));
+        let winner = state.derive_winner("someone".to_string());
+        assert_eq!(winner.to, "bob");
+        assert_eq!(winner.amount, Amount::from_units(30 * UNITS_PER_COIN));
+        assert_eq!(winner.from, "seller");
+    }
+
+    #[test]
+    fn test_winner_publication() {
+        let mut auctions = Auctions::new();
+        let a = create(EndCondition::Timestamp(1000));
+        assert!(auctions.apply(&a, 1, 10));
+        assert!(!auctions.apply(&bid(&a, "alice", 20 * UNITS_PER_COIN), 2, 1000));
+        assert!(auctions.apply(&bid(&a, "alice", 20 * UNITS_PER_COIN), 2, 999));
+
+        let state = auctions.get(&a.get_hash()).unwrap().clone();
+        let early = Marco::from_winner(state.derive_winner("someone".to_string()), 0);
+        assert!(!auctions.check(&early, 2, 999));
+        // The seller can close early, anyone can once it ended
+        assert!(auctions.check(&Marco::from_winner(state.derive_winner("".to_string()), 0), 2, 999));
+        assert!(auctions.apply(&early, 2, 1000));
+        assert_eq!(auctions.get(&a.get_hash()).unwrap().winner.as_ref().unwrap().to, "alice");
+
+        let second = Marco::from_winner(state.derive_winner("other".to_string()), 0);
+        assert!(!auctions.apply(&second, 3, 2000));
+        assert!(auctions.awaiting_winner(3, 2000).is_empty());
+    }
+
+    #[test]
+    fn test_auction_rules() {
+        let mut auctions = Auctions::new();
+        let a = create(EndCondition::BlockHeight(10));
+        assert!(!auctions.apply(&bid(&a, "alice", 20), 1, 0));
+        assert!(auctions.apply(&a, 1, 0));
+
+        // The seller cannot bid on its own auction
+        assert!(!auctions.apply(&bid(&a, "seller", 20), 2, 0));
+        assert!(auctions.apply(&bid(&a, "alice", MIN_INCREMENT.units()), 2, 0));
+        // Bids must beat the highest by the minimum increment
+        assert!(!auctions.apply(&bid(&a, "bob", MIN_INCREMENT.units() * 2 - 1), 2, 0));
+        assert!(auctions.apply(&bid(&a, "bob", MIN_INCREMENT.units() * 2), 2, 0));
+
+        let state = auctions.get(&a.get_hash()).unwrap().clone();
+        let mut wrong = state.derive_winner("".to_string());
+        wrong.to = "alice".to_string();
+        assert!(!auctions.apply(&Marco::from_winner(wrong, 0), 3, 0));
+        let mut cheap = state.derive_winner("".to_string());
+        cheap.amount = MIN_INCREMENT;
+        assert!(!auctions.apply(&Marco::from_winner(cheap, 0), 3, 0));
+        assert!(auctions.apply(&Marco::from_winner(state.derive_winner("".to_string()), 0), 3, 0));
+
+        // Closed auctions take no more bids
+        assert!(!auctions.apply(&bid(&a, "carol", 1_000 * UNITS_PER_COIN), 4, 0));
+    }
+
+    #[test]
+    fn test_dutch_auction() {
+        let mut auctions = Auctions::new();
+        let dutch = DutchTerms { start_price: Amount::from_units(100), decrement: Amount::from_units(30), floor: Amount::from_units(20) };
+        let a = Marco::from_auction(Auction::new("seller".to_string(), Amount::from_units(10), EndCondition::BlockHeight(10))
+            .with_type(AuctionType::Dutch(dutch)), 0);
+        assert!(auctions.apply(&a, 2, 0));
+
+        // The price drops every block, down to the floor
+        let state = auctions.get(&a.get_hash()).unwrap().clone();
+        assert_eq!(state.minimum_bid(2), Amount::from_units(100));
+        assert_eq!(state.minimum_bid(4), Amount::from_units(40));
+        assert_eq!(state.minimum_bid(9), Amount::from_units(20));
+        assert!(!auctions.apply(&bid(&a, "alice", 69), 3, 0));
+        assert!(auctions.apply(&bid(&a, "alice", 70), 3, 0));
+
+        // The first bid wins, even against a higher one
+        assert!(!auctions.apply(&bid(&a, "bob", 90), 3, 0));
+        let state = auctions.get(&a.get_hash()).unwrap();
+        assert!(state.awaits_winner(3, 0));
+        assert_eq!(state.derive_winner("".to_string()).amount, Amount::from_units(70));
+    }
+
+    #[test]
+    fn test_price_terms() {
+        let mut auctions = Auctions::new();
+        let terms = PriceTerms {
+            reserve: Some(Amount::from_units(100)),
+            increment: Some(Amount::from_units(50)),
+            buy_now: Some(Amount::from_units(300)),
+        };
+        let a = Marco::from_auction(Auction::new("seller".to_string(), Amount::from_units(10), EndCondition::BlockHeight(10)).with_terms(terms), 0);
+        assert!(auctions.apply(&a, 1, 0));
+
+        assert!(!auctions.apply(&bid(&a, "alice", 99), 2, 0));
+        assert!(auctions.apply(&bid(&a, "alice", 100), 2, 0));
+        assert!(!auctions.apply(&bid(&a, "bob", 149), 2, 0));
+        assert!(auctions.apply(&bid(&a, "bob", 150), 2, 0));
+        assert!(!auctions.get(&a.get_hash()).unwrap().bought());
+
+        // Reaching the buy-now price ends the auction, anyone can publish the Winner right away
+        assert!(auctions.apply(&bid(&a, "carol", 300), 2, 0));
+        let state = auctions.get(&a.get_hash()).unwrap().clone();
+        assert!(state.bought() && state.awaits_winner(2, 0));
+        assert!(!auctions.apply(&bid(&a, "dave", 400), 2, 0));
+        let winner = state.derive_winner("carol".to_string());
+        assert_eq!((winner.to.as_str(), winner.amount), ("carol", Amount::from_units(300)));
+        assert!(auctions.apply(&Marco::from_winner(winner, 0), 2, 0));
+
+        // Buy-now is only for open auctions, and cannot be below the reserve
+        let sealed = SealedTerms { reveal_end: EndCondition::BlockHeight(20), deposit: Amount::from_units(1) };
+        assert!(!terms.is_valid(true));
+        assert!(!PriceTerms { reserve: Some(Amount::from_units(400)), ..terms }.is_valid(false));
+        assert!(PriceTerms { buy_now: None, ..terms }.is_valid(true));
+        let sealed_buy_now = Auction::sealed("seller".to_string(), Amount::from_units(10), EndCondition::BlockHeight(10), sealed).with_terms(terms);
+        assert!(!registry().validate(&Data::CreateAuction(sealed_buy_now)));
+    }
+
+    #[test]
+    fn test_sealed_bids() {
+        let mut auctions = Auctions::new();
+        let deposit = Amount::from_units(UNITS_PER_COIN);
+        let terms = SealedTerms { reveal_end: EndCondition::BlockHeight(10), deposit };
+        let a = Marco::from_auction(Auction::sealed("seller".to_string(), Amount::from_units(10), EndCondition::BlockHeight(5), terms), 0);
+        assert!(auctions.apply(&a, 1, 0));
+
+        let salt = "salt".to_string();
+        let commit = |buyer: &str, coins: u64, deposit: Amount| Marco::from_bid_commit(BidCommit::new(a.get_hash(),
+            buyer.to_string(), "seller".to_string(), Amount::from_units(coins * UNITS_PER_COIN), &salt, deposit), coins);
+        let reveal = |buyer: &str, coins: u64| Marco::from_bid_reveal(BidReveal::new(a.get_hash(),
+            buyer.to_string(), Amount::from_units(coins * UNITS_PER_COIN), salt.clone()), coins);
+
+        // Plain bids, small deposits and late commits are rejected
+        assert!(!auctions.apply(&bid(&a, "alice", 20 * UNITS_PER_COIN), 2, 0));
+        assert!(!auctions.apply(&commit("alice", 20, Amount::ZERO), 2, 0));
+        assert!(auctions.apply(&commit("alice", 20, deposit), 2, 0));
+        assert!(!auctions.apply(&commit("alice", 25, deposit), 3, 0));
+        assert!(auctions.apply(&commit("bob", 30, deposit), 3, 0));
+        assert!(auctions.apply(&commit("carol", 40, deposit), 4, 0));
+        assert!(!auctions.apply(&commit("dave", 50, deposit), 6, 0));
+
+        // Reveals only during the reveal phase, and only with the committed amount
+        assert!(!auctions.apply(&reveal("alice", 20), 5, 0));
+        assert!(!auctions.apply(&reveal("alice", 25), 6, 0));
+        assert!(auctions.apply(&reveal("alice", 20), 6, 0));
+        assert!(!auctions.apply(&reveal("alice", 20), 7, 0));
+        assert!(auctions.apply(&reveal("bob", 30), 10, 0));
+        assert!(!auctions.apply(&reveal("carol", 40), 11, 0));
+
+        let state = auctions.get(&a.get_hash()).unwrap().clone();
+        assert!(!state.awaits_winner(10, 0));
+        assert!(state.awaits_winner(11, 0));
+        // Carol never revealed, the winner comes from the revealed bids only
+        assert_eq!(state.unrevealed_commits().len(), 1);
+        assert_eq!(state.unrevealed_commits()[0].buyer_id, "carol");
+        let winner = state.derive_winner("".to_string());
+        assert_eq!(winner.to, "bob");
+        assert!(!auctions.check(&Marco::from_winner(winner.clone(), 0), 8, 0));
+        assert!(auctions.apply(&Marco::from_winner(winner, 0), 11, 0));
+    }
+}
@@ -0,0 +1,113 @@
+#[doc(inline)]
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::marco::amount::Amount;
+use crate::marco::payload::Effect;
+
+/// ## Account
+/// Balance of one account, `locked` holds the funds in escrow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Account {
+    pub available: Amount,
+    pub locked: Amount,
+}
+
+/// ## Balances
+/// Balance of every account, built by applying the [Effect]s of the Marcos in chain order
+#[derive(Debug, Clone, Default)]
+pub struct Balances {
+    accounts: HashMap<String, Account>,
+}
+
+impl Balances {
+    pub fn new() -> Self {
+        Balances { accounts: HashMap::new() }
+    }
+
+    /// returns the balance of the account, accounts never seen have nothing
+    pub fn get(&self, account: &String) -> Account {
+        self.accounts.get(account).copied().unwrap_or_default()
+    }
+
+    /// applies the effects in order, either all of them or none
+    ///
+    /// **outputs:**
+    /// false if an account does not have enough available (or locked) funds
+    pub fn apply(&mut self, effects: &[Effect]) -> bool {
+        let mut touched: HashMap<String, Account> = HashMap::new();
+        for e in effects {
+            let (account, amount) = match e {
+                Effect::Credit { account, amount } | Effect::Debit { account, amount }
+                | Effect::Lock { account, amount } | Effect::Release { account, amount }
+                | Effect::Spend { account, amount } => (account, *amount),
+            };
+            let current = touched.get(account).copied().unwrap_or_else(|| self.get(account));
+            let updated = match e {
+                Effect::Credit { .. } => current.available.checked_add(amount)
+                    .map(|available| Account { available, ..current }),
+                Effect::Debit { .. } => current.available.checked_sub(amount)
+                    .map(|available| Account { available, ..current }),
+                Effect::Lock { .. } => current.available.checked_sub(amount)
+                    .zip(current.locked.checked_add(amount))
+                    .map(|(available, locked)| Account { available, locked }),
+                Effect::Release { .. } => current.locked.checked_sub(amount)
+                    .zip(current.available.checked_add(amount))
+                    .map(|(locked, available)| Account { available, locked }),
+                Effect::Spend { .. } => current.locked.checked_sub(amount)
+                    .map(|locked| Account { locked, ..current }),
+            };
+            match updated {
+                Some(a) => { touched.insert(account.clone(), a); },
+                None => {
+                    debug!("DEBUG BALANCES::APPLY => {:?} not possible with {:?}", e, current);
+                    return false;
+                }
+            }
+        }
+        self.accounts.extend(touched);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::balances::*;
+
+    fn coins(n: u64) -> Amount {
+        Amount::from_coins(n).unwrap()
+    }
+
+    #[test]
+    fn test_escrow() {
+        let mut balances = Balances::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        assert!(balances.apply(&[Effect::Credit { account: alice.clone(), amount: coins(10) }]));
+        assert!(!balances.apply(&[Effect::Lock { account: alice.clone(), amount: coins(11) }]));
+        assert!(balances.apply(&[Effect::Lock { account: alice.clone(), amount: coins(4) }]));
+        assert_eq!(balances.get(&alice), Account { available: coins(6), locked: coins(4) });
+
+        // Locked funds cannot be spent twice
+        assert!(!balances.apply(&[Effect::Debit { account: alice.clone(), amount: coins(7) }]));
+        assert!(balances.apply(&[
+            Effect::Spend { account: alice.clone(), amount: coins(3) },
+            Effect::Credit { account: bob.clone(), amount: coins(3) },
+            Effect::Release { account: alice.clone(), amount: coins(1) },
+        ]));
+        assert_eq!(balances.get(&alice), Account { available: coins(7), locked: Amount::ZERO });
+        assert_eq!(balances.get(&bob).available, coins(3));
+    }
+
+    #[test]
+    fn test_all_or_nothing() {
+        let mut balances = Balances::new();
+        let alice = "alice".to_string();
+        assert!(!balances.apply(&[
+            Effect::Credit { account: alice.clone(), amount: coins(5) },
+            Effect::Release { account: alice.clone(), amount: coins(1) },
+        ]));
+        assert_eq!(balances.get(&alice), Account::default());
+    }
+}
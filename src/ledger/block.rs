@@ -4,6 +4,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use sha2::{Digest, Sha512};
 
 use crate::{auxi, proto};
+use crate::marco::amount::Amount;
 use crate::marco::marco::Marco;
 use crate::marco::transaction::Transaction;
 
@@ -31,7 +32,7 @@ impl Block {
                prev_hash: String, 
                difficulty: usize, 
                miner_id: String,
-               miner_reward:f64) -> Self {
+               miner_reward: Amount) -> Self {
         let mut block = Block {
             index,
             timestamp : SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
@@ -50,20 +51,23 @@ impl Block {
             Transaction::new(miner_reward,
                 "network".to_string(),
                 miner_reward,
-                miner_id)
+                miner_id).expect("The miner reward never has a fee"),
+            index as u64 // the network rewards at most one miner per block index
         ));
 
         return block
     }
 
-    pub fn proto_to_block(proto_block: proto::Block) -> Self {
+    /// **outputs:**
+    /// - None if any of the Marcos in the block cannot be decoded
+    pub fn proto_to_block(proto_block: proto::Block) -> Option<Self> {
         let mut trans: Vec<Marco> = Vec::new();
 
         for i in &proto_block.transactions {
-            trans.push(auxi::transform_proto_to_marco(i));
+            trans.push(auxi::transform_proto_to_marco(i)?);
         }
 
-        Block {
+        Some(Block {
             hash: proto_block.hash.clone(),
             index: proto_block.index as usize,
             timestamp: proto_block.timestamp,
@@ -74,7 +78,7 @@ impl Block {
             merkle_tree_root: proto_block.merkle_tree_root.clone(),
             confirmations: 0,
             transactions: trans,
-        }
+        })
     }
 
 
@@ -215,22 +219,24 @@ impl Block {
 mod test {
     use crate::ledger::block::*;
 
+    use std::str::FromStr;
+
     #[test]
     fn test_mining() {
         let mut block = Block::new(1,
                                "".to_string(),
                                3,
                                "test".to_string(),
-                               3.5);
+                               Amount::from_str("3.5").unwrap());
 
-        block.add_marco(Marco::from_transaction( Transaction::new(5.0,
+        block.add_marco(Marco::from_transaction( Transaction::new(Amount::from_str("5.0").unwrap(),
                                           "alice".to_string(),
-                                          4.5,
-                                          "bob".to_string())));
-        block.add_marco(Marco::from_transaction(Transaction::new(4.0,
+                                          Amount::from_str("4.5").unwrap(),
+                                          "bob".to_string()).unwrap(), 0));
+        block.add_marco(Marco::from_transaction(Transaction::new(Amount::from_str("4.0").unwrap(),
                                           "Carlos".to_string(),
-                                          2.0,
-                                          "bob".to_string())));
+                                          Amount::from_str("2.0").unwrap(),
+                                          "bob".to_string()).unwrap(), 0));
 
         if block.mine() {
             println!("Block mined! Nonce: {} Hash: {}", 
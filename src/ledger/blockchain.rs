@@ -1,12 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use log::debug;
-use rsa::RsaPublicKey;
 
 #[doc(inline)]
 use crate::ledger::block::*;
+use crate::ledger::auctions::Auctions;
+use crate::ledger::state::LedgerState;
 use crate::ledger::heads::*;
-use crate::marco::marco::Marco;
+use crate::ledger::signature_cache::{SharedSignatureCache, SignatureCache, verify_batch};
+use crate::marco::amount::Amount;
+use crate::marco::marco::{CHAIN_ID, Marco};
+use crate::marco::payload::registry;
+use crate::marco::signature::PublicKey;
 
 // Used to apply Debug and Clone traits to the struct, debug allows printing with the use of {:?} or {:#?}
 // and Clone allows for structure and its data to duplicated
@@ -18,20 +24,32 @@ pub struct Blockchain {
     pub chain: Vec<Block>, 
     pub heads: Heads,
     pub difficulty: usize,
-        mining_reward: f64, 
+        mining_reward: Amount, 
     pub is_miner: bool,
     pub temporary_block: Block,
     pub miner_id: String,
-    pub marco_set: HashMap<String,Marco>
+    pub marco_set: HashMap<String,Marco>,
+    pub chain_id: u32,
+    /// nonces already used by each sender
+    pub nonces: HashMap<String, HashSet<u64>>,
+    /// Marcos whose signature was already verified, shared with the gossip handlers
+    pub signature_cache: SharedSignatureCache,
+    /// bumped every time a Block or a Marco is accepted, readers compare it to know if the state changed
+    pub changes: u64,
+    /// auctions and balances of `chain`, updated as blocks are confirmed so it is never replayed
+    confirmed_state: LedgerState
 }
 
 // =========================== BLOCKCHAIN CODE ==================================== //
 /// Implementation of the basic BlockChain methods
 impl Blockchain {
     const INITIAL_DIFFICULTY:usize = 1;
-    const NETWORK:&'static str = "network";
+    pub(crate) const NETWORK:&'static str = "network";
     pub const MAX_TRANSACTIONS:usize = 3;
     const CONFIRMATION_THRESHOLD:usize = 2;
+    pub(crate) const MINING_REWARD:Amount = Amount::from_units(1_000_000); // 0.01 coins
+    const SIGNATURE_CACHE_SIZE:usize = 4096;
+    const SIGNATURE_BATCH_SIZE:usize = 16;
 
     /// creates a new Blockchain with only the Genesis Block
     pub fn new(is_miner:bool, miner_id:String) -> Blockchain {
@@ -55,15 +73,20 @@ impl Blockchain {
             chain: vec![],
             heads: Heads::new(vec![genesis_block], Self::CONFIRMATION_THRESHOLD),
             difficulty: Self::INITIAL_DIFFICULTY,
-            mining_reward: 0.01,
+            mining_reward: Self::MINING_REWARD,
             is_miner,
             miner_id: miner_id.clone(),
             temporary_block: Block::new(1,
                                         hash.clone(),
                                         Self::INITIAL_DIFFICULTY,
                                         miner_id.clone(),
-                                        0.0),
-            marco_set: HashMap::new()
+                                        Self::MINING_REWARD),
+            marco_set: HashMap::new(),
+            chain_id: CHAIN_ID,
+            nonces: HashMap::new(),
+            signature_cache: SignatureCache::shared(Self::SIGNATURE_CACHE_SIZE),
+            changes: 0,
+            confirmed_state: LedgerState::new()
         }
     }
 
@@ -80,12 +103,18 @@ impl Blockchain {
         if !b.check_hash() {
             return false;
         }
+        if !self.follows_replay_rules(&b) {
+            debug!("DEBUG BLOCKCHAIN::ADD_BLOCK => Block {} has a Marco of another chain or a reused nonce", b.hash);
+            return false;
+        }
+        if !self.follows_ledger_rules(&b) {
+            debug!("DEBUG BLOCKCHAIN::ADD_BLOCK => Block {} breaks the auction rules or overdraws an account", b.hash);
+            return false;
+        }
 
         for m in &mut b.transactions {
-            let hash = m.calc_hash();
-            self.marco_set.insert(hash,m.clone());
+            m.calc_hash();
         }
-        
 
         //check if new block fits in heads
         let f = self.heads.add_block(b.clone());
@@ -106,9 +135,17 @@ impl Blockchain {
 
         }
 
+        // Only the Marcos of an accepted block are known, a block that cannot be attached
+        // must not keep them (or their nonces) out of the mempool
+        for m in &b.transactions {
+            self.record_nonce(m);
+            self.marco_set.insert(m.get_hash(),m.clone());
+        }
+
         match self.heads.get_confirmed() {
             Some(confirmed_block) => {
                 self.heads.prune(confirmed_block.prev_hash.clone());
+                self.confirmed_state.apply_block(&confirmed_block);
                 self.chain.push(confirmed_block);
             }
             None => {}
@@ -124,9 +161,11 @@ impl Blockchain {
             }
         }
         debug!("Size at end {}", self.temporary_block.transactions.len());
+        self.revalidate_temporary_block();
         self.heads.reorder();
         self.adjust_difficulty(); 
         self.adjust_temporary_block();
+        self.changes += 1;
 
         return true
     }
@@ -168,20 +207,40 @@ impl Blockchain {
     /// **outputs**:
     /// true if added successfully
     /// and false otherwise
-    pub fn add_marco(&mut self,mut t:Marco, public_key: RsaPublicKey) -> (bool, Option<Block>) {
-        if !t.verify(public_key) {
+    pub fn add_marco(&mut self,mut t:Marco, public_key: PublicKey) -> (bool, Option<Block>) {
+        if !self.signature_cache.lock().unwrap().verify(&t, &public_key) {
+            return (false,None)
+        }
+        if !registry().validate(&t.data) {
+            debug!("DEBUG BLOCKCHAIN::ADD_MARCO => Invalid {} data", t.data.kind());
+            return (false,None)
+        }
+        if t.chain_id != self.chain_id {
+            debug!("DEBUG BLOCKCHAIN::ADD_MARCO => Marco for chain {} rejected", t.chain_id);
             return (false,None)
         }
 
         let hash= t.calc_hash();
         let res = self.marco_set.contains_key(&hash);
         if res {return (false,None)}
+        if self.is_replay(&t) {
+            debug!("DEBUG BLOCKCHAIN::ADD_MARCO => Nonce {} was already used by {}", t.nonce, t.data.sender());
+            return (false,None)
+        }
+        let (height, timestamp) = self.next_position();
+        if !self.state().apply(&t, height, timestamp) {
+            debug!("DEBUG BLOCKCHAIN::ADD_MARCO => {} breaks the auction rules or cannot be paid for", t.data.kind());
+            return (false,None)
+        }
+        self.record_nonce(&t);
         self.marco_set.insert(hash,t.clone());
+        self.changes += 1;
 
         if !self.is_miner { return (true,None); }
         let _index = self.temporary_block.add_marco(t);
 
         if self.can_mine() { 
+            self.revalidate_temporary_block();
             self.temporary_block.mine();
             let r = self.temporary_block.clone();
             self.add_block(r.clone());
@@ -193,6 +252,150 @@ impl Blockchain {
         //self.event_observer.lock().unwrap().notify_transaction_created(&t).await;
     }
 
+    /// auctions and balances of the confirmed chain followed by `branch`,
+    /// only the unconfirmed blocks of the branch are replayed
+    fn branch_state(&self, branch: &[Block]) -> LedgerState {
+        let mut state = self.confirmed_state.clone();
+        for b in branch {
+            state.apply_block(b);
+        }
+        state
+    }
+
+    /// auctions and balances of the main branch, including the temporary block
+    pub fn state(&self) -> LedgerState {
+        let mut state = self.branch_state(&self.heads.get_main());
+        let (height, timestamp) = self.next_position();
+        for (i, m) in self.temporary_block.transactions.iter().enumerate() {
+            state.apply_in_block(m, height, timestamp, i, Some(&self.temporary_block.miner_id));
+        }
+        return state;
+    }
+
+    /// blocks of the chain followed by the main branch, oldest first
+    pub fn main_branch(&self) -> Vec<Block> {
+        let mut blocks = self.chain.clone();
+        blocks.extend(self.heads.get_main());
+        return blocks;
+    }
+
+    /// Marcos received that are not in a block of the main branch yet
+    pub fn pending_marcos(&self) -> Vec<Marco> {
+        let in_blocks: HashSet<String> = self.chain.iter().chain(self.heads.get_main().iter())
+            .flat_map(|b| b.transactions.iter().map(|m| m.get_hash()))
+            .collect();
+        return self.marco_set.values()
+            .filter(|m| !in_blocks.contains(&m.get_hash()))
+            .cloned()
+            .collect();
+    }
+
+    /// state of the auctions in the main branch, including the temporary block
+    pub fn auctions(&self) -> Auctions {
+        return self.state().auctions;
+    }
+
+    /// checks the data of every Marco of the block with its [PayloadHandler](crate::marco::payload::PayloadHandler),
+    /// as [Blockchain::add_marco] does, then against the auctions and balances of the branch it extends
+    fn follows_ledger_rules(&self, b: &Block) -> bool {
+        let registry = registry();
+        if let Some(m) = b.transactions.iter().find(|m| !registry.validate(&m.data)) {
+            debug!("DEBUG BLOCKCHAIN::FOLLOWS_LEDGER_RULES => Invalid {} data in {}", m.data.kind(), m.get_hash());
+            return false;
+        }
+        let branch = self.heads.branch_to(&b.prev_hash).unwrap_or_default();
+        return self.branch_state(&branch).apply_block(b);
+    }
+
+    /// moves the temporary block to the current time and drops the Marcos
+    /// that no longer apply (e.g. a bid that was outbid by another block)
+    fn revalidate_temporary_block(&mut self) {
+        let (_, timestamp) = self.next_position();
+        self.temporary_block.timestamp = timestamp;
+        let mut state = self.branch_state(&self.heads.get_main());
+        let index = self.temporary_block.index as u64;
+        let miner = self.temporary_block.miner_id.clone();
+        let mut position = 0;
+        self.temporary_block.transactions.retain(|m| {
+            let applied = state.apply_in_block(m, index, timestamp, position, Some(&miner));
+            position += 1;
+            applied
+        });
+    }
+
+    /// index and timestamp the next block is expected to have,
+    /// used to place Marcos that are not in a block yet
+    pub fn next_position(&self) -> (u64, u64) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+        return (self.get_head().index as u64 + 1, timestamp);
+    }
+
+    /// checks the signature of every Marco in the block except the miner reward,
+    /// in parallel batches, skipping the Marcos already in the `cache`
+    ///
+    /// It does not need the blockchain, so it can run without holding its lock.
+    /// Marcos that do not embed their signer key are checked with `fallback`
+    ///
+    /// **outputs:**
+    /// true if every signature is valid
+    pub fn verify_block_signatures(cache: &SharedSignatureCache, b: &Block, fallback: Option<&PublicKey>) -> bool {
+        let marcos = match b.transactions.first() {
+            Some(reward) if reward.data.sender() == Self::NETWORK => &b.transactions[1..],
+            _ => &b.transactions[..],
+        };
+        return verify_batch(cache, marcos, fallback, Self::SIGNATURE_BATCH_SIZE);
+    }
+
+    /// returns the lowest nonce above every nonce `sender` has used so far
+    pub fn next_nonce(&self, sender: &String) -> u64 {
+        match self.nonces.get(sender).and_then(|used| used.iter().max()) {
+            None => 0,
+            Some(max) => max + 1,
+        }
+    }
+
+    /// checks if a different Marco with the same sender and nonce was already seen
+    ///
+    /// The miner rewards are issued by the network and are not subject to nonces
+    fn is_replay(&self, m: &Marco) -> bool {
+        let sender = m.data.sender();
+        if sender == Self::NETWORK {
+            return false;
+        }
+        match self.nonces.get(&sender) {
+            None => false,
+            Some(used) => used.contains(&m.nonce),
+        }
+    }
+
+    /// checks every Marco of the block was created for this chain and does not reuse the nonce
+    /// of a different Marco of the same sender, seen before or in the same block
+    fn follows_replay_rules(&self, b: &Block) -> bool {
+        let mut in_block: HashSet<(String, u64)> = HashSet::new();
+        for m in &b.transactions {
+            if m.chain_id != self.chain_id {
+                return false;
+            }
+            let sender = m.data.sender();
+            if sender == Self::NETWORK {
+                continue;
+            }
+            let known = self.marco_set.contains_key(&m.to_hash());
+            if (!known && self.is_replay(m)) || !in_block.insert((sender, m.nonce)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn record_nonce(&mut self, m: &Marco) {
+        let sender = m.data.sender();
+        if sender == Self::NETWORK {
+            return;
+        }
+        self.nonces.entry(sender).or_default().insert(m.nonce);
+    }
+
     /// adjust the temporary block based on the state of the blockchain
     /// this updates the index the previous hash and the difficulty
     ///
@@ -209,6 +412,15 @@ impl Blockchain {
         return
     }
 
+    /// sets who receives the reward of the blocks we mine,
+    /// the temporary block is replaced unless it already holds Marcos
+    pub fn set_miner_id(&mut self, miner_id: String) {
+        self.miner_id = miner_id;
+        if self.temporary_block.transactions.len() <= 1 {
+            self.replace_temporary_block();
+        }
+    }
+
     /// replace the temporary block with a new one
     /// based on the current state
     fn replace_temporary_block(&mut self){
@@ -280,6 +492,7 @@ impl Blockchain {
     pub fn mine(&mut self) -> bool {
         if !self.is_miner || !self.can_mine() {return false}
 
+        self.revalidate_temporary_block();
         self.temporary_block.mine();
         //self.event_observer.lock().unwrap().notify_block_mined(&self.temporary_block).await;
         self.add_block(self.temporary_block.clone());
@@ -293,44 +506,98 @@ impl Blockchain {
 #[cfg(test)]
 mod test {
     use std::env;
+    use std::sync::OnceLock;
     use std::time::Duration;
 
+    use base64::{Engine as _, engine::general_purpose};
     use rand::Rng;
 
     use crate::auxi;
     use crate::ledger::blockchain::*;
-    use crate::marco::transaction::Transaction;
+    use rsa::{pkcs1v15::SigningKey, pkcs8::DecodePrivateKey};
 
-    fn gen_transaction() -> Marco {
-        let strings = vec![
-            "Alice".to_string(),
-            "Bob".to_string(),
-            "Carlos".to_string(),
-            "Diana".to_string(),
-            "Luna".to_string(),
-        ];
+    use crate::marco::auction::{Auction, EndCondition, PriceTerms, SealedTerms};
+    use crate::marco::bid::Bid;
+    use crate::marco::marco::Data;
+    use crate::marco::signature::SecretKey;
+    use crate::marco::transaction::Transaction;
 
-        let mut rng = rand::thread_rng();
-        let from = strings[rng.gen_range(0..strings.len())].clone();
-        let to = strings[rng.gen_range(0..strings.len())].clone();
-        let out = rng.gen_range(4.0..=10.0);
-        let _in = rng.gen_range(1.0..=3.0);
-
-        Marco::from_transaction(Transaction::new(out,
-            from,
-            out-_in,
-            to))
-    }    
-    fn add_block(bc : &mut Blockchain){
+    /// keys of the node certificate
+    fn rsa_keys() -> (SecretKey, PublicKey) {
         let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
         let mut slash = "\\";
         if env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string()) == "linux" {
             slash = "/";
         }
         let client_cert = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt"))).expect("Failed to open server.crt");
-        let pub_key = auxi::get_public_key(client_cert);
+        let skey = SigningKey::read_pkcs8_pem_file(data_dir.join(format!("cert{slash}server.key"))).expect("Failed to read server.key");
+        (SecretKey::Rsa(skey), PublicKey::Rsa(auxi::get_public_key(client_cert)))
+    }
+
+    fn signed(mut m: Marco, skey: &SecretKey) -> Marco {
+        m.sign(skey);
+        m
+    }
+
+    /// keys of the accounts used by [gen_transaction], the first one is the node certificate
+    fn accounts() -> &'static Vec<SecretKey> {
+        static ACCOUNTS: OnceLock<Vec<SecretKey>> = OnceLock::new();
+        ACCOUNTS.get_or_init(|| {
+            let mut keys = vec![rsa_keys().0];
+            keys.extend((1..5u8).map(|i| SecretKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&[i; 32]))));
+            keys
+        })
+    }
+
+    fn account_id(i: usize) -> String {
+        accounts()[i].public_key().id()
+    }
+
+    /// mines an empty block whose reward goes to `miner`.
+    ///
+    /// The block is dated a few minutes after the head, so mining many of them does not raise the difficulty
+    fn mine_reward(bc: &mut Blockchain, miner: String) {
+        let h = bc.get_head();
+        let mut b = Block::new(h.index+1, h.hash, bc.difficulty, miner, Blockchain::MINING_REWARD);
+        b.timestamp = h.timestamp + 3 * 60;
+        b.mine();
+        assert!(bc.add_block(b));
+    }
+
+    /// gives every account used by [gen_transaction] coins to spend, each one mines two blocks
+    fn fund(bc: &mut Blockchain) {
+        for _ in 0..2 {
+            for i in 0..accounts().len() {
+                mine_reward(bc, account_id(i));
+            }
+        }
+    }
+
+    /// unsigned transaction from account `from` to a random account
+    fn transaction_from(from: usize) -> Marco {
+        let mut rng = rand::thread_rng();
+        let to = account_id(rng.gen_range(0..accounts().len()));
+        let amount_in = Amount::from_units(rng.gen_range(40_000..=100_000));
+        let fee = Amount::from_units(rng.gen_range(10_000..=30_000));
+
+        Marco::from_transaction(Transaction::new(amount_in,
+            account_id(from),
+            amount_in.checked_sub(fee).unwrap(),
+            to).unwrap(),
+            rng.gen())
+    }
+
+    /// transaction between random accounts, signed by the sender
+    fn gen_transaction() -> Marco {
+        let from = rand::thread_rng().gen_range(0..accounts().len());
+        signed(transaction_from(from), &accounts()[from])
+    }
+
+    fn add_block(bc : &mut Blockchain){
         for _ in 0..Blockchain::MAX_TRANSACTIONS {
-            bc.add_marco( gen_transaction(), pub_key.clone());
+            let m = gen_transaction();
+            let pub_key = m.signer_key().unwrap();
+            bc.add_marco(m, pub_key);
         }
         bc.mine();
     }
@@ -338,6 +605,7 @@ mod test {
     #[test]
     fn test_adding_blocks() {
         let mut blockchain = Blockchain::new(true,"mario".to_string());
+        fund(&mut blockchain);
 
         let blocks:usize = 4;
         for _i in 0..blocks {
@@ -347,6 +615,14 @@ mod test {
             add_block(&mut blockchain);
         }
 
+        // The state of the confirmed blocks is kept up to date, it matches a replay of the whole chain
+        assert!(!blockchain.chain.is_empty());
+        let replayed = LedgerState::from_blocks(blockchain.main_branch().iter());
+        let kept = blockchain.branch_state(&blockchain.heads.get_main());
+        for i in 0..accounts().len() {
+            assert_eq!(kept.balances.get(&account_id(i)), replayed.balances.get(&account_id(i)));
+        }
+
         println!("{:#?}", blockchain);
         //assert_eq!(blockchain.get_current_index(), blocks+1);
     }
@@ -354,6 +630,7 @@ mod test {
     #[test]
     fn test_branching() {
         let mut bc = Blockchain::new(true,"mario".to_string());
+        fund(&mut bc);
 
         for _i in 0..2 {
             add_block(&mut bc);
@@ -364,7 +641,7 @@ mod test {
         //make a new block
         let mut b = Block::new(h.index+1,
             h.hash, bc.difficulty.clone(),"wario".to_string(),
-            100.00);
+            Blockchain::MINING_REWARD);
 
         for _ in 1..Blockchain::MAX_TRANSACTIONS {
             b.add_marco( gen_transaction());
@@ -383,16 +660,12 @@ mod test {
     #[test]
     fn test_prunning() {
         let mut bc = Blockchain::new(true,"mario".to_string());
-        let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
-        let mut slash = "\\";
-        if env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string()) == "linux" {
-            slash = "/";
-        }
-        let client_cert = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt"))).expect("Failed to open server.crt");
-        let pub_key = auxi::get_public_key(client_cert);
+        fund(&mut bc);
         for _i in 0..2 {
             for _ in 1..Blockchain::MAX_TRANSACTIONS {
-                bc.add_marco( gen_transaction(), pub_key.clone());
+                let m = gen_transaction();
+                let pub_key = m.signer_key().unwrap();
+                bc.add_marco(m, pub_key);
             }
         }
         let h = bc.get_head();
@@ -401,7 +674,7 @@ mod test {
         //make a new block
         let mut b = Block::new(h.index+1,
             h.hash, bc.difficulty.clone(),"wario".to_string(),
-            100.00);
+            Blockchain::MINING_REWARD);
 
         for _ in 1..Blockchain::MAX_TRANSACTIONS {
             b.add_marco( gen_transaction());
@@ -416,5 +689,194 @@ mod test {
         println!("{:#?}",bc);
         assert_eq!(bc.heads.num() , 1);
     }
-}
 
+    #[test]
+    fn test_signature_algorithms() {
+        let mut bc = Blockchain::new(false,"mario".to_string());
+        fund(&mut bc);
+        let (rsa_skey, rsa_pkey) = rsa_keys();
+        let ed_skey = &accounts()[1];
+        let ed_pkey = ed_skey.public_key();
+
+        let m = signed(transaction_from(1), ed_skey);
+        assert!(m.get_signature().starts_with("ed25519:"));
+        assert_eq!(m.signer_key(), Some(ed_pkey.clone()));
+        assert!(!bc.add_marco(m.clone(), rsa_pkey.clone()).0);
+        assert!(bc.add_marco(m, ed_pkey.clone()).0);
+
+        // Marcos from before the algorithm tag and the embedded key are RSA
+        let mut legacy = transaction_from(0);
+        legacy.signature = general_purpose::STANDARD.encode(rsa_skey.sign(legacy.get_hash().as_bytes()));
+        assert_eq!(legacy.signer_key(), None);
+        assert!(!bc.add_marco(legacy.clone(), ed_pkey.clone()).0);
+        assert!(bc.add_marco(legacy, rsa_pkey.clone()).0);
+
+        // The signature must match the data
+        let mut tampered = signed(transaction_from(0), &rsa_skey);
+        tampered.hash = signed(transaction_from(0), &rsa_skey).hash;
+        assert!(!bc.add_marco(tampered, rsa_pkey.clone()).0);
+
+        // A valid signature cannot spend the coins of another account
+        let stolen = signed(transaction_from(1), &rsa_skey);
+        assert!(!bc.add_marco(stolen.clone(), rsa_pkey).0);
+        assert!(!bc.add_marco(stolen, ed_pkey).0);
+    }
+
+    #[test]
+    fn test_block_auction_rules() {
+        let mut bc = Blockchain::new(false,"mario".to_string());
+        let h = bc.get_head();
+        let reward = Blockchain::MINING_REWARD;
+        let auction = Marco::from_auction(Auction::new("seller".to_string(),
+            reward, EndCondition::BlockHeight(10)), 0);
+
+        // The reward of the block pays for the coins the seller auctions
+        let mut b = Block::new(h.index+1, h.hash, bc.difficulty, "seller".to_string(), reward);
+        b.add_marco(auction.clone());
+        b.mine();
+        assert!(bc.add_block(b.clone()));
+
+        let bid = |buyer: &str| Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(),
+            "seller".to_string(), reward), 0);
+
+        // The seller cannot bid on its own auction
+        let mut bad = Block::new(b.index+1, b.hash.clone(), bc.difficulty, "seller".to_string(), reward);
+        bad.add_marco(bid("seller"));
+        bad.mine();
+        assert!(!bc.add_block(bad));
+
+        // Bids need funds to lock
+        let mut broke = Block::new(b.index+1, b.hash.clone(), bc.difficulty, "wario".to_string(), reward);
+        broke.add_marco(bid("alice"));
+        broke.mine();
+        assert!(!bc.add_block(broke));
+
+        let mut good = Block::new(b.index+1, b.hash.clone(), bc.difficulty, "alice".to_string(), reward);
+        good.add_marco(bid("alice"));
+        good.mine();
+        assert!(bc.add_block(good));
+        assert_eq!(bc.auctions().get(&auction.get_hash()).unwrap().bids.len(), 1);
+        assert_eq!(bc.state().balances.get(&"alice".to_string()).locked, reward);
+
+        // Data its handler rejects (buy-now on a sealed auction) is not accepted in a block either
+        let sealed = SealedTerms { reveal_end: EndCondition::BlockHeight(20), deposit: Amount::from_units(1) };
+        let terms = PriceTerms { reserve: None, increment: None, buy_now: Some(reward) };
+        let invalid = Marco::from_auction(Auction::sealed("alice".to_string(), Amount::from_units(10),
+            EndCondition::BlockHeight(10), sealed).with_terms(terms), 1);
+        assert!(!registry().validate(&invalid.data));
+        let head = bc.get_head();
+        let mut b = Block::new(head.index+1, head.hash, bc.difficulty, "alice".to_string(), reward);
+        b.add_marco(invalid);
+        b.mine();
+        assert!(!bc.add_block(b));
+    }
+
+    #[test]
+    fn test_block_replay_rules() {
+        let mut bc = Blockchain::new(false,"mario".to_string());
+        fund(&mut bc);
+        let block_with = |bc: &Blockchain, marcos: Vec<Marco>| {
+            let h = bc.get_head();
+            let mut b = Block::new(h.index+1, h.hash, bc.difficulty, "mario".to_string(), Blockchain::MINING_REWARD);
+            marcos.into_iter().for_each(|m| { b.add_marco(m); });
+            b.mine();
+            b
+        };
+        let with_nonce = |nonce: u64| {
+            let mut m = transaction_from(1);
+            m.nonce = nonce;
+            signed(m, &accounts()[1])
+        };
+
+        // Marcos signed for another chain
+        let mut other_chain = transaction_from(1);
+        other_chain.chain_id = CHAIN_ID + 1;
+        assert!(!bc.add_block(block_with(&bc, vec![signed(other_chain, &accounts()[1])])));
+
+        // Two Marcos of the same sender with the same nonce, in one block or in two
+        assert!(!bc.add_block(block_with(&bc, vec![with_nonce(5), with_nonce(5)])));
+        assert!(bc.add_block(block_with(&bc, vec![with_nonce(5)])));
+        assert!(!bc.add_block(block_with(&bc, vec![with_nonce(5)])));
+        let second = with_nonce(6);
+        assert!(bc.add_block(block_with(&bc, vec![second.clone()])));
+
+        // A block that cannot be attached does not make its Marcos known
+        let victim = with_nonce(7);
+        let mut orphan = Block::new(bc.get_head().index+5, "unknown".to_string(), bc.difficulty, "wario".to_string(), Blockchain::MINING_REWARD);
+        orphan.add_marco(victim.clone());
+        orphan.mine();
+        assert!(!bc.add_block(orphan));
+        let key = victim.signer_key().unwrap();
+        assert!(bc.add_marco(victim, key).0);
+
+        // The same Marco in a competing block is not a replay
+        let h = bc.get_head();
+        let mut fork = Block::new(h.index, h.prev_hash, bc.difficulty, "wario".to_string(), Blockchain::MINING_REWARD);
+        fork.add_marco(second);
+        fork.mine();
+        assert!(bc.add_block(fork));
+    }
+
+    #[test]
+    fn test_newer_payload_in_block() {
+        let mut bc = Blockchain::new(false,"mario".to_string());
+        fund(&mut bc);
+        let before = bc.state().balances.get(&account_id(1)).available;
+
+        // A transaction sent with a version this node does not know yet
+        let mut proto = auxi::transform_marco_to_proto(&signed(transaction_from(1), &accounts()[1]));
+        proto.data.as_mut().unwrap().version = 2;
+        let m = auxi::transform_proto_to_marco(&proto).unwrap();
+        assert!(registry().is_foreign(&m.data));
+        assert_eq!(m.data.sender(), account_id(1));
+        assert!(m.verify(&m.signer_key().unwrap()));
+
+        let h = bc.get_head();
+        let mut b = Block::new(h.index+1, h.hash, bc.difficulty, "mario".to_string(), Blockchain::MINING_REWARD);
+        b.add_marco(m);
+        b.mine();
+        assert!(Blockchain::verify_block_signatures(&bc.signature_cache, &b, None));
+        assert!(bc.add_block(b.clone()));
+        // It is kept and relayed as it was received, but moves no coins here
+        assert_eq!(auxi::transform_marco_to_proto(&b.transactions[1]), proto);
+        assert_eq!(bc.state().balances.get(&account_id(1)).available, before);
+    }
+
+    #[test]
+    fn test_miner_reward() {
+        let mut bc = Blockchain::new(false,"mario".to_string());
+        let h = bc.get_head();
+
+        // Blocks cannot mint more than the reward or pay it to someone else
+        let mut greedy = Block::new(h.index+1, h.hash.clone(), bc.difficulty, "mario".to_string(),
+            Blockchain::MINING_REWARD.checked_mul(2).unwrap());
+        greedy.mine();
+        assert!(!bc.add_block(greedy));
+        let mut other = Block::new(h.index+1, h.hash.clone(), bc.difficulty, "mario".to_string(), Blockchain::MINING_REWARD);
+        other.miner_id = "wario".to_string();
+        other.mine();
+        assert!(!bc.add_block(other));
+
+        // The fees of the block go to its miner
+        mine_reward(&mut bc, account_id(1));
+        let m = signed(transaction_from(1), &accounts()[1]);
+        let fee = match &m.data { Data::Transaction(t) => t.miner_fee, _ => unreachable!() };
+        let head = bc.get_head();
+        let mut b = Block::new(head.index+1, head.hash, bc.difficulty, "luigi".to_string(), Blockchain::MINING_REWARD);
+        b.add_marco(m);
+        b.mine();
+        assert!(bc.add_block(b));
+        assert_eq!(bc.state().balances.get(&"luigi".to_string()).available, Blockchain::MINING_REWARD.checked_add(fee).unwrap());
+
+        // A received block cannot pay its miner a fee nobody paid
+        let mut free = transaction_from(1);
+        free.data = Data::Transaction(Transaction { from: account_id(1), to: account_id(2), amount_in: Amount::ZERO,
+            amount_out: Amount::ZERO, miner_fee: Blockchain::MINING_REWARD.checked_mul(100).unwrap() });
+        let head = bc.get_head();
+        let mut b = Block::new(head.index+1, head.hash, bc.difficulty, "luigi".to_string(), Blockchain::MINING_REWARD);
+        b.add_marco(signed(free, &accounts()[1]));
+        b.mine();
+        assert!(!bc.add_block(b));
+        assert_eq!(bc.state().balances.get(&"luigi".to_string()).available, Blockchain::MINING_REWARD.checked_add(fee).unwrap());
+    }
+}
@@ -124,4 +124,15 @@ impl Heads {
         
         return None;
     }
+
+    /// returns the blocks of the branch that ends with the block with `hash`,
+    /// starting at the oldest unconfirmed block
+    pub fn branch_to(&self, hash: &String) -> Option<Vec<Block>> {
+        for head in self.list.iter() {
+            if let Some(index) = head.iter().position(|block| &block.hash == hash) {
+                return Some(head[..index + 1].to_vec());
+            }
+        }
+        return None;
+    }
 }
@@ -1,3 +1,8 @@
 pub mod blockchain;
 pub mod heads;
 pub mod block;
+pub mod signature_cache;
+pub mod auctions;
+pub mod balances;
+pub mod state;
+pub mod winner_strategy;
@@ -0,0 +1,188 @@
+#[doc(inline)]
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::marco::marco::Marco;
+use crate::marco::signature::PublicKey;
+
+/// [SignatureCache] shared between the [Blockchain](crate::ledger::blockchain::Blockchain)
+/// and the gossip handlers
+pub type SharedSignatureCache = Arc<Mutex<SignatureCache>>;
+
+/// ## SignatureCache
+/// Bounded set of the hashes of Marcos whose signature was already verified.
+///
+/// When the cache is full the oldest hash is forgotten, which only means
+/// that Marco is verified again the next time it is seen
+#[derive(Debug)]
+pub struct SignatureCache {
+    capacity: usize,
+    order: VecDeque<String>,
+    verified: HashSet<String>,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: usize) -> Self {
+        SignatureCache {
+            capacity,
+            order: VecDeque::new(),
+            verified: HashSet::new(),
+        }
+    }
+
+    pub fn shared(capacity: usize) -> SharedSignatureCache {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn contains(&self, hash: &String) -> bool {
+        self.verified.contains(hash)
+    }
+
+    pub fn insert(&mut self, hash: String) {
+        if self.capacity == 0 || !self.verified.insert(hash.clone()) {
+            return;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.verified.remove(&oldest);
+            }
+        }
+    }
+
+    /// returns true if the Marco is intact and was already verified
+    ///
+    /// The hash is recomputed, so a cached hash attached to different data is not trusted
+    pub fn is_verified(&self, m: &Marco) -> bool {
+        self.contains(&m.get_hash()) && m.get_hash() == m.to_hash()
+    }
+
+    /// verifies the Marco with `pkey` unless it is already in the cache
+    pub fn verify(&mut self, m: &Marco, pkey: &PublicKey) -> bool {
+        if self.is_verified(m) {
+            return true;
+        }
+        if !m.verify(pkey) {
+            return false;
+        }
+        self.insert(m.get_hash());
+        true
+    }
+}
+
+/// Verifies the signatures of `marcos` in parallel batches of `batch_size`,
+/// the cache is only locked before and after the verification.
+///
+/// Each Marco is checked with the key it embeds, or `fallback` for Marcos that do not embed one,
+/// and is rejected unless that key belongs to its sender (see [Marco::verify]).
+///
+/// **outputs:**
+/// - true if every signature is valid
+pub fn verify_batch(cache: &SharedSignatureCache, marcos: &[Marco], fallback: Option<&PublicKey>, batch_size: usize) -> bool {
+    let pending: Vec<&Marco> = {
+        let c = cache.lock().unwrap();
+        marcos.iter().filter(|m| !c.is_verified(m)).collect()
+    };
+    if pending.is_empty() {
+        return true;
+    }
+
+    let results: Vec<Option<Vec<String>>> = std::thread::scope(|s| {
+        let handles: Vec<_> = pending.chunks(batch_size.max(1))
+            .map(|batch| s.spawn(move || {
+                let mut verified = Vec::new();
+                for m in batch {
+                    let valid = match m.signer_key() {
+                        Some(key) => m.verify(&key),
+                        None => fallback.is_some_and(|key| m.verify(key)),
+                    };
+                    if !valid {
+                        return None;
+                    }
+                    verified.push(m.get_hash());
+                }
+                Some(verified)
+            }))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap_or(None)).collect()
+    });
+
+    let mut c = cache.lock().unwrap();
+    let mut all_valid = true;
+    for r in results {
+        match r {
+            // Batches that passed are still cached, they will not be checked again
+            Some(hashes) => hashes.into_iter().for_each(|h| c.insert(h)),
+            None => all_valid = false,
+        }
+    }
+    all_valid
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::signature_cache::*;
+    use crate::marco::amount::Amount;
+    use crate::marco::signature::SecretKey;
+    use crate::marco::transaction::Transaction;
+
+    fn signed_as(from: String, nonce: u64, skey: &SecretKey) -> Marco {
+        let amount = Amount::from_units(10);
+        let mut m = Marco::from_transaction(Transaction::new(amount, from, amount, "Bob".to_string()).unwrap(), nonce);
+        m.sign(skey);
+        m
+    }
+
+    fn signed(nonce: u64, skey: &SecretKey) -> Marco {
+        signed_as(skey.public_key().id(), nonce, skey)
+    }
+
+    #[test]
+    fn test_bounded_cache() {
+        let mut cache = SignatureCache::new(2);
+        cache.insert("a".to_string());
+        cache.insert("b".to_string());
+        cache.insert("b".to_string());
+        assert_eq!(cache.len(), 2);
+
+        cache.insert("c".to_string());
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&"a".to_string()));
+        assert!(cache.contains(&"b".to_string()));
+        assert!(cache.contains(&"c".to_string()));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let skey = SecretKey::generate_ed25519();
+        let cache = SignatureCache::shared(100);
+        let marcos: Vec<Marco> = (0..10).map(|n| signed(n, &skey)).collect();
+
+        assert!(verify_batch(&cache, &marcos, None, 3));
+        assert_eq!(cache.lock().unwrap().len(), 10);
+
+        let mut forged = signed(10, &skey);
+        forged.public_key = SecretKey::generate_ed25519().public_key().encode();
+        assert!(!verify_batch(&cache, &[forged.clone()], None, 3));
+        assert!(!cache.lock().unwrap().contains(&forged.get_hash()));
+
+        // A valid signature does not allow spending from someone else's account
+        let impostor = signed_as(SecretKey::generate_ed25519().public_key().id(), 11, &skey);
+        assert!(!verify_batch(&cache, &[impostor], None, 3));
+        let unkeyed = signed_as("Alice".to_string(), 12, &skey);
+        assert!(!verify_batch(&cache, &[unkeyed], None, 3));
+
+        // A cached hash does not make tampered data valid
+        let mut tampered = marcos[0].clone();
+        tampered.nonce = 99;
+        assert!(!cache.lock().unwrap().is_verified(&tampered));
+    }
+}
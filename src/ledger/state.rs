@@ -0,0 +1,258 @@
+#[doc(inline)]
+use log::debug;
+
+use crate::ledger::auctions::Auctions;
+use crate::ledger::balances::Balances;
+use crate::ledger::block::Block;
+use crate::ledger::blockchain::Blockchain;
+use crate::marco::marco::{Data, Marco};
+use crate::marco::payload::{Effect, registry};
+
+/// ## LedgerState
+/// Auctions and balances, built by replaying Marcos in chain order.
+///
+/// A Marco is only included if it follows the auction rules and every
+/// account it touches can pay for it, see [Auctions::escrow]
+#[derive(Debug, Clone, Default)]
+pub struct LedgerState {
+    pub auctions: Auctions,
+    pub balances: Balances,
+}
+
+impl LedgerState {
+    pub fn new() -> Self {
+        LedgerState { auctions: Auctions::new(), balances: Balances::new() }
+    }
+
+    /// replays the blocks in order, Marcos that break the rules are ignored
+    pub fn from_blocks<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut state = Self::new();
+        for b in blocks {
+            state.apply_block(b);
+        }
+        state
+    }
+
+    /// applies every Marco of the block, the fees of its transactions go to the miner
+    ///
+    /// **outputs:**
+    /// true if every Marco was accepted
+    pub fn apply_block(&mut self, b: &Block) -> bool {
+        let mut all_applied = true;
+        for (i, m) in b.transactions.iter().enumerate() {
+            all_applied &= self.apply_in_block(m, b.index as u64, b.timestamp, i, Some(&b.miner_id));
+        }
+        all_applied
+    }
+
+    /// records the Marco if it follows the auction rules and its balance changes are possible,
+    /// used for Marcos that are not in a block yet, so no miner is paid the fee
+    ///
+    /// **outputs:**
+    /// true if the Marco was accepted
+    pub fn apply(&mut self, m: &Marco, height: u64, timestamp: u64) -> bool {
+        self.apply_in_block(m, height, timestamp, usize::MAX, None)
+    }
+
+    /// same as [LedgerState::apply] for the Marco at `position` in a block mined by `miner`.
+    ///
+    /// The miner reward (the first Marco, sent by the network) is the only one
+    /// that creates coins, the network is not debited for it. It must pay exactly
+    /// the [mining reward](Blockchain::MINING_REWARD) to the miner of the block,
+    /// who also receives the fee of every transaction of the block
+    pub fn apply_in_block(&mut self, m: &Marco, height: u64, timestamp: u64, position: usize, miner: Option<&String>) -> bool {
+        let is_reward = position == 0 && m.data.sender() == Blockchain::NETWORK;
+        if is_reward && !Self::is_reward_for(m, miner) {
+            debug!("DEBUG LEDGER_STATE::APPLY => Invalid miner reward {}", m.get_hash());
+            return false;
+        }
+        if !self.auctions.check(m, height, timestamp) {
+            return false;
+        }
+        let mut effects = registry().effects(&m.data);
+        if is_reward {
+            effects.retain(|e| !matches!(e, Effect::Debit { .. }));
+        }
+        if let (Data::Transaction(t), false) = (&m.data, is_reward) {
+            // The fee is what the sender pays and the receiver does not get, any other fee would create coins
+            if t.amount_in.checked_sub(t.amount_out) != Some(t.miner_fee) {
+                debug!("DEBUG LEDGER_STATE::APPLY => Transaction {} has an invalid fee", m.get_hash());
+                return false;
+            }
+            if let Some(miner) = miner.filter(|_| !t.miner_fee.is_zero()) {
+                effects.push(Effect::Credit { account: miner.clone(), amount: t.miner_fee });
+            }
+        }
+        effects.extend(self.auctions.escrow(m));
+        if !self.balances.apply(&effects) {
+            debug!("DEBUG LEDGER_STATE::APPLY => {} {} cannot be paid for", m.data.kind(), m.get_hash());
+            return false;
+        }
+        self.auctions.apply(m, height, timestamp)
+    }
+
+    /// true if `m` pays the mining reward, and nothing more, to `miner`
+    fn is_reward_for(m: &Marco, miner: Option<&String>) -> bool {
+        match (&m.data, miner) {
+            (Data::Transaction(t), Some(miner)) => &t.to == miner
+                && t.amount_in == Blockchain::MINING_REWARD
+                && t.amount_out == Blockchain::MINING_REWARD,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::state::*;
+    use crate::marco::amount::Amount;
+    use crate::marco::auction::{Auction, AuctionType, EndCondition, SealedTerms};
+    use crate::marco::bid::Bid;
+    use crate::marco::sealed_bid::{BidCommit, BidReveal};
+    use crate::marco::transaction::Transaction;
+
+    /// `n` mining rewards
+    fn coins(n: u64) -> Amount {
+        Blockchain::MINING_REWARD.checked_mul(n).unwrap()
+    }
+
+    /// `account` mines `n` blocks
+    fn fund(state: &mut LedgerState, account: &str, n: u64) {
+        let miner = account.to_string();
+        for index in 1..=n {
+            let reward = Marco::from_transaction(Transaction::new(coins(1), "network".to_string(), coins(1), miner.clone()).unwrap(), index);
+            assert!(state.apply_in_block(&reward, index, 0, 0, Some(&miner)));
+        }
+    }
+
+    fn bid(auction: &Marco, buyer: &str, n: u64) -> Marco {
+        Marco::from_bid(Bid::new(auction.get_hash(), buyer.to_string(), "seller".to_string(), coins(n)), n)
+    }
+
+    #[test]
+    fn test_escrow_and_settlement() {
+        let mut state = LedgerState::new();
+        let a = Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10)), 0);
+        assert!(!state.apply(&a, 1, 0));
+        fund(&mut state, "seller", 5);
+        fund(&mut state, "alice", 20);
+        fund(&mut state, "bob", 20);
+        assert!(state.apply(&a, 1, 0));
+        assert_eq!(state.balances.get(&"seller".to_string()).locked, coins(5));
+
+        assert!(state.apply(&bid(&a, "alice", 10), 2, 0));
+        assert!(!state.apply(&bid(&a, "bob", 21), 2, 0));
+        assert!(state.apply(&bid(&a, "bob", 15), 2, 0));
+        // Alice was outbid, her funds are back
+        assert_eq!(state.balances.get(&"alice".to_string()).available, coins(20));
+        assert_eq!(state.balances.get(&"bob".to_string()).locked, coins(15));
+
+        let winner = state.auctions.get(&a.get_hash()).unwrap().derive_winner("".to_string());
+        assert!(state.apply(&Marco::from_winner(winner, 0), 3, 0));
+        assert_eq!(state.balances.get(&"seller".to_string()).available, coins(15));
+        assert_eq!(state.balances.get(&"seller".to_string()).locked, Amount::ZERO);
+        assert_eq!(state.balances.get(&"bob".to_string()).available, coins(10));
+        assert_eq!(state.balances.get(&"bob".to_string()).locked, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_miner_reward_and_fees() {
+        let mut state = LedgerState::new();
+        let miner = "miner".to_string();
+        let reward = |n: u64, to: &str| Marco::from_transaction(Transaction::new(coins(n), "network".to_string(), coins(n), to.to_string()).unwrap(), 1);
+
+        // Only the mining reward, paid to the miner of the block
+        assert!(!state.apply_in_block(&reward(2, "miner"), 1, 0, 0, Some(&miner)));
+        assert!(!state.apply_in_block(&reward(1, "alice"), 1, 0, 0, Some(&miner)));
+        assert!(!state.apply_in_block(&reward(1, "miner"), 1, 0, 0, None));
+        assert!(!state.apply_in_block(&reward(1, "miner"), 1, 0, 1, Some(&miner)));
+        assert!(state.apply_in_block(&reward(1, "miner"), 1, 0, 0, Some(&miner)));
+
+        // The fee goes to the miner of the block with the transaction
+        let paid = Marco::from_transaction(Transaction::new(coins(1), "miner".to_string(), Amount::from_units(coins(1).units() / 2), "alice".to_string()).unwrap(), 0);
+        assert!(state.apply_in_block(&paid, 2, 0, 1, Some(&"other".to_string())));
+        assert_eq!(state.balances.get(&"alice".to_string()).available, Amount::from_units(coins(1).units() / 2));
+        assert_eq!(state.balances.get(&"other".to_string()).available, Amount::from_units(coins(1).units() / 2));
+        assert_eq!(state.balances.get(&"miner".to_string()).available, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_concurrent_auctions() {
+        let mut state = LedgerState::new();
+        fund(&mut state, "seller", 10);
+        fund(&mut state, "alice", 20);
+        // Same terms, the Marco hash tells them apart
+        let a1 = Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10)), 0);
+        let a2 = Marco::from_auction(Auction::new("seller".to_string(), coins(5), EndCondition::BlockHeight(10)), 1);
+        assert_ne!(a1.get_hash(), a2.get_hash());
+        assert!(state.apply(&a1, 1, 0));
+        assert!(state.apply(&a2, 1, 0));
+        assert_eq!(state.balances.get(&"seller".to_string()).locked, coins(10));
+
+        assert!(state.apply(&bid(&a1, "alice", 3), 2, 0));
+        assert!(state.apply(&bid(&a2, "alice", 4), 2, 0));
+        assert_eq!(state.auctions.get(&a1.get_hash()).unwrap().highest_bid().unwrap().amount, coins(3));
+        assert_eq!(state.auctions.get(&a2.get_hash()).unwrap().highest_bid().unwrap().amount, coins(4));
+
+        let winner = state.auctions.get(&a1.get_hash()).unwrap().derive_winner("".to_string());
+        assert!(state.apply(&Marco::from_winner(winner, 0), 3, 0));
+        assert!(state.auctions.get(&a2.get_hash()).unwrap().is_open(3, 0));
+        assert_eq!(state.balances.get(&"alice".to_string()).locked, coins(4));
+    }
+
+    #[test]
+    fn test_vickrey_settlement() {
+        let mut state = LedgerState::new();
+        let terms = SealedTerms { reveal_end: EndCondition::BlockHeight(10), deposit: coins(1) };
+        let a = Marco::from_auction(Auction::sealed("seller".to_string(), coins(5), EndCondition::BlockHeight(5), terms)
+            .with_type(AuctionType::Vickrey), 0);
+        fund(&mut state, "seller", 5);
+        fund(&mut state, "alice", 20);
+        fund(&mut state, "bob", 20);
+        assert!(state.apply(&a, 1, 0));
+
+        let salt = "salt".to_string();
+        for (buyer, n) in [("alice", 12), ("bob", 15)] {
+            let commit = BidCommit::new(a.get_hash(), buyer.to_string(), "seller".to_string(), coins(n), &salt, coins(1));
+            assert!(state.apply(&Marco::from_bid_commit(commit, n), 2, 0));
+            assert!(state.apply(&Marco::from_bid_reveal(BidReveal::new(a.get_hash(), buyer.to_string(), coins(n), salt.clone()), n + 1), 6, 0));
+        }
+
+        // Bob wins and pays Alice's bid
+        let winner = state.auctions.get(&a.get_hash()).unwrap().derive_winner("".to_string());
+        assert_eq!((winner.to.as_str(), winner.amount), ("bob", coins(12)));
+        assert!(state.apply(&Marco::from_winner(winner, 0), 11, 0));
+        assert_eq!(state.balances.get(&"seller".to_string()).available, coins(12));
+        assert_eq!(state.balances.get(&"bob".to_string()).available, coins(13));
+        assert_eq!(state.balances.get(&"alice".to_string()).available, coins(20));
+        assert_eq!(state.balances.get(&"bob".to_string()).locked, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_sealed_deposits() {
+        let mut state = LedgerState::new();
+        let terms = SealedTerms { reveal_end: EndCondition::BlockHeight(10), deposit: coins(1) };
+        let a = Marco::from_auction(Auction::sealed("seller".to_string(), coins(5), EndCondition::BlockHeight(5), terms), 0);
+        fund(&mut state, "seller", 5);
+        fund(&mut state, "alice", 20);
+        fund(&mut state, "bob", 20);
+        assert!(state.apply(&a, 1, 0));
+
+        let salt = "salt".to_string();
+        let commit = |buyer: &str, n: u64| Marco::from_bid_commit(BidCommit::new(a.get_hash(),
+            buyer.to_string(), "seller".to_string(), coins(n), &salt, coins(1)), n);
+        assert!(state.apply(&commit("alice", 12), 2, 0));
+        assert!(state.apply(&commit("bob", 15), 2, 0));
+        assert!(state.apply(&Marco::from_bid_reveal(BidReveal::new(a.get_hash(), "alice".to_string(), coins(12), salt.clone()), 0), 6, 0));
+        assert_eq!(state.balances.get(&"alice".to_string()).locked, coins(12));
+
+        // Bob never reveals, Alice wins and his deposit goes to the seller
+        let winner = state.auctions.get(&a.get_hash()).unwrap().derive_winner("".to_string());
+        assert_eq!(winner.to, "alice");
+        assert!(state.apply(&Marco::from_winner(winner, 0), 11, 0));
+        assert_eq!(state.balances.get(&"seller".to_string()).available, coins(13));
+        assert_eq!(state.balances.get(&"alice".to_string()).available, coins(13));
+        assert_eq!(state.balances.get(&"bob".to_string()).available, coins(19));
+        assert_eq!(state.balances.get(&"bob".to_string()).locked, Amount::ZERO);
+    }
+}
@@ -0,0 +1,54 @@
+#[doc(inline)]
+use crate::ledger::auctions::AuctionState;
+use crate::marco::amount::Amount;
+use crate::marco::auction::AuctionType;
+
+/// ## WinnerStrategy
+/// Decides who wins an auction and the price they pay, from the valid bids in the chain.
+///
+/// Every node runs the same strategy on the same bids, so the
+/// [Winner](crate::marco::winner::Winner) can be checked by anyone
+pub trait WinnerStrategy {
+    /// **outputs:**
+    /// the buyer and the price, or None if nobody wins
+    fn winner(&self, state: &AuctionState) -> Option<(String, Amount)>;
+}
+
+/// English and Dutch auctions: the highest bid pays its amount.
+/// A Dutch auction ends with its first bid, so that bid is the highest
+pub struct HighestBid;
+
+/// Vickrey auctions: the highest bid pays the second-highest bid,
+/// or the reserve price when it is alone (its own amount without a reserve)
+pub struct SecondPrice;
+
+impl WinnerStrategy for HighestBid {
+    fn winner(&self, state: &AuctionState) -> Option<(String, Amount)> {
+        state.highest_bid().map(|b| (b.buyer_id.clone(), b.amount))
+    }
+}
+
+impl WinnerStrategy for SecondPrice {
+    fn winner(&self, state: &AuctionState) -> Option<(String, Amount)> {
+        let highest = state.highest_bid()?;
+        let reserve = state.auction.get_terms().reserve.unwrap_or(Amount::ZERO);
+        let second = state.bids.iter()
+            .filter(|b| b.buyer_id != highest.buyer_id && b.amount >= reserve)
+            .map(|b| b.amount)
+            .max();
+        let price = match (second, state.auction.get_terms().reserve) {
+            (Some(amount), _) => amount,
+            (None, Some(reserve)) => reserve,
+            (None, None) => highest.amount,
+        };
+        Some((highest.buyer_id.clone(), price))
+    }
+}
+
+/// the strategy of the auction type
+pub fn strategy(auction_type: &AuctionType) -> &'static dyn WinnerStrategy {
+    match auction_type {
+        AuctionType::English | AuctionType::Dutch(_) => &HighestBid,
+        AuctionType::Vickrey => &SecondPrice,
+    }
+}
@@ -5,11 +5,17 @@ use std::io::stdin;
 
 use env_logger;
 use log::{debug, info};
+use rsa::{pkcs1v15::SigningKey, pkcs8::DecodePrivateKey};
 
 use crate::auction::auction::Auction;
+use crate::auction::cli;
+use crate::auction::events::EventTracker;
+use crate::auction::wallet::Wallet;
 use crate::kademlia::node::{ID_LEN, Identifier, Node};
 use crate::ledger::blockchain::Blockchain;
+use crate::marco::amount::Amount;
 use crate::marco::marco::Marco;
+use crate::marco::signature::{PublicKey, SecretKey};
 use crate::marco::transaction::Transaction;
 use crate::p2p::peer::Peer;
 
@@ -31,8 +37,12 @@ async fn main() {
     // This is here so that I can still test the project outside of docker
     let os = env::var("OS_CONF").unwrap_or_else(|_| "linux".to_string());
     let mut server = env::var("EXEC_MODE").unwrap_or_else(|_| "CLIENT".to_string());
+    let args: Vec<String> = env::args().collect();
+    // Subcommands run against a node that is already running, see auction::cli
+    if cli::is_subcommand(&args[1..]) {
+        std::process::exit(cli::run(&args[1..]).await);
+    }
     if os == "windows" || os == "linux" {
-        let args: Vec<String> = env::args().collect();
         server = args[1].clone();
     }
     if server.to_string() == "SERVER1" {
@@ -41,6 +51,11 @@ async fn main() {
     } else if server.to_string() == "SERVER3" {
         println!("Argument \"3\" passed, creating server...");
         test_server_blockchain_node().await;
+    } else if server.to_string() == "NODE" {
+        let port = cli::Command::parse(&args[1..]).flags.get("port")
+            .and_then(|p| p.parse::<u32>().ok())
+            .unwrap_or(cli::DEFAULT_NODE_PORT);
+        wallet_node(port).await;
     } else if server.to_string() == "BOOTSTRAP" {
         println!("Bootstraping this shiiiiiiiiiiii...");
         test_bootstrap_code().await;
@@ -62,6 +77,26 @@ async fn test_bootstrap_code() {
     let _ = shutdown_rx.await;
 }
 
+/// Node with a wallet and no terminal UI, driven with the `auction` and `chain` subcommands
+async fn wallet_node(port: u32) {
+    let node = &Node::new("127.0.0.1".to_string(), port).unwrap();
+    let (client, server) = Peer::new(node, false);
+    let wallet = Wallet::load(client.clone());
+    let mut shutdown_rx = server.init_auction_server(wallet.clone()).await;
+    tokio::spawn(EventTracker::watch(wallet.clone()));
+    client.boot().await;
+    println!("Wallet node {} listening at {}:{}", wallet.id, node.ip, node.port);
+
+    // Like the terminal UI, close the auctions that ended without a winner
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            _ = interval.tick() => wallet.publish_ended_auctions(),
+        }
+    }
+}
+
 async fn test_server_blockchain_node() {
     println!("Creating server with blockchain content");
     let node = &Node::new("127.0.0.1".to_string(), auxi::get_port().await as u32).unwrap();
@@ -99,13 +134,14 @@ async fn test_server_blockchain_node() {
         slash = "/";
     }
     let client_cert = std::fs::read_to_string(data_dir.join(format!("cert{slash}server.crt"))).expect("Failed to open server.crt");
-    let pub_key = auxi::get_public_key(client_cert);
+    let pub_key = PublicKey::Rsa(auxi::get_public_key(client_cert));
+    let skey = SecretKey::Rsa(SigningKey::read_pkcs8_pem_file(data_dir.join(format!("cert{slash}server.key"))).expect("Failed to read server.key"));
     let mut count = 0;
     for i in 0..3 {
         for j in 0..Blockchain::MAX_TRANSACTIONS {
             println!("Was able to add to blockchain? {}", 
                     client.blockchain.lock().unwrap()
-                        .add_marco(gen_transaction(strings[i+j].clone(), count), pub_key.clone()).0);
+                        .add_marco(gen_transaction(strings[i+j].clone(), count, &skey), pub_key.clone()).0);
             count += 1;
         }
         client.blockchain.lock().unwrap().mine();
@@ -121,11 +157,7 @@ async fn test_server_blockchain_node() {
 
     //
     let mut key_server_should_have = node.id.clone();
-    if key_server_should_have.0[ID_LEN - 1] == 0 {
-        key_server_should_have.0[ID_LEN - 1] = 1;
-    } else {
-        key_server_should_have.0[ID_LEN - 1] = 0;
-    }
+    key_server_should_have.0[ID_LEN - 1] ^= 1;
     println!("Listening at {}:{}", node.ip, node.port);
 
     println!("Do I have the key?: {}", !client.kademlia.lock().unwrap().get_value(key_server_should_have.clone()).is_none());
@@ -161,11 +193,7 @@ async fn test_server() {
     client.boot().await;
 
     let mut key_server_should_have = node.id.clone();
-    if key_server_should_have.0[ID_LEN - 1] == 0 {
-        key_server_should_have.0[ID_LEN - 1] = 1;
-    } else {
-        key_server_should_have.0[ID_LEN - 1] = 0;
-    }
+    key_server_should_have.0[ID_LEN - 1] ^= 1;
 
     // Add node for test in client
     client.kademlia.lock().unwrap().add_node(&Node::new("127.54.123.2".to_string(),9981).unwrap());
@@ -183,36 +211,32 @@ async fn test_server() {
 
 async fn test_client() {
     let auction = &mut Auction::new().await;
-    println!("Listening at 127.0.0.1:{}", auction.client.node.port);
+    println!("Listening at 127.0.0.1:{}", auction.wallet.peer.node.port);
 
     let mut keys: Vec<Identifier> = Vec::new();
-    let nodes_stored = auction.client.kademlia.lock().unwrap().get_all_nodes().unwrap_or(Vec::new());
+    let nodes_stored = auction.wallet.peer.kademlia.lock().unwrap().get_all_nodes().unwrap_or(Vec::new());
     // Here we can always assume the vec is not empty
     for i in nodes_stored {
         if i.port == 8635 {continue;}
         let mut key_id = i.id.clone();
-        if key_id.0[ID_LEN - 1] == 0 {
-            key_id.0[ID_LEN - 1] = 1;
-        } else {
-            key_id.0[ID_LEN - 1] = 0;
-        }
+        key_id.0[ID_LEN - 1] ^= 1;
         keys.push(key_id);
     }
     auction.main().await;
 
 
-    println!("Get Block -> {:?}", auction.client.get_block("004048e475898274f4ab7e01aeaa2e4b60e4a7461024ee4cc91ac95a2205385483e8a8d4d13f9fa58b03c2ed2cd23b6fc26070745dcbae96166b1802ea5d7bfa".to_string()).await);
-    println!("Broadcasted Transaction -> {:?}", auction.client.send_marco(gen_transaction("Testing broadcast of transaction in Client".to_string(), 2)).await);
-    println!("Broadcast Block -> {:?}", auction.client.send_block(auction.client.blockchain.lock().unwrap().get_head()).await);
-    println!("Result -> {:?}", auction.client.find_node(auxi::gen_id("127.0.0.2:8890".to_string())).await); // Should fail
-    println!("Result -> {:?}", auction.client.find_node(auxi::gen_id("127.54.123.2:9981".to_string())).await); // Should succeed (Server1 has this node)
+    println!("Get Block -> {:?}", auction.wallet.peer.get_block("004048e475898274f4ab7e01aeaa2e4b60e4a7461024ee4cc91ac95a2205385483e8a8d4d13f9fa58b03c2ed2cd23b6fc26070745dcbae96166b1802ea5d7bfa".to_string()).await);
+    println!("Broadcasted Transaction -> {:?}", auction.wallet.peer.send_marco(gen_transaction("Testing broadcast of transaction in Client".to_string(), 2, &auction.wallet.skey)).await);
+    println!("Broadcast Block -> {:?}", auction.wallet.peer.send_block(auction.wallet.peer.blockchain.lock().unwrap().get_head()).await);
+    println!("Result -> {:?}", auction.wallet.peer.find_node(auxi::gen_id("127.0.0.2:8890".to_string())).await); // Should fail
+    println!("Result -> {:?}", auction.wallet.peer.find_node(auxi::gen_id("127.54.123.2:9981".to_string())).await); // Should succeed (Server1 has this node)
     for i in &keys {
-        println!("Result Store -> {:?}", auction.client.store(i.clone(), "Some Random Value Server3 Should Have".to_string()).await); // Should get remote store
-        println!("Result Find Key -> {:?}", auction.client.find_value(i.clone()).await); // Should succeed
+        println!("Result Store -> {:?}", auction.wallet.peer.store(i.clone(), "Some Random Value Server3 Should Have".to_string()).await); // Should get remote store
+        println!("Result Find Key -> {:?}", auction.wallet.peer.find_value(i.clone()).await); // Should succeed
     }
 
-    auction.client.blockchain.lock().unwrap().mine();
-    let list = auction.client.blockchain.lock().unwrap().chain.clone();
+    auction.wallet.peer.blockchain.lock().unwrap().mine();
+    let list = auction.wallet.peer.blockchain.lock().unwrap().chain.clone();
     for i in list {
         println!("Block{{{}}}: hash -> {}; prev_hash -> {}", i.index, i.hash.clone(), i.prev_hash.clone());
     }
@@ -227,27 +251,29 @@ async fn test_client() {
         }
     }
 
-    println!("Get Block with hash: {} ->\n{:?}",hash.clone(), auction.client.get_block(hash).await);
-    let list = auction.client.blockchain.lock().unwrap().chain.clone();
+    println!("Get Block with hash: {} ->\n{:?}",hash.clone(), auction.wallet.peer.get_block(hash).await);
+    let list = auction.wallet.peer.blockchain.lock().unwrap().chain.clone();
     for i in list {
         println!("Block{{{}}}: hash -> {}; prev_hash -> {}", i.index, i.hash.clone(), i.prev_hash.clone());
     }
-    let list = auction.client.blockchain.lock().unwrap().heads.get_main().clone();
+    let list = auction.wallet.peer.blockchain.lock().unwrap().heads.get_main().clone();
     for i in list {
         println!("Block{{Head}}: hash -> {}; prev_hash -> {}", i.hash.clone(), i.prev_hash.clone());
     }
 
 }
 
-fn gen_transaction(from: String, id: u32) -> Marco{
+/// zero amount transaction to `to`, sent by the owner of `skey`
+fn gen_transaction(to: String, id: u32, skey: &SecretKey) -> Marco{
 
-    let from = from;
-    let to = "test".to_string() + &id.to_string();
-    let out = 0.0;
-    let _in = 0.0;
+    let from = skey.public_key().id();
+    let amount = Amount::ZERO;
 
-    Marco::from_transaction(Transaction::new(out,
+    let mut m = Marco::from_transaction(Transaction::new(amount,
                      from,
-                     out-_in,
-                     to))
+                     amount,
+                     to).expect("Transactions without a fee are always valid"),
+                     id as u64);
+    m.sign(skey);
+    m
 }
@@ -0,0 +1,167 @@
+#[doc(inline)]
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of decimal places an [Amount] can represent
+pub const DECIMALS: u32 = 8;
+/// Number of base units in one whole coin
+pub const UNITS_PER_COIN: u64 = 10u64.pow(DECIMALS);
+
+/// ## Amount
+/// Fixed-point representation of money.
+///
+/// The value is stored as an integer number of base units (1 coin = [UNITS_PER_COIN] units),
+/// which means every value has exactly one encoding, and
+/// arithmetic never accumulates rounding errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    /// The input was empty or contained something other than digits and a single `.`
+    Invalid,
+    /// The input had more than [DECIMALS] decimal places
+    TooPrecise,
+    /// The value does not fit in an [Amount]
+    Overflow,
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    /// creates an amount from a number of base units
+    pub const fn from_units(units: u64) -> Amount {
+        Amount(units)
+    }
+
+    /// creates an amount from a number of whole coins
+    ///
+    /// **outputs:**
+    /// - None if the value overflows
+    pub fn from_coins(coins: u64) -> Option<Amount> {
+        coins.checked_mul(UNITS_PER_COIN).map(Amount)
+    }
+
+    /// returns the number of base units
+    pub const fn units(&self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    /// **outputs:**
+    /// - None if `other` is bigger than `self`
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    /// little endian bytes of the base units, used when hashing
+    pub fn to_le_bytes(&self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    /// Parses a decimal string such as `"12"`, `"0.5"` or `"3.25000000"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (whole, frac) = match s.split_once('.') {
+            Some((w, f)) => (w, f),
+            None => (s, ""),
+        };
+        if whole.is_empty() && frac.is_empty() {
+            return Err(AmountError::Invalid);
+        }
+        if !whole.chars().all(|c| c.is_ascii_digit()) || !frac.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AmountError::Invalid);
+        }
+        if frac.len() > DECIMALS as usize {
+            return Err(AmountError::TooPrecise);
+        }
+
+        let whole_units = if whole.is_empty() {
+            0
+        } else {
+            whole.parse::<u64>().map_err(|_| AmountError::Overflow)?
+                .checked_mul(UNITS_PER_COIN).ok_or(AmountError::Overflow)?
+        };
+        let frac_units = if frac.is_empty() {
+            0
+        } else {
+            // "5" with 8 decimals means 50000000 units
+            frac.parse::<u64>().map_err(|_| AmountError::Invalid)?
+                * 10u64.pow(DECIMALS - frac.len() as u32)
+        };
+
+        whole_units.checked_add(frac_units).map(Amount).ok_or(AmountError::Overflow)
+    }
+}
+
+// Formats the amount in coins, without trailing zeros (e.g. 1.5 instead of 1.50000000)
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let whole = self.0 / UNITS_PER_COIN;
+        let frac = self.0 % UNITS_PER_COIN;
+        if frac == 0 {
+            return write!(f, "{}", whole);
+        }
+        let frac_str = format!("{:0width$}", frac, width = DECIMALS as usize);
+        write!(f, "{}.{}", whole, frac_str.trim_end_matches('0'))
+    }
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmountError::Invalid => write!(f, "not a valid decimal amount"),
+            AmountError::TooPrecise => write!(f, "amounts can have at most {} decimal places", DECIMALS),
+            AmountError::Overflow => write!(f, "amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::marco::amount::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(Amount::from_str("1").unwrap(), Amount::from_units(UNITS_PER_COIN));
+        assert_eq!(Amount::from_str("0.5").unwrap(), Amount::from_units(UNITS_PER_COIN / 2));
+        assert_eq!(Amount::from_str(".25").unwrap().to_string(), "0.25");
+        assert_eq!(Amount::from_str("3.10000000").unwrap().to_string(), "3.1");
+        assert_eq!(Amount::from_str("0.00000001").unwrap(), Amount::from_units(1));
+
+        assert_eq!(Amount::from_str(""), Err(AmountError::Invalid));
+        assert_eq!(Amount::from_str("-1"), Err(AmountError::Invalid));
+        assert_eq!(Amount::from_str("1.2.3"), Err(AmountError::Invalid));
+        assert_eq!(Amount::from_str("0.000000001"), Err(AmountError::TooPrecise));
+        assert_eq!(Amount::from_str("999999999999999999999"), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let a = Amount::from_str("5").unwrap();
+        let b = Amount::from_str("4.5").unwrap();
+
+        assert_eq!(a.checked_sub(b).unwrap().to_string(), "0.5");
+        assert_eq!(b.checked_sub(a), None);
+        assert_eq!(Amount::MAX.checked_add(Amount::from_units(1)), None);
+        assert_eq!(a.checked_add(b).unwrap().to_string(), "9.5");
+    }
+}
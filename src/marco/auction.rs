@@ -3,23 +3,297 @@ use std::fmt;
 
 use sha2::{Digest, Sha512};
 
+use crate::marco::amount::Amount;
+use crate::marco::marco::Data;
+use crate::marco::payload::PayloadHandler;
 use crate::marco::sha512hash::Sha512Hash;
+use crate::proto::{Auction as ProtoAuction, AuctionType as ProtoAuctionType, DutchTerms as ProtoDutchTerms, PriceTerms as ProtoPriceTerms, SealedTerms as ProtoSealedTerms};
+use crate::proto::auction::End as ProtoEnd;
+use crate::proto::sealed_terms::RevealEnd as ProtoRevealEnd;
+use crate::proto::data::DataType as ProtoDataType;
 
+/// ## EndCondition
+/// When an auction stops accepting bids.
+///
+/// Both conditions are checked against the block the bid is included in,
+/// so every node reaches the same result from the chain alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndCondition {
+    /// bids are accepted up to (and including) the block with this index
+    BlockHeight(u64),
+    /// bids are accepted in blocks with a timestamp (seconds since the epoch) before this one
+    Timestamp(u64),
+}
+
+impl EndCondition {
+    /// returns true if a bid included at `height` with `timestamp` is too late
+    pub fn has_passed(&self, height: u64, timestamp: u64) -> bool {
+        match self {
+            EndCondition::BlockHeight(h) => height > *h,
+            EndCondition::Timestamp(t) => timestamp >= *t,
+        }
+    }
+
+    fn to_proto(self) -> ProtoEnd {
+        match self {
+            EndCondition::BlockHeight(h) => ProtoEnd::EndHeight(h),
+            EndCondition::Timestamp(t) => ProtoEnd::EndTimestamp(t),
+        }
+    }
+
+    fn from_proto(end: &ProtoEnd) -> EndCondition {
+        match end {
+            ProtoEnd::EndHeight(h) => EndCondition::BlockHeight(*h),
+            ProtoEnd::EndTimestamp(t) => EndCondition::Timestamp(*t),
+        }
+    }
+}
+
+/// ## SealedTerms
+/// Terms of an auction with sealed bids.
+///
+/// Bids are committed until the auction [EndCondition] and revealed until `reveal_end`,
+/// every commit needs at least `deposit`, which is forfeited if the bid is never revealed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealedTerms {
+    pub reveal_end: EndCondition,
+    pub deposit: Amount,
+}
+
+impl SealedTerms {
+    fn to_proto(self) -> ProtoSealedTerms {
+        ProtoSealedTerms {
+            reveal_end: Some(match self.reveal_end {
+                EndCondition::BlockHeight(h) => ProtoRevealEnd::RevealEndHeight(h),
+                EndCondition::Timestamp(t) => ProtoRevealEnd::RevealEndTimestamp(t),
+            }),
+            deposit: self.deposit.units(),
+        }
+    }
+
+    fn from_proto(terms: &ProtoSealedTerms) -> Option<SealedTerms> {
+        let reveal_end = match terms.reveal_end.as_ref()? {
+            ProtoRevealEnd::RevealEndHeight(h) => EndCondition::BlockHeight(*h),
+            ProtoRevealEnd::RevealEndTimestamp(t) => EndCondition::Timestamp(*t),
+        };
+        Some(SealedTerms { reveal_end, deposit: Amount::from_units(terms.deposit) })
+    }
+}
+
+/// ## DutchTerms
+/// The price of a Dutch auction starts at `start_price` in the block that opens it
+/// and drops by `decrement` every block, down to `floor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DutchTerms {
+    pub start_price: Amount,
+    pub decrement: Amount,
+    pub floor: Amount,
+}
+
+impl DutchTerms {
+    /// price `blocks` blocks after the auction was opened
+    pub fn price_after(&self, blocks: u64) -> Amount {
+        let drop = self.decrement.units().saturating_mul(blocks);
+        Amount::from_units(self.start_price.units().saturating_sub(drop)).max(self.floor)
+    }
+
+    pub fn to_proto(self) -> ProtoDutchTerms {
+        ProtoDutchTerms {
+            start_price: self.start_price.units(),
+            decrement: self.decrement.units(),
+            floor: self.floor.units(),
+        }
+    }
+
+    pub fn from_proto(terms: &ProtoDutchTerms) -> DutchTerms {
+        DutchTerms {
+            start_price: Amount::from_units(terms.start_price),
+            decrement: Amount::from_units(terms.decrement),
+            floor: Amount::from_units(terms.floor),
+        }
+    }
+}
+
+/// ## AuctionType
+/// How the winner of an auction is decided, see [WinnerStrategy](crate::ledger::winner_strategy::WinnerStrategy)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuctionType {
+    /// ascending bids, the highest bid wins and pays its amount
+    #[default]
+    English,
+    /// the price descends every block, the first bid wins
+    Dutch(DutchTerms),
+    /// sealed bids, the highest bid wins and pays the second-highest
+    Vickrey,
+}
+
+impl AuctionType {
+    /// returns false if the type does not fit the rest of the auction
+    pub fn is_valid(&self, sealed: bool, terms: &PriceTerms) -> bool {
+        match self {
+            AuctionType::English => true,
+            AuctionType::Dutch(d) => !sealed && terms.buy_now.is_none()
+                && d.decrement != Amount::ZERO && d.start_price > d.floor && d.floor != Amount::ZERO,
+            AuctionType::Vickrey => sealed,
+        }
+    }
+
+    /// proto kind and Dutch terms
+    pub fn to_proto(self) -> (ProtoAuctionType, Option<ProtoDutchTerms>) {
+        match self {
+            AuctionType::English => (ProtoAuctionType::English, None),
+            AuctionType::Dutch(d) => (ProtoAuctionType::Dutch, Some(d.to_proto())),
+            AuctionType::Vickrey => (ProtoAuctionType::Vickrey, None),
+        }
+    }
+
+    /// None for a Dutch auction without terms or an unknown type
+    pub fn from_proto(kind: i32, dutch: Option<&ProtoDutchTerms>) -> Option<AuctionType> {
+        match ProtoAuctionType::try_from(kind).ok()? {
+            ProtoAuctionType::English => Some(AuctionType::English),
+            ProtoAuctionType::Dutch => Some(AuctionType::Dutch(DutchTerms::from_proto(dutch?))),
+            ProtoAuctionType::Vickrey => Some(AuctionType::Vickrey),
+        }
+    }
+}
+
+impl fmt::Display for AuctionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuctionType::English => write!(f, "English"),
+            AuctionType::Dutch(d) => write!(f, "Dutch from {} down to {}, -{} per block", d.start_price, d.floor, d.decrement),
+            AuctionType::Vickrey => write!(f, "Vickrey"),
+        }
+    }
+}
+
+/// ## PriceTerms
+/// Optional price rules of an auction, checked against every bid.
+///
+/// - `reserve`: bids below it cannot win, open auctions reject them
+/// - `increment`: how much a bid has to add to the highest bid, [MIN_INCREMENT](crate::ledger::auctions::MIN_INCREMENT) if not set
+/// - `buy_now`: the first bid of at least this amount ends the auction, open auctions only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PriceTerms {
+    pub reserve: Option<Amount>,
+    pub increment: Option<Amount>,
+    pub buy_now: Option<Amount>,
+}
+
+impl PriceTerms {
+    /// returns true if no term is set
+    pub fn is_empty(&self) -> bool {
+        *self == PriceTerms::default()
+    }
+
+    /// returns false if the terms contradict each other (or the auction they are part of)
+    pub fn is_valid(&self, sealed: bool) -> bool {
+        if self.increment.is_some_and(|i| i == Amount::ZERO) {
+            return false;
+        }
+        match self.buy_now {
+            None => true,
+            Some(price) => !sealed && self.reserve.is_none_or(|reserve| price >= reserve),
+        }
+    }
+
+    pub fn to_proto(self) -> ProtoPriceTerms {
+        let units = |a: Option<Amount>| a.map(|a| a.units()).unwrap_or(0);
+        ProtoPriceTerms {
+            reserve: units(self.reserve),
+            increment: units(self.increment),
+            buy_now: units(self.buy_now),
+        }
+    }
+
+    pub fn from_proto(terms: &ProtoPriceTerms) -> PriceTerms {
+        let amount = |units: u64| (units > 0).then(|| Amount::from_units(units));
+        PriceTerms {
+            reserve: amount(terms.reserve),
+            increment: amount(terms.increment),
+            buy_now: amount(terms.buy_now),
+        }
+    }
+}
+
+impl fmt::Display for EndCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EndCondition::BlockHeight(h) => write!(f, "after block {}", h),
+            EndCondition::Timestamp(t) => write!(f, "at timestamp {}", t),
+        }
+    }
+}
+
+/// ## Auction
+/// An auction is identified by the hash of the Marco that created it,
+/// so a seller can have any number of them open at the same time
 #[derive(Debug, Clone, PartialEq)]
 pub struct Auction {
-    pub(crate) auction_id : i64,
     pub(crate) seller_id: String,
-    pub(crate) amount: f64,
+    pub(crate) amount: Amount,
+    pub(crate) end: EndCondition,
+    /// set for auctions with sealed bids
+    pub(crate) sealed: Option<SealedTerms>,
+    pub(crate) terms: PriceTerms,
+    pub(crate) auction_type: AuctionType,
+    /// content hash of the [ItemDescriptor](crate::auction::item::ItemDescriptor) stored in the DHT
+    pub(crate) item: Option<String>,
 }
 
 impl Auction{
-    pub fn new( seller_id: String, amount: f64) -> Auction{
+    pub fn new( seller_id: String, amount: Amount, end: EndCondition) -> Auction{
         Auction{
-            auction_id: 1,
             seller_id,
-            amount
+            amount,
+            end,
+            sealed: None,
+            terms: PriceTerms::default(),
+            auction_type: AuctionType::English,
+            item: None,
         }
     }
+
+    /// creates an auction with sealed bids, see [SealedTerms]
+    pub fn sealed( seller_id: String, amount: Amount, end: EndCondition, terms: SealedTerms) -> Auction{
+        Auction{
+            seller_id,
+            amount,
+            end,
+            sealed: Some(terms),
+            terms: PriceTerms::default(),
+            auction_type: AuctionType::English,
+            item: None,
+        }
+    }
+
+    /// sets the reserve price, increment and buy-now price of the auction
+    pub fn with_terms(mut self, terms: PriceTerms) -> Auction {
+        self.terms = terms;
+        self
+    }
+
+    pub fn get_sealed(&self) -> Option<SealedTerms> { self.sealed }
+
+    pub fn get_end(&self) -> EndCondition { self.end }
+
+    /// sets how the winner is decided, English by default
+    pub fn with_type(mut self, auction_type: AuctionType) -> Auction {
+        self.auction_type = auction_type;
+        self
+    }
+
+    /// commits the content hash of the item sold with the coins
+    pub fn with_item(mut self, hash: String) -> Auction {
+        self.item = Some(hash);
+        self
+    }
+
+    pub fn get_terms(&self) -> PriceTerms { self.terms }
+
+    pub fn get_item(&self) -> Option<&String> { self.item.as_ref() }
+
+    pub fn get_type(&self) -> AuctionType { self.auction_type }
 } 
 
 impl Sha512Hash for Auction {
@@ -27,6 +301,39 @@ impl Sha512Hash for Auction {
         let mut hasher = Sha512::new();
         hasher.update(self.seller_id.as_bytes());
         hasher.update(self.amount.to_le_bytes());
+        match self.end {
+            EndCondition::BlockHeight(h) => { hasher.update([0u8]); hasher.update(h.to_le_bytes()); },
+            EndCondition::Timestamp(t) => { hasher.update([1u8]); hasher.update(t.to_le_bytes()); },
+        }
+        if let Some(terms) = self.sealed {
+            hasher.update(b"sealed");
+            match terms.reveal_end {
+                EndCondition::BlockHeight(h) => { hasher.update([0u8]); hasher.update(h.to_le_bytes()); },
+                EndCondition::Timestamp(t) => { hasher.update([1u8]); hasher.update(t.to_le_bytes()); },
+            }
+            hasher.update(terms.deposit.to_le_bytes());
+        }
+        match self.auction_type {
+            AuctionType::English => {},
+            AuctionType::Dutch(d) => {
+                hasher.update(b"dutch");
+                for price in [d.start_price, d.decrement, d.floor] {
+                    hasher.update(price.to_le_bytes());
+                }
+            },
+            AuctionType::Vickrey => hasher.update(b"vickrey"),
+        }
+        // Auctions without price terms keep the hash they had before the terms existed
+        if !self.terms.is_empty() {
+            hasher.update(b"terms");
+            for term in [self.terms.reserve, self.terms.increment, self.terms.buy_now] {
+                hasher.update(term.map(|a| a.units()).unwrap_or(0).to_le_bytes());
+            }
+        }
+        if let Some(item) = &self.item {
+            hasher.update(b"item");
+            hasher.update(item.as_bytes());
+        }
 
         let hash_result = hasher.finalize();
 
@@ -41,7 +348,108 @@ impl Sha512Hash for Auction {
 // Implementing the Display trait for the Auction struct
 impl fmt::Display for Auction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"Auction {{seller_id: {}, amount: {} }}",
-            self.seller_id, self.amount)
+        match self.sealed {
+            None => write!(f,"Auction {{seller_id: {}, amount: {}, ends: {}",
+                self.seller_id, self.amount, self.end)?,
+            Some(terms) => write!(f,"Auction {{seller_id: {}, amount: {}, sealed bids until: {}, reveals until: {}, deposit: {}",
+                self.seller_id, self.amount, self.end, terms.reveal_end, terms.deposit)?,
+        }
+        if let Some(reserve) = self.terms.reserve {
+            write!(f, ", reserve: {}", reserve)?;
+        }
+        if let Some(increment) = self.terms.increment {
+            write!(f, ", increment: {}", increment)?;
+        }
+        if let Some(buy_now) = self.terms.buy_now {
+            write!(f, ", buy now: {}", buy_now)?;
+        }
+        if self.auction_type != AuctionType::English {
+            write!(f, ", type: {}", self.auction_type)?;
+        }
+        if let Some(item) = &self.item {
+            write!(f, ", item: {}", item)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+/// [PayloadHandler] for [Data::CreateAuction]
+pub struct AuctionHandler;
+
+impl AuctionHandler {
+    pub const KIND: &'static str = "create_auction";
+}
+
+impl PayloadHandler for AuctionHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::CreateAuction(a) => a.seller_id.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::CreateAuction(a) => !a.seller_id.is_empty() && !a.amount.is_zero()
+                && a.terms.is_valid(a.sealed.is_some())
+                && a.auction_type.is_valid(a.sealed.is_some(), &a.terms)
+                // The item hash is a Sha512 in hex
+                && a.item.as_ref().is_none_or(|h| h.len() == 128 && h.chars().all(|c| c.is_ascii_hexdigit())),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::CreateAuction(a) => a.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::CreateAuction(a) => a.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::CreateAuction(a) => {
+                let (auction_type, dutch) = a.auction_type.to_proto();
+                Some(ProtoDataType::CreateAuction(ProtoAuction {
+                    seller_id: a.seller_id.clone(),
+                    amount: a.amount.units(),
+                    end: Some(a.end.to_proto()),
+                    sealed: a.sealed.map(SealedTerms::to_proto),
+                    terms: (!a.terms.is_empty()).then(|| a.terms.to_proto()),
+                    auction_type: auction_type as i32,
+                    dutch,
+                    item: a.item.clone().unwrap_or_default(),
+                }))
+            },
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            // Auctions without an end condition are not accepted
+            ProtoDataType::CreateAuction(a) => Some(Data::CreateAuction(Auction {
+                seller_id: a.seller_id.clone(),
+                amount: Amount::from_units(a.amount),
+                end: EndCondition::from_proto(a.end.as_ref()?),
+                sealed: match a.sealed.as_ref() {
+                    None => None,
+                    Some(terms) => Some(SealedTerms::from_proto(terms)?),
+                },
+                terms: a.terms.as_ref().map(PriceTerms::from_proto).unwrap_or_default(),
+                auction_type: AuctionType::from_proto(a.auction_type, a.dutch.as_ref())?,
+                item: (!a.item.is_empty()).then(|| a.item.clone()),
+            })),
+            _ => None,
+        }
     }
 }
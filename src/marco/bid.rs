@@ -3,18 +3,23 @@ use std::fmt;
 
 use sha2::{Digest, Sha512};
 
+use crate::marco::amount::Amount;
+use crate::marco::marco::Data;
+use crate::marco::payload::PayloadHandler;
 use crate::marco::sha512hash::Sha512Hash;
+use crate::proto::Bid as ProtoBid;
+use crate::proto::data::DataType as ProtoDataType;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Bid {
     pub auction_id: String,
     pub buyer_id: String,
     pub seller_id: String,
-    pub amount: f64,
+    pub amount: Amount,
 }
 
 impl Bid{
-    pub fn new(auction_id:String, buyer_id: String, seller_id: String, amount: f64) -> Bid{
+    pub fn new(auction_id:String, buyer_id: String, seller_id: String, amount: Amount) -> Bid{
         Bid {
             buyer_id,
             seller_id,
@@ -50,3 +55,66 @@ impl fmt::Display for Bid {
         )
     }
 }
+
+/// [PayloadHandler] for [Data::Bid]
+pub struct BidHandler;
+
+impl BidHandler {
+    pub const KIND: &'static str = "bid";
+}
+
+impl PayloadHandler for BidHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::Bid(b) => b.buyer_id.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::Bid(b) => !b.auction_id.is_empty() && !b.buyer_id.is_empty() && !b.amount.is_zero(),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::Bid(b) => b.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::Bid(b) => b.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::Bid(b) => Some(ProtoDataType::Bid(ProtoBid {
+                auction_id: b.auction_id.clone(),
+                buyer_id: b.buyer_id.clone(),
+                seller_id: b.seller_id.clone(),
+                amount: b.amount.units(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::Bid(b) => Some(Data::Bid(Bid {
+                auction_id: b.auction_id.clone(),
+                buyer_id: b.buyer_id.clone(),
+                seller_id: b.seller_id.clone(),
+                amount: Amount::from_units(b.amount),
+            })),
+            _ => None,
+        }
+    }
+}
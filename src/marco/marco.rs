@@ -2,37 +2,54 @@
 
 use std::fmt;
 use std::fmt::Display;
+use std::str::FromStr;
 use std::time::SystemTime;
 
 use base64::{Engine as _, engine::general_purpose};
 use log::debug;
-use rsa::{pkcs1v15::SigningKey, RsaPublicKey};
-use rsa::pss::VerifyingKey;
 use rsa::sha2::{Digest, Sha256};
-use rsa::signature::Verifier;
-use x509_certificate::Signer;
 
-use crate::marco::auction::Auction;
-use crate::marco::bid::Bid;
+use crate::marco::auction::{Auction, AuctionHandler};
+use crate::marco::bid::{Bid, BidHandler};
+use crate::marco::payload::{Payload, registry};
+use crate::marco::sealed_bid::{BidCommit, BidCommitHandler, BidReveal, BidRevealHandler};
 use crate::marco::sha512hash::Sha512Hash;
-use crate::marco::transaction::Transaction;
-use crate::marco::winner::Winner;
+use crate::marco::signature::{PublicKey, SecretKey, SignatureAlgorithm};
+use crate::marco::transaction::{Transaction, TransactionHandler};
+use crate::marco::winner::{Winner, WinnerHandler};
+
+/// Identifier of the network a Marco was created for.
+/// Marcos signed for a different chain are rejected by the [Blockchain](crate::ledger::blockchain::Blockchain)
+pub const CHAIN_ID: u32 = 1;
 
 ///## MARCO
 #[derive(Debug, Clone, PartialEq)]
 pub struct Marco {
     pub(crate) hash: String,
+    /// `<algorithm>:<base64 signature>`, signatures without a tag are RSA
     pub(crate) signature: String,
+    /// encoded [PublicKey] of the signer, empty for Marcos signed before keys were embedded
+    pub(crate) public_key: String,
     pub(crate) timestamp: SystemTime,
+    /// per-sender sequence number, a sender never reuses a nonce
+    pub(crate) nonce: u64,
+    pub(crate) chain_id: u32,
     pub data : Data
 }
 
+/// The content of a [Marco].
+///
+/// Each kind of data is handled by a [PayloadHandler](crate::marco::payload::PayloadHandler)
+/// from the [registry]; new types should use [Data::Custom] instead of a new variant
 #[derive(Debug, Clone, PartialEq)]
 pub enum Data {
     Transaction(Transaction),
     CreateAuction(Auction),
     Bid(Bid),
-    Winner(Winner)
+    Winner(Winner),
+    Custom(Payload),
+    BidCommit(BidCommit),
+    BidReveal(BidReveal)
 }
 
 impl Marco{
@@ -45,35 +62,54 @@ impl Marco{
         return self.hash.clone();
     }
     
-    pub fn sign(&mut self, skey:SigningKey<Sha256>) -> String {
-        if self.hash == "".to_string() {
-            self.calc_hash();
-        }
+    /// embeds the public key of `skey` and signs the Marco with it,
+    /// the hash is recomputed since it covers the embedded key
+    pub fn sign(&mut self, skey: &SecretKey) -> String {
+        self.public_key = skey.public_key().encode();
+        self.hash = self.to_hash();
 
         let signature = skey.sign(&self.hash.clone().into_bytes());
-        // Convert Signature to Box<[u8]> using the From trait
-        let boxed_bytes: Box<[u8]> = Box::from(signature);
-
-        // Convert Box<[u8]> to Vec<u8> to manipulate it as a vector
-        let byte_vec: Vec<u8> = boxed_bytes.into();
-
-        // Convert Vec<u8> to String
-        self.signature = general_purpose::STANDARD.encode(byte_vec);
+        self.signature = format!("{}:{}", skey.algorithm().tag(), general_purpose::STANDARD.encode(signature));
 
         return self.signature.clone();
     }
 
-    pub fn verify(&self, _pkey: RsaPublicKey) -> bool{
+    /// verifies the signature with `pkey`, the algorithm tag of the signature must match the key,
+    /// the key must be the one embedded in the Marco (if any) and its [id](PublicKey::id) must be the sender
+    pub fn verify(&self, pkey: &PublicKey) -> bool{
         if self.hash == "".to_string() {
             debug!("DEBUG MARCO::VERIFY => Empty hash");
             return false;
         }
-        if self.hash != self.to_hash() {
+        if !self.public_key.is_empty() && self.public_key != pkey.encode() {
+            debug!("DEBUG MARCO::VERIFY => Checked with a key that is not the embedded one");
+            return false;
+        }
+        if self.data.sender() != pkey.id() {
+            debug!("DEBUG MARCO::VERIFY => The key of {} cannot sign for {}", pkey.id(), self.data.sender());
+            return false;
+        }
+        // Data of a newer version cannot be hashed here, it has no effects on this node
+        if self.hash != self.to_hash() && !registry().is_foreign(&self.data) {
             debug!("DEBUG MARCO::VERIFY => Invalid Hash\nhash:{} <-> data to hash:{}", self.hash.clone(), self.data.to_hash());
             return false;
         }
-        let verifying_key: VerifyingKey<Sha256> = VerifyingKey::new(_pkey);
-        let signature_bytes = match general_purpose::STANDARD.decode(self.signature.clone()) {
+        let (algorithm, encoded) = match self.signature.split_once(':') {
+            Some((tag, sig)) => match SignatureAlgorithm::from_str(tag) {
+                Ok(algorithm) => (algorithm, sig),
+                Err(_) => {
+                    debug!("DEBUG MARCO::VERIFY => Unknown signature algorithm {}", tag);
+                    return false
+                }
+            },
+            // Marcos signed before the tag was introduced are RSA
+            None => (SignatureAlgorithm::Rsa, self.signature.as_str()),
+        };
+        if algorithm != pkey.algorithm() {
+            debug!("DEBUG MARCO::VERIFY => Signed with {} but the key is {}", algorithm, pkey.algorithm());
+            return false;
+        }
+        let signature_bytes = match general_purpose::STANDARD.decode(encoded) {
             Ok(bytes) => bytes,
             Err(_) => {
                 debug!("DEBUG MARCO::VERIFY => Failed to decode base64");
@@ -81,70 +117,68 @@ impl Marco{
             }
         };
 
-        let slice: &[u8] = &signature_bytes;
-        match verifying_key.verify(self.hash.clone().as_bytes(), &rsa::pss::Signature::try_from(slice).expect("Failed unwraping Signature")) {
-            Ok(_) => {
-                println!("Signature verified with success");
-                true
-            },  // Signature is valid
-            Err(_) => {
-                true
-            }, // Signature is invalid
-        }
+        return pkey.verify(self.hash.clone().as_bytes(), &signature_bytes);
     }
 
-    pub fn from_transaction(t: Transaction) -> Marco {
+    /// returns the public key embedded by [Marco::sign], if any
+    pub fn signer_key(&self) -> Option<PublicKey> {
+        PublicKey::decode(&self.public_key)
+    }
+
+    fn new(data: Data, nonce: u64) -> Marco {
         let mut m = Marco {
             hash: "".to_string(),
             signature: "".to_string(),
+            public_key: "".to_string(),
             timestamp: SystemTime::now(),
-            data: Data::Transaction(t)
+            nonce,
+            chain_id: CHAIN_ID,
+            data
         };
         m.calc_hash();
         return m;
+    }
 
+    /// `nonce` is the sequence number of this Marco for the sender (see [Data::sender])
+    pub fn from_transaction(t: Transaction, nonce: u64) -> Marco {
+        Self::new(Data::Transaction(t), nonce)
     }
-    pub fn from_winner(t: Winner) -> Marco {
-        let mut m = Marco {
-            hash: "".to_string(),
-            signature: "".to_string(),
-            timestamp: SystemTime::now(),
-            data: Data::Winner(t)
-        };
-        m.calc_hash();
-        return m;
 
+    pub fn from_winner(w: Winner, nonce: u64) -> Marco {
+        Self::new(Data::Winner(w), nonce)
     }
 
-    pub fn from_auction(a: Auction) -> Marco {
-        let mut m = Marco {
-            hash: "".to_string(),
-            signature: "".to_string(),
-            timestamp: SystemTime::now(),
-            data: Data::CreateAuction(a)
-        };
-        m.calc_hash();
-        return m;
+    pub fn from_auction(a: Auction, nonce: u64) -> Marco {
+        Self::new(Data::CreateAuction(a), nonce)
     }
 
-    pub fn from_bid(b: Bid) -> Marco {
-        let mut m = Marco {
-            hash: "".to_string(),
-            signature: "".to_string(),
-            timestamp: SystemTime::now(),
-            data: Data::Bid(b)
-        };
-        m.calc_hash();
-        return m;
+    pub fn from_bid(b: Bid, nonce: u64) -> Marco {
+        Self::new(Data::Bid(b), nonce)
+    }
+
+    pub fn from_bid_commit(c: BidCommit, nonce: u64) -> Marco {
+        Self::new(Data::BidCommit(c), nonce)
+    }
+
+    pub fn from_bid_reveal(r: BidReveal, nonce: u64) -> Marco {
+        Self::new(Data::BidReveal(r), nonce)
+    }
+
+    pub fn from_payload(p: Payload, nonce: u64) -> Marco {
+        Self::new(Data::Custom(p), nonce)
     }
 
+    /// The hash (and therefore the signature) covers the data, the full timestamp,
+    /// the nonce, the chain id and the embedded public key, so two Marcos with the same data never collide,
+    /// a signed Marco cannot be replayed on another chain and its key cannot be swapped
     pub fn to_hash(&self) -> String {
         let mut hasher = Sha256::new();
         hasher.update(self.data.to_hash().into_bytes());
         let duration_since_epoch = self.timestamp.duration_since(std::time::UNIX_EPOCH).expect("Time went backwards");
-        // Convert the duration to seconds (or any other unit you prefer)
-        let seconds_since_epoch = duration_since_epoch.as_secs();
-        hasher.update(seconds_since_epoch.to_string());
+        hasher.update(duration_since_epoch.as_nanos().to_le_bytes());
+        hasher.update(self.nonce.to_le_bytes());
+        hasher.update(self.chain_id.to_le_bytes());
+        hasher.update(self.public_key.as_bytes());
         let hash_result = hasher.finalize();
 
         return hash_result.iter()
@@ -155,35 +189,48 @@ impl Marco{
     
     pub fn get_signature(&self) -> String { self.signature.clone()}
     pub fn get_hash(&self) -> String { self.hash.clone()}
+    pub fn get_nonce(&self) -> u64 { self.nonce }
+    pub fn get_chain_id(&self) -> u32 { self.chain_id }
+}
+
+impl Data {
+    /// returns the id of whoever created (and signs) the Marco
+    pub fn sender(&self) -> String {
+        registry().sender(self)
+    }
+
+    /// returns the name of the handler responsible for this data
+    pub fn kind(&self) -> String {
+        match self {
+            Data::Transaction(_) => TransactionHandler::KIND.to_string(),
+            Data::CreateAuction(_) => AuctionHandler::KIND.to_string(),
+            Data::Bid(_) => BidHandler::KIND.to_string(),
+            Data::Winner(_) => WinnerHandler::KIND.to_string(),
+            Data::BidCommit(_) => BidCommitHandler::KIND.to_string(),
+            Data::BidReveal(_) => BidRevealHandler::KIND.to_string(),
+            Data::Custom(p) => p.kind.clone(),
+        }
+    }
+
+    /// returns the version tag of the data, the built-in types are all at version 1
+    /// (a newer one received from another node is kept as a [Payload](crate::marco::payload::Payload))
+    pub fn version(&self) -> u32 {
+        match self {
+            Data::Custom(p) => p.version,
+            _ => 1,
+        }
+    }
 }
 
 impl Display for Marco { 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.data.clone() {
-            Data::Transaction(t) =>
-                write!(f, "Data:{}, with hash {} and signature {}",
-                       t.to_string(), self.hash, self.signature),
-            Data::CreateAuction(a) => 
-                write!(f, "Data:{}, with hash {} and signature {}", 
-                       a.to_string(), self.hash, self.signature),
-            Data::Bid(b) => 
-                write!(f, "Data:{}, with hash {} and signature {}", 
-                       b.to_string(), self.hash, self.signature),
-            Data::Winner(b) => 
-                write!(f, "Data:{}, with hash {} and signature {}", 
-                       b.to_string(), self.hash, self.signature),
-  
-        }
+        write!(f, "Data:{}, with hash {} and signature {}",
+               registry().describe(&self.data), self.hash, self.signature)
     }
 }
 
 impl Sha512Hash for Data {
     fn to_hash(&self) -> String {
-        match self {
-            Data::Transaction(t) => t.to_hash(),
-            Data::Winner(w) => w.to_hash(),
-            Data::CreateAuction(a) => a.to_hash(),
-            Data::Bid(b) => b.to_hash(),
-        }
+        registry().hash(self)
     }
 }
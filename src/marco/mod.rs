@@ -4,4 +4,7 @@ pub mod transaction;
 pub mod auction;
 pub mod winner;
 pub mod bid;
-
+pub mod sealed_bid;
+pub mod amount;
+pub mod payload;
+pub mod signature;
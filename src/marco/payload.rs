@@ -0,0 +1,371 @@
+#[doc(inline)]
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use prost::Message;
+use sha2::{Digest, Sha512};
+
+use crate::marco::amount::Amount;
+use crate::marco::auction::AuctionHandler;
+use crate::marco::bid::BidHandler;
+use crate::marco::marco::Data;
+use crate::marco::sealed_bid::{BidCommitHandler, BidRevealHandler};
+use crate::marco::transaction::TransactionHandler;
+use crate::marco::winner::WinnerHandler;
+use crate::proto::{Data as ProtoData, Payload as ProtoPayload};
+use crate::proto::data::DataType as ProtoDataType;
+
+/// ## Payload
+/// Marco data of a type that is not built into the [Data] enum.
+///
+/// The body is opaque to the ledger, only the [PayloadHandler] registered for `kind`
+/// knows how to read it. Nodes without that handler still hash, store and relay it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payload {
+    pub kind: String,
+    pub version: u32,
+    pub sender: String,
+    pub body: Vec<u8>,
+}
+
+/// ## Effect
+/// A change to account balances caused by a Marco once it is confirmed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// adds to the available balance
+    Credit { account: String, amount: Amount },
+    /// takes from the available balance
+    Debit { account: String, amount: Amount },
+    /// moves from the available to the locked balance (escrow)
+    Lock { account: String, amount: Amount },
+    /// moves from the locked back to the available balance
+    Release { account: String, amount: Amount },
+    /// takes from the locked balance, paired with a [Effect::Credit] to whoever receives it
+    Spend { account: String, amount: Amount },
+}
+
+/// ## PayloadHandler
+/// Everything the node needs to know about one type of Marco data.
+///
+/// The default methods treat the data as an opaque [Payload], which is what
+/// new payload types should use so that nodes that do not know them can still
+/// compute the same hash. The built-in types override every method.
+pub trait PayloadHandler: Send + Sync {
+    /// name of the payload type, unique within the [PayloadRegistry]
+    fn kind(&self) -> &'static str;
+
+    /// highest version of the payload this handler understands
+    fn version(&self) -> u32 { 1 }
+
+    /// id of whoever created (and signs) the data
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::Custom(p) => p.sender.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    /// checks the data is well formed, regardless of the ledger state
+    fn validate(&self, _data: &Data) -> bool { true }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::Custom(p) => {
+                let mut hasher = Sha512::new();
+                hasher.update(p.kind.as_bytes());
+                hasher.update(p.version.to_le_bytes());
+                hasher.update(p.sender.as_bytes());
+                hasher.update(&p.body);
+                let hash_result = hasher.finalize();
+
+                hash_result.iter()
+                    .map(|byte| format!("{:02x}",byte))
+                    .collect::<Vec<String>>()
+                    .join("")
+            }
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::Custom(p) => format!("Payload {{ kind: {}, version: {}, sender: {}, size: {} bytes }}",
+                                       p.kind, p.version, p.sender, p.body.len()),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::Custom(p) => Some(ProtoDataType::Custom(ProtoPayload {
+                kind: p.kind.clone(),
+                sender: p.sender.clone(),
+                body: p.body.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// `version` is the version tag the sender attached to the data
+    fn decode(&self, data_type: &ProtoDataType, version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::Custom(p) => Some(Data::Custom(Payload {
+                kind: p.kind.clone(),
+                version,
+                sender: p.sender.clone(),
+                body: p.body.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// balance changes caused by the data once it is confirmed
+    fn effects(&self, _data: &Data) -> Vec<Effect> { Vec::new() }
+}
+
+/// Handler used for payloads nobody registered a handler for
+#[derive(Clone)]
+struct OpaqueHandler;
+
+impl PayloadHandler for OpaqueHandler {
+    fn kind(&self) -> &'static str { "opaque" }
+}
+
+/// ## PayloadRegistry
+/// Maps each payload kind to its [PayloadHandler].
+///
+/// Data of an unknown kind, or of a version newer than its handler, is handled
+/// as an opaque [Payload]: it is accepted and relayed but has no effects.
+/// A built-in type of a newer version keeps its version, its sender and the data as it was received in the body,
+/// see [PayloadRegistry::is_foreign]
+#[derive(Clone)]
+pub struct PayloadRegistry {
+    handlers: HashMap<String, Arc<dyn PayloadHandler>>,
+    opaque: OpaqueHandler,
+}
+
+impl PayloadRegistry {
+    /// creates a registry with no handlers
+    pub fn new() -> Self {
+        PayloadRegistry {
+            handlers: HashMap::new(),
+            opaque: OpaqueHandler,
+        }
+    }
+
+    /// creates a registry with the handlers for the built-in [Data] types
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(TransactionHandler));
+        registry.register(Box::new(AuctionHandler));
+        registry.register(Box::new(BidHandler));
+        registry.register(Box::new(WinnerHandler));
+        registry.register(Box::new(BidCommitHandler));
+        registry.register(Box::new(BidRevealHandler));
+        registry
+    }
+
+    /// **outputs:**
+    /// - false if a handler for the same kind already exists, in which case nothing changes
+    pub fn register(&mut self, handler: Box<dyn PayloadHandler>) -> bool {
+        if self.handlers.contains_key(handler.kind()) {
+            return false;
+        }
+        self.handlers.insert(handler.kind().to_string(), Arc::from(handler));
+        true
+    }
+
+    /// returns the handler able to fully understand the data,
+    /// or the opaque handler if there is none
+    pub fn handler(&self, data: &Data) -> &dyn PayloadHandler {
+        match self.handlers.get(&data.kind()) {
+            Some(h) if data.version() <= h.version() => h.as_ref(),
+            _ => &self.opaque,
+        }
+    }
+
+    pub fn is_known(&self, data: &Data) -> bool {
+        self.handlers.get(&data.kind()).is_some_and(|h| data.version() <= h.version())
+    }
+
+    pub fn sender(&self, data: &Data) -> String { self.handler(data).sender(data) }
+
+    pub fn validate(&self, data: &Data) -> bool { self.handler(data).validate(data) }
+
+    pub fn hash(&self, data: &Data) -> String { self.handler(data).hash(data) }
+
+    pub fn describe(&self, data: &Data) -> String { self.handler(data).describe(data) }
+
+    pub fn effects(&self, data: &Data) -> Vec<Effect> { self.handler(data).effects(data) }
+
+    pub fn encode(&self, data: &Data) -> ProtoData {
+        if let Data::Custom(p) = data {
+            // A built-in type too new for this node is sent again as it was received
+            if Self::is_builtin(&p.kind) {
+                if let Ok(received) = ProtoData::decode(p.body.as_slice()) {
+                    return received;
+                }
+            }
+        }
+        ProtoData {
+            data_type: self.handler(data).encode(data),
+            version: data.version(),
+            kind: data.kind(),
+        }
+    }
+
+    /// **outputs:**
+    /// - None if the data has no content or no handler can read it
+    pub fn decode(&self, proto_data: &ProtoData) -> Option<Data> {
+        let data_type = proto_data.data_type.as_ref()?;
+        if let ProtoDataType::Custom(_) = data_type {
+            // Custom payloads never need a handler to be decoded
+            return self.opaque.decode(data_type, proto_data.version);
+        }
+        if let Some(h) = self.handlers.get(&proto_data.kind) {
+            if proto_data.version > h.version() && Self::is_builtin(&proto_data.kind) {
+                // The built-in types have no version of their own, the received data keeps it.
+                // The fields this node knows are enough to tell who signed it
+                let sender = h.decode(data_type, proto_data.version).map(|d| h.sender(&d)).unwrap_or_default();
+                return Some(Data::Custom(Payload {
+                    kind: proto_data.kind.clone(),
+                    version: proto_data.version,
+                    sender,
+                    body: proto_data.encode_to_vec(),
+                }));
+            }
+            if let Some(data) = h.decode(data_type, proto_data.version) {
+                return Some(data);
+            }
+        }
+        // Older nodes do not send the kind
+        self.handlers.values().find_map(|h| h.decode(data_type, proto_data.version))
+    }
+
+    /// true for built-in data of a version newer than this node understands.
+    /// Only the nodes that know the version can compute its hash, the others take the signed hash as it is
+    pub fn is_foreign(&self, data: &Data) -> bool {
+        matches!(data, Data::Custom(p) if Self::is_builtin(&p.kind))
+    }
+
+    /// true for the kinds of the built-in [Data] types, which are not sent as a [ProtoPayload]
+    fn is_builtin(kind: &str) -> bool {
+        [TransactionHandler::KIND, AuctionHandler::KIND, BidHandler::KIND,
+         WinnerHandler::KIND, BidCommitHandler::KIND, BidRevealHandler::KIND].contains(&kind)
+    }
+}
+
+impl Default for PayloadRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The registry is never changed in place: [register] swaps in an updated copy,
+/// so the lock is only held to take or replace the [Arc], never while a handler runs
+static REGISTRY: OnceLock<RwLock<Arc<PayloadRegistry>>> = OnceLock::new();
+
+fn registry_lock() -> &'static RwLock<Arc<PayloadRegistry>> {
+    REGISTRY.get_or_init(|| RwLock::new(Arc::new(PayloadRegistry::with_builtin())))
+}
+
+/// returns the registry used by this node, initialized with the built-in handlers.
+///
+/// It holds no lock: handlers may call it again, and [register] can run while it is in use
+/// (the handlers it adds are seen by the next call)
+pub fn registry() -> Arc<PayloadRegistry> {
+    registry_lock().read().expect("Payload registry lock poisoned").clone()
+}
+
+/// adds a handler to the registry used by this node
+///
+/// **outputs:**
+/// - false if a handler for the same kind already exists
+pub fn register(handler: Box<dyn PayloadHandler>) -> bool {
+    let mut current = registry_lock().write().expect("Payload registry lock poisoned");
+    let mut updated = PayloadRegistry::clone(&current);
+    let added = updated.register(handler);
+    *current = Arc::new(updated);
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::marco::payload::*;
+
+    struct NoteHandler;
+
+    impl PayloadHandler for NoteHandler {
+        fn kind(&self) -> &'static str { "note" }
+        fn validate(&self, data: &Data) -> bool {
+            match data {
+                Data::Custom(p) => !p.body.is_empty(),
+                _ => false,
+            }
+        }
+    }
+
+    fn note(version: u32, body: &str) -> Data {
+        Data::Custom(Payload {
+            kind: "note".to_string(),
+            version,
+            sender: "alice".to_string(),
+            body: body.as_bytes().to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_unknown_payload_roundtrip() {
+        let registry = PayloadRegistry::with_builtin();
+        let data = note(1, "hello");
+
+        assert!(!registry.is_known(&data));
+        assert!(registry.validate(&data));
+        assert_eq!(registry.sender(&data), "alice");
+
+        let decoded = registry.decode(&registry.encode(&data)).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(registry.hash(&decoded), registry.hash(&data));
+        assert!(registry.decode(&ProtoData { data_type: None, version: 1, kind: "note".to_string() }).is_none());
+    }
+
+    #[test]
+    fn test_registered_payload() {
+        let mut registry = PayloadRegistry::with_builtin();
+        let unknown_hash = registry.hash(&note(1, ""));
+        assert!(registry.register(Box::new(NoteHandler)));
+        assert!(!registry.register(Box::new(NoteHandler)));
+
+        assert!(registry.is_known(&note(1, "")));
+        assert!(!registry.validate(&note(1, "")));
+        // Nodes with and without the handler agree on the hash
+        assert_eq!(registry.hash(&note(1, "")), unknown_hash);
+
+        // A newer version than the handler knows is treated as opaque
+        assert!(!registry.is_known(&note(2, "")));
+        assert!(registry.validate(&note(2, "")));
+    }
+
+    #[test]
+    fn test_builtin_keeps_version() {
+        let builtin = PayloadRegistry::with_builtin();
+        let transaction = Data::Transaction(crate::marco::transaction::Transaction::new(
+            Amount::from_units(10), "alice".to_string(), Amount::from_units(8), "bob".to_string()).unwrap());
+        let mut proto = builtin.encode(&transaction);
+        assert_eq!(builtin.decode(&proto).unwrap(), transaction);
+
+        // A newer version is kept, without effects, and relayed as it was received
+        proto.version = 2;
+        let decoded = builtin.decode(&proto).unwrap();
+        assert_eq!((decoded.kind(), decoded.version()), ("transaction".to_string(), 2));
+        assert!(!builtin.is_known(&decoded));
+        assert!(builtin.effects(&decoded).is_empty());
+        assert_eq!(builtin.encode(&decoded), proto);
+
+        // Handlers can use the registry, and register while it is in use
+        let current = registry();
+        assert!(register(Box::new(NoteHandler)));
+        assert!(!current.is_known(&note(1, "")));
+        assert!(registry().is_known(&note(1, "")));
+    }
+}
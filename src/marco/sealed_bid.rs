@@ -0,0 +1,278 @@
+#[doc(inline)]
+use std::fmt;
+
+use sha2::{Digest, Sha512};
+
+use crate::marco::amount::Amount;
+use crate::marco::marco::Data;
+use crate::marco::payload::PayloadHandler;
+use crate::marco::sha512hash::Sha512Hash;
+use crate::proto::{BidCommit as ProtoBidCommit, BidReveal as ProtoBidReveal};
+use crate::proto::data::DataType as ProtoDataType;
+
+/// ## BidCommit
+/// Sealed bid, only the hash of the amount is public until it is revealed.
+///
+/// The deposit is forfeited if the bid is not revealed before the reveal phase ends
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidCommit {
+    pub auction_id: String,
+    pub buyer_id: String,
+    pub seller_id: String,
+    /// see [BidCommit::commitment]
+    pub commitment: String,
+    pub deposit: Amount,
+}
+
+/// ## BidReveal
+/// Opens the [BidCommit] of `buyer_id` for the auction
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidReveal {
+    pub auction_id: String,
+    pub buyer_id: String,
+    pub amount: Amount,
+    pub salt: String,
+}
+
+impl BidCommit {
+    pub fn new(auction_id: String, buyer_id: String, seller_id: String, amount: Amount, salt: &String, deposit: Amount) -> BidCommit {
+        BidCommit {
+            commitment: Self::commitment(&auction_id, &buyer_id, amount, salt),
+            auction_id,
+            buyer_id,
+            seller_id,
+            deposit,
+        }
+    }
+
+    /// hash of the sealed amount, bound to the auction and the buyer
+    /// so a commitment cannot be copied by someone else
+    pub fn commitment(auction_id: &String, buyer_id: &String, amount: Amount, salt: &String) -> String {
+        let mut hasher = Sha512::new();
+        hasher.update(auction_id.as_bytes());
+        hasher.update(buyer_id.as_bytes());
+        hasher.update(amount.to_le_bytes());
+        hasher.update(salt.as_bytes());
+        let hash_result = hasher.finalize();
+
+        hash_result.iter()
+            .map(|byte| format!("{:02x}",byte))
+            .collect::<Vec<String>>()
+            .join("")
+    }
+
+    /// returns true if the reveal opens this commit
+    pub fn is_opened_by(&self, r: &BidReveal) -> bool {
+        self.auction_id == r.auction_id && self.buyer_id == r.buyer_id
+            && self.commitment == Self::commitment(&r.auction_id, &r.buyer_id, r.amount, &r.salt)
+    }
+}
+
+impl BidReveal {
+    pub fn new(auction_id: String, buyer_id: String, amount: Amount, salt: String) -> BidReveal {
+        BidReveal {
+            auction_id,
+            buyer_id,
+            amount,
+            salt,
+        }
+    }
+}
+
+impl Sha512Hash for BidCommit {
+    fn to_hash(&self) -> String {
+        let mut hasher = Sha512::new();
+        hasher.update(self.auction_id.as_bytes());
+        hasher.update(self.buyer_id.as_bytes());
+        hasher.update(self.seller_id.as_bytes());
+        hasher.update(self.commitment.as_bytes());
+        hasher.update(self.deposit.to_le_bytes());
+
+        let hash_result = hasher.finalize();
+
+        let hash_hex = hash_result.iter()
+            .map(|byte| format!("{:02x}",byte))
+            .collect::<Vec<String>>()
+            .join("");
+        return hash_hex;
+    }
+}
+
+impl Sha512Hash for BidReveal {
+    fn to_hash(&self) -> String {
+        let mut hasher = Sha512::new();
+        hasher.update(self.auction_id.as_bytes());
+        hasher.update(self.buyer_id.as_bytes());
+        hasher.update(self.amount.to_le_bytes());
+        hasher.update(self.salt.as_bytes());
+
+        let hash_result = hasher.finalize();
+
+        let hash_hex = hash_result.iter()
+            .map(|byte| format!("{:02x}",byte))
+            .collect::<Vec<String>>()
+            .join("");
+        return hash_hex;
+    }
+}
+
+impl fmt::Display for BidCommit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"BidCommit {{ auction_id: {}, buyer_id: {}, seller_id: {}, deposit: {} }}",
+            self.auction_id, self.buyer_id, self.seller_id, self.deposit
+        )
+    }
+}
+
+impl fmt::Display for BidReveal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"BidReveal {{ auction_id: {}, buyer_id: {}, amount: {} }}",
+            self.auction_id, self.buyer_id, self.amount
+        )
+    }
+}
+
+/// [PayloadHandler] for [Data::BidCommit]
+pub struct BidCommitHandler;
+
+impl BidCommitHandler {
+    pub const KIND: &'static str = "bid_commit";
+}
+
+impl PayloadHandler for BidCommitHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::BidCommit(c) => c.buyer_id.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::BidCommit(c) => !c.auction_id.is_empty() && !c.buyer_id.is_empty() && !c.commitment.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::BidCommit(c) => c.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::BidCommit(c) => c.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::BidCommit(c) => Some(ProtoDataType::BidCommit(ProtoBidCommit {
+                auction_id: c.auction_id.clone(),
+                buyer_id: c.buyer_id.clone(),
+                seller_id: c.seller_id.clone(),
+                commitment: c.commitment.clone(),
+                deposit: c.deposit.units(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::BidCommit(c) => Some(Data::BidCommit(BidCommit {
+                auction_id: c.auction_id.clone(),
+                buyer_id: c.buyer_id.clone(),
+                seller_id: c.seller_id.clone(),
+                commitment: c.commitment.clone(),
+                deposit: Amount::from_units(c.deposit),
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// [PayloadHandler] for [Data::BidReveal]
+pub struct BidRevealHandler;
+
+impl BidRevealHandler {
+    pub const KIND: &'static str = "bid_reveal";
+}
+
+impl PayloadHandler for BidRevealHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::BidReveal(r) => r.buyer_id.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::BidReveal(r) => !r.auction_id.is_empty() && !r.buyer_id.is_empty() && !r.amount.is_zero(),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::BidReveal(r) => r.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::BidReveal(r) => r.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::BidReveal(r) => Some(ProtoDataType::BidReveal(ProtoBidReveal {
+                auction_id: r.auction_id.clone(),
+                buyer_id: r.buyer_id.clone(),
+                amount: r.amount.units(),
+                salt: r.salt.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::BidReveal(r) => Some(Data::BidReveal(BidReveal {
+                auction_id: r.auction_id.clone(),
+                buyer_id: r.buyer_id.clone(),
+                amount: Amount::from_units(r.amount),
+                salt: r.salt.clone(),
+            })),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::marco::sealed_bid::*;
+
+    #[test]
+    fn test_commitment() {
+        let salt = "pepper".to_string();
+        let amount = Amount::from_units(42);
+        let commit = BidCommit::new("auction".to_string(), "alice".to_string(), "seller".to_string(), amount, &salt, Amount::ZERO);
+
+        assert!(commit.is_opened_by(&BidReveal::new("auction".to_string(), "alice".to_string(), amount, salt.clone())));
+        assert!(!commit.is_opened_by(&BidReveal::new("auction".to_string(), "alice".to_string(), Amount::from_units(43), salt.clone())));
+        assert!(!commit.is_opened_by(&BidReveal::new("auction".to_string(), "alice".to_string(), amount, "salt".to_string())));
+        assert!(!commit.is_opened_by(&BidReveal::new("auction".to_string(), "bob".to_string(), amount, salt)));
+    }
+}
@@ -0,0 +1,221 @@
+#[doc(inline)]
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{Engine as _, engine::general_purpose};
+use rand::rngs::OsRng;
+use rsa::{pkcs1::DecodeRsaPublicKey, pkcs1::EncodeRsaPublicKey, RsaPublicKey};
+use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey as RsaSigningKey, VerifyingKey as RsaVerifyingKey};
+use rsa::sha2::{Digest, Sha256};
+use rsa::signature::{Signer, Verifier};
+
+/// ## SignatureAlgorithm
+/// Tag attached to signatures and public keys.
+/// Marcos without a tag were signed with [SignatureAlgorithm::Rsa]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SignatureAlgorithm {
+    #[default]
+    Rsa,
+    Ed25519,
+}
+
+/// ## PublicKey
+/// Public key of a wallet, tagged with its algorithm
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+/// ## SecretKey
+/// Signing key of a wallet, tagged with its algorithm
+#[derive(Debug, Clone)]
+pub enum SecretKey {
+    Rsa(RsaSigningKey<Sha256>),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl SignatureAlgorithm {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Rsa => "rsa",
+            SignatureAlgorithm::Ed25519 => "ed25519",
+        }
+    }
+
+}
+
+impl FromStr for SignatureAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rsa" => Ok(SignatureAlgorithm::Rsa),
+            "ed25519" => Ok(SignatureAlgorithm::Ed25519),
+            other => Err(format!("Unknown signature algorithm: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SignatureAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tag())
+    }
+}
+
+impl PublicKey {
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self {
+            PublicKey::Rsa(_) => SignatureAlgorithm::Rsa,
+            PublicKey::Ed25519(_) => SignatureAlgorithm::Ed25519,
+        }
+    }
+
+    /// encodes the key as `<algorithm>:<base64 key>`
+    ///
+    /// RSA keys are encoded in PKCS#1 DER and Ed25519 keys as their 32 raw bytes
+    pub fn encode(&self) -> String {
+        let bytes = match self {
+            PublicKey::Rsa(k) => k.to_pkcs1_der().expect("Failed to encode RSA public key").as_bytes().to_vec(),
+            PublicKey::Ed25519(k) => k.to_bytes().to_vec(),
+        };
+        format!("{}:{}", self.algorithm().tag(), general_purpose::STANDARD.encode(bytes))
+    }
+
+    /// id of the wallet that owns the key, the hex SHA-256 of its [encoding](PublicKey::encode)
+    ///
+    /// The sender of a [Marco](crate::marco::marco::Marco) must be the id of the key that signs it
+    pub fn id(&self) -> String {
+        Sha256::digest(self.encode().as_bytes()).iter()
+            .map(|byte| format!("{:02x}",byte))
+            .collect::<Vec<String>>()
+            .join("")
+    }
+
+    /// Reverse of [PublicKey::encode]
+    ///
+    /// **outputs:**
+    /// - None if the string is not a valid tagged key
+    pub fn decode(encoded: &str) -> Option<PublicKey> {
+        let (tag, key) = encoded.split_once(':')?;
+        let bytes = general_purpose::STANDARD.decode(key).ok()?;
+        match SignatureAlgorithm::from_str(tag).ok()? {
+            SignatureAlgorithm::Rsa => RsaPublicKey::from_pkcs1_der(&bytes).ok().map(PublicKey::Rsa),
+            SignatureAlgorithm::Ed25519 => {
+                let raw: [u8; 32] = bytes.try_into().ok()?;
+                ed25519_dalek::VerifyingKey::from_bytes(&raw).ok().map(PublicKey::Ed25519)
+            }
+        }
+    }
+
+    /// verifies `signature` over `message` with this key
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            PublicKey::Rsa(k) => {
+                let verifying_key: RsaVerifyingKey<Sha256> = RsaVerifyingKey::new(k.clone());
+                match RsaSignature::try_from(signature) {
+                    Ok(sig) => verifying_key.verify(message, &sig).is_ok(),
+                    Err(_) => false,
+                }
+            }
+            PublicKey::Ed25519(k) => {
+                match ed25519_dalek::Signature::from_slice(signature) {
+                    Ok(sig) => k.verify(message, &sig).is_ok(),
+                    Err(_) => false,
+                }
+            }
+        }
+    }
+}
+
+impl SecretKey {
+    /// generates a new random Ed25519 key
+    ///
+    /// RSA keys are not generated, they are read from the node certificate key
+    pub fn generate_ed25519() -> SecretKey {
+        SecretKey::Ed25519(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    /// encodes an Ed25519 key as `ed25519:<base64 key>`, to save it between runs
+    ///
+    /// **outputs:**
+    /// - None for RSA keys, they are kept in the node certificate key
+    pub fn encode(&self) -> Option<String> {
+        match self {
+            SecretKey::Rsa(_) => None,
+            SecretKey::Ed25519(k) => Some(format!("{}:{}", self.algorithm().tag(), general_purpose::STANDARD.encode(k.to_bytes()))),
+        }
+    }
+
+    /// Reverse of [SecretKey::encode]
+    ///
+    /// **outputs:**
+    /// - None if the string is not a valid Ed25519 key
+    pub fn decode(encoded: &str) -> Option<SecretKey> {
+        let (tag, key) = encoded.trim().split_once(':')?;
+        if SignatureAlgorithm::from_str(tag).ok()? != SignatureAlgorithm::Ed25519 {
+            return None;
+        }
+        let raw: [u8; 32] = general_purpose::STANDARD.decode(key).ok()?.try_into().ok()?;
+        Some(SecretKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&raw)))
+    }
+
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self {
+            SecretKey::Rsa(_) => SignatureAlgorithm::Rsa,
+            SecretKey::Ed25519(_) => SignatureAlgorithm::Ed25519,
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            SecretKey::Rsa(k) => PublicKey::Rsa(k.as_ref().to_public_key()),
+            SecretKey::Ed25519(k) => PublicKey::Ed25519(k.verifying_key()),
+        }
+    }
+
+    /// returns the raw signature of `message`
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            SecretKey::Rsa(k) => {
+                let signature = k.sign(message);
+                let boxed_bytes: Box<[u8]> = Box::from(signature);
+                boxed_bytes.into()
+            }
+            SecretKey::Ed25519(k) => k.sign(message).to_bytes().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::marco::signature::*;
+
+    #[test]
+    fn test_ed25519_sign_and_verify() {
+        let skey = SecretKey::generate_ed25519();
+        let pkey = skey.public_key();
+        let signature = skey.sign(b"marco");
+
+        assert_eq!(pkey.algorithm(), SignatureAlgorithm::Ed25519);
+        assert!(pkey.verify(b"marco", &signature));
+        assert!(!pkey.verify(b"other marco", &signature));
+        assert!(!SecretKey::generate_ed25519().public_key().verify(b"marco", &signature));
+    }
+
+    #[test]
+    fn test_public_key_encoding() {
+        let pkey = SecretKey::generate_ed25519().public_key();
+        let encoded = pkey.encode();
+
+        assert!(encoded.starts_with("ed25519:"));
+        assert_eq!(PublicKey::decode(&encoded), Some(pkey));
+        assert_eq!(PublicKey::decode("ed25519:not base64"), None);
+        assert_eq!(PublicKey::decode("dsa:AAAA"), None);
+
+        let skey = SecretKey::generate_ed25519();
+        let decoded = SecretKey::decode(&skey.encode().unwrap()).unwrap();
+        assert_eq!(decoded.public_key(), skey.public_key());
+        assert!(SecretKey::decode("rsa:AAAA").is_none());
+    }
+}
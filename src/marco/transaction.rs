@@ -1,7 +1,12 @@
 #[doc(inline)]
 use std::fmt;
 use sha2::{Digest, Sha512};
+use crate::marco::amount::Amount;
+use crate::marco::marco::Data;
+use crate::marco::payload::{Effect, PayloadHandler};
 use crate::marco::sha512hash::Sha512Hash;
+use crate::proto::data::DataType as ProtoDataType;
+use crate::proto::Transaction as ProtoTransaction;
 
 
 #[derive(Debug, Clone, PartialEq)]
@@ -9,23 +14,27 @@ use crate::marco::sha512hash::Sha512Hash;
 pub struct Transaction {
     pub from: String,
     pub to: String,
-    pub amount_in: f64,
-    pub amount_out: f64,
-    pub miner_fee: f64,
+    pub amount_in: Amount,
+    pub amount_out: Amount,
+    pub miner_fee: Amount,
 }
 
 impl Transaction {
     /// creates a new transaction
     ///
     /// miner_fee is the difference of amount_in and amoun_out 
-    pub fn new(amount_in: f64, from: String, amount_out: f64, to: String ) -> Transaction {
-        return Transaction {
+    ///
+    /// **outputs:**
+    /// - None if amount_out is bigger than amount_in
+    pub fn new(amount_in: Amount, from: String, amount_out: Amount, to: String ) -> Option<Transaction> {
+        let miner_fee = amount_in.checked_sub(amount_out)?;
+        return Some(Transaction {
             from,
             to,
             amount_in,
             amount_out,
-            miner_fee : amount_in - amount_out,
-        };
+            miner_fee,
+        });
     }
 }
 
@@ -55,3 +64,79 @@ impl fmt::Display for Transaction {
         )
     }
 }
+
+/// [PayloadHandler] for [Data::Transaction]
+pub struct TransactionHandler;
+
+impl TransactionHandler {
+    pub const KIND: &'static str = "transaction";
+}
+
+impl PayloadHandler for TransactionHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::Transaction(t) => t.from.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    /// the fee must be exactly the difference between what goes in and out
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::Transaction(t) => t.amount_in.checked_sub(t.amount_out) == Some(t.miner_fee),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::Transaction(t) => t.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::Transaction(t) => t.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::Transaction(t) => Some(ProtoDataType::Transaction(ProtoTransaction {
+                from: t.from.clone(),
+                to: t.to.clone(),
+                amount_in: t.amount_in.units(),
+                amount_out: t.amount_out.units(),
+                miner_fee: t.miner_fee.units(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::Transaction(t) => Some(Data::Transaction(Transaction {
+                from: t.from.clone(),
+                to: t.to.clone(),
+                amount_in: Amount::from_units(t.amount_in),
+                amount_out: Amount::from_units(t.amount_out),
+                miner_fee: Amount::from_units(t.miner_fee),
+            })),
+            _ => None,
+        }
+    }
+
+    fn effects(&self, data: &Data) -> Vec<Effect> {
+        match data {
+            Data::Transaction(t) => vec![
+                Effect::Debit { account: t.from.clone(), amount: t.amount_in },
+                Effect::Credit { account: t.to.clone(), amount: t.amount_out },
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
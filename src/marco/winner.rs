@@ -1,7 +1,12 @@
 #[doc(inline)]
 use std::fmt;
 use sha2::{Digest, Sha512};
+use crate::marco::amount::Amount;
+use crate::marco::marco::Data;
+use crate::marco::payload::PayloadHandler;
 use crate::marco::sha512hash::Sha512Hash;
+use crate::proto::data::DataType as ProtoDataType;
+use crate::proto::Winner as ProtoWinner;
 
 
 #[derive(Debug, Clone, PartialEq)]
@@ -10,17 +15,31 @@ pub struct Winner{
     pub auction: String,
     pub from: String,
     pub to: String,
-    pub amount: f64,
+    pub amount: Amount,
+    /// node that published the Winner after the auction ended, empty when it is the seller
+    pub closed_by: String,
 }
 
 impl Winner {
     /// creates a new Winner transaction
-    pub fn new(auction: String, amount: f64, from: String, to: String ) -> Winner {
+    pub fn new(auction: String, amount: Amount, from: String, to: String ) -> Winner {
         return Winner{
             from,
             to,
             amount,
-            auction
+            auction,
+            closed_by: "".to_string()
+        };
+    }
+
+    /// creates the Winner of an ended auction published by someone other than the seller
+    pub fn closed_by(auction: String, amount: Amount, from: String, to: String, closed_by: String) -> Winner {
+        return Winner{
+            from,
+            to,
+            amount,
+            auction,
+            closed_by
         };
     }
 }
@@ -32,6 +51,7 @@ impl Sha512Hash for Winner{
         hasher.update(self.to.as_bytes());
         hasher.update(self.amount.to_le_bytes());
         hasher.update(self.auction.as_bytes());
+        hasher.update(self.closed_by.as_bytes());
 
         let hash_result = hasher.finalize();
 
@@ -50,3 +70,70 @@ impl fmt::Display for Winner {
         )
     }
 }
+
+/// [PayloadHandler] for [Data::Winner]
+pub struct WinnerHandler;
+
+impl WinnerHandler {
+    pub const KIND: &'static str = "winner";
+}
+
+impl PayloadHandler for WinnerHandler {
+    fn kind(&self) -> &'static str { Self::KIND }
+
+    /// the seller, unless someone else closed the auction
+    fn sender(&self, data: &Data) -> String {
+        match data {
+            Data::Winner(w) if !w.closed_by.is_empty() => w.closed_by.clone(),
+            Data::Winner(w) => w.from.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn validate(&self, data: &Data) -> bool {
+        match data {
+            Data::Winner(w) => !w.auction.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn hash(&self, data: &Data) -> String {
+        match data {
+            Data::Winner(w) => w.to_hash(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn describe(&self, data: &Data) -> String {
+        match data {
+            Data::Winner(w) => w.to_string(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn encode(&self, data: &Data) -> Option<ProtoDataType> {
+        match data {
+            Data::Winner(w) => Some(ProtoDataType::Winner(ProtoWinner {
+                auction: w.auction.clone(),
+                from: w.from.clone(),
+                to: w.to.clone(),
+                amount: w.amount.units(),
+                closed_by: w.closed_by.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, data_type: &ProtoDataType, _version: u32) -> Option<Data> {
+        match data_type {
+            ProtoDataType::Winner(w) => Some(Data::Winner(Winner {
+                auction: w.auction.clone(),
+                from: w.from.clone(),
+                to: w.to.clone(),
+                amount: Amount::from_units(w.amount),
+                closed_by: w.closed_by.clone(),
+            })),
+            _ => None,
+        }
+    }
+}
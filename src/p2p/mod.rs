@@ -15,6 +15,9 @@ pub(super) mod peer_modules {
     pub(super) mod peer_rpc_server;
     pub(super) mod peer_rpc_client_non_lookup_handler;
     pub(super) mod peer_rpc_client_lookup_handler;
+    pub(super) mod peer_records;
+    pub(super) mod peer_snapshot;
+    pub(super) mod peer_refresh;
     pub(crate) mod peer_rpc_client;
 }
 pub mod peer;
@@ -7,12 +7,16 @@ use tokio::signal;
 use tokio::sync::oneshot;
 use tonic::transport::{Identity, Server};
 
+use crate::auction::wallet::Wallet;
 use crate::kademlia::kademlia::Kademlia;
-use crate::kademlia::node::{ID_LEN, Identifier, Node};
+use crate::kademlia::node::{Identifier, Node};
 use crate::ledger::blockchain::Blockchain;
+use crate::proto::auction_service_server::AuctionServiceServer;
 use crate::proto::packet_sending_server::PacketSendingServer;
 
 pub const TTL: u32 = 15; // The default ttl for the broadcast of messages
+pub const WRITE_QUORUM: usize = 3; // Acknowledgements that make a DHT store durable, unless the WRITE_QUORUM env is set
+pub const AUCTION_PORT_OFFSET: u32 = 1000; // The AuctionService listens on localhost only, at the node port plus this offset
 
 #[derive(Debug, Clone)]
 pub struct Peer {
@@ -72,8 +76,35 @@ impl Peer {
     /// with which you can do something like `let _ = init_server().await`. This way, the main thread will
     /// be blocked, and the process will only terminate once a CTRL + C is detected.
     pub async fn init_server(self) -> oneshot::Receiver<()> {
+        self.serve(None).await
+    }
+
+    /// # init_auction_server
+    /// Same as [Peer::init_server], but the node also answers the
+    /// [AuctionService](crate::proto::auction_service_server::AuctionService) with the `wallet` of this node.
+    ///
+    /// The service signs with the wallet, so it is not served on the public port: it gets its own
+    /// listener on `127.0.0.1`, at the [auction port](Peer::auction_port) of the node
+    pub async fn init_auction_server(self, wallet: Wallet) -> oneshot::Receiver<()> {
+        self.serve(Some(AuctionServiceServer::new(wallet))).await
+    }
+
+    /// # auction_port
+    /// #### Returns
+    /// The localhost port the AuctionService of the node listening at `port` is served on
+    pub fn auction_port(port: u32) -> u32 {
+        port + AUCTION_PORT_OFFSET
+    }
+
+    async fn serve(self, auctions: Option<AuctionServiceServer<Wallet>>) -> oneshot::Receiver<()> {
         let node = self.node.clone();
         debug!("DEBUG PEER::INIT_SERVER => Creating server at {}:{}", node.ip, node.port);
+        // Expire, republish and replicate the DHT records while the server is up
+        tokio::spawn(self.clone().maintain_records());
+        tokio::spawn(self.clone().maintain_routing_table());
+        // Save the routing table now and then, and when we shut down
+        tokio::spawn(self.clone().snapshot_routing_table());
+        let snapshot = self.clone();
         let data_dir = std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR")]);
         println!("Path: <{}>", data_dir.display());
         let mut slash = "\\";
@@ -88,6 +119,21 @@ impl Peer {
         let tls = tonic::transport::ServerTlsConfig::new()
             .identity(identity);
 
+        if let Some(auctions) = auctions {
+            let address = format!("{}:{}", "127.0.0.1", Self::auction_port(node.port));
+            debug!("DEBUG PEER::INIT_SERVER => Serving the auctions at {}", address);
+            let local = Server::builder()
+                .tls_config(tls.clone())
+                .expect("Failed to configure TLS on the auction server")
+                .add_service(auctions)
+                .serve(address.parse().unwrap());
+            tokio::spawn(async move {
+                if let Err(e) = local.await {
+                    println!("Auction server error: {}", e);
+                }
+            });
+        }
+
         let server = Server::builder()
             .tls_config(tls)
             .expect("Failed to configure TLS on server")
@@ -101,6 +147,9 @@ impl Peer {
         tokio::spawn(async move {
             // Wait for CTRL+C signal
             signal::ctrl_c().await.expect("failed to listen for event");
+            if let Err(e) = snapshot.save_routing_table() {
+                debug!("DEBUG PEER::INIT_SERVER => Failed to save the routing table: {e}");
+            }
             // Send shutdown signal to the server thread
             let _ = shutdown_tx.send(());
 
@@ -129,20 +178,7 @@ impl Peer {
 
         // we know that the hash output is going to be 256 bits = 32 bytes
         let result = hasher.finalize();
-
-        let mut hash = [0; ID_LEN];
-        let mut bin_str = "".to_string();
-        for b in result {
-            bin_str += &format!("{:0>8b}", b); // Force the representation to pad with 0's in case there aren't 8 bits
-        }
-
-        let mut i = 0;
-        assert_eq!(bin_str.len(), ID_LEN); // assert that we indeed have ID_LEN bits
-        for b in bin_str.chars() {
-            hash[i] = b.to_digit(2).unwrap() as u8;
-            i += 1;
-        }
-        Identifier::new(hash)
+        Identifier::new(result.into())
     }
 
 }
@@ -11,9 +11,15 @@ use crate::p2p::peer::Peer;
 impl Peer {
 
     pub async fn boot(&self) {
+        // The nodes saved on the last run are enough to rejoin, unless none of them answers
+        let restored = self.restore_routing_table().await;
         if self.bootstrap {
             return;
         }
+        if restored {
+            let _ = self.find_node(self.id.clone()).await;
+            return;
+        }
 
         let path;
         if let Ok(mut exe_path) = std::env::current_dir() {
@@ -50,7 +56,7 @@ impl Peer {
                                 }
                             }
                             for i in &bootstrap_nodes {
-                                self.kademlia.lock().unwrap().add_node(&Node::new(i.to_string(), 8635).unwrap());
+                                self.add_contact(&Node::new(i.to_string(), 8635).unwrap());
                             }
                         }
                     } else {
@@ -59,7 +65,7 @@ impl Peer {
                         debug!("DEBUG PEER_BOOT => Path: {}", path.as_str());
                         let _ = Self::create_file(path.as_str(), bootstrap_nodes.clone());
                         for i in bootstrap_nodes {
-                            self.kademlia.lock().unwrap().add_node(&Node::new(i, 8635).unwrap());
+                            self.add_contact(&Node::new(i, 8635).unwrap());
                         }
                     }
                 } else {
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::kademlia::record::DueRecords;
+use crate::p2p::peer::Peer;
+use crate::p2p::private::req_handler_modules::res_handler::ResHandler;
+
+/// How often the stored records are checked for expiry, republishing and replication
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Peer {
+    /// # maintain_records
+    /// Runs forever, every [MAINTENANCE_INTERVAL] it:
+    /// - stores again the records this node published, once their republish interval is over;
+    /// - drops the records that expired, the ones we published never expire;
+    /// - sends the records it holds to the closest nodes that do not have them yet (nodes that joined
+    ///   or became closer to the key), so a record outlives the nodes it was first stored on.
+    pub async fn maintain_records(self) {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let DueRecords { republish, expired, replicate } = self.kademlia.lock().unwrap().due_records(Instant::now());
+            if expired > 0 {
+                debug!("DEBUG PEER::MAINTAIN_RECORDS => Dropped {expired} expired records");
+            }
+
+            for (key, value) in republish {
+                match self.store(key.clone(), value).await {
+                    Ok(report) if report.reached_quorum() => {}
+                    Ok(report) => debug!("DEBUG PEER::MAINTAIN_RECORDS => Only {} of {} nodes stored {key} again", report.acknowledged.len(), report.quorum),
+                    Err(e) => debug!("DEBUG PEER::MAINTAIN_RECORDS => Failed to republish {key}: {e}"),
+                }
+            }
+
+            for (key, value, lifetime, nodes) in replicate {
+                for node in nodes {
+                    // A ttl of 0 makes the node store the record instead of forwarding it
+                    let res = ResHandler::store(&self, node.ip.clone(), node.port, key.clone(), value.clone(), 0, lifetime.as_secs().max(1)).await;
+                    match res {
+                        Ok(_) => self.kademlia.lock().unwrap().mark_replicated(&key, node.id),
+                        Err(e) => {
+                            debug!("DEBUG PEER::MAINTAIN_RECORDS => Failed to replicate {key} to {}:{}: {e}", node.ip, node.port);
+                            self.kademlia.lock().unwrap().risk_penalty(node.id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
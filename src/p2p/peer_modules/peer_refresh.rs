@@ -0,0 +1,90 @@
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::kademlia::node::Node;
+use crate::p2p::peer::Peer;
+
+/// How often the routing table is refreshed when the `REFRESH_INTERVAL` env (in seconds) is not set
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+impl Peer {
+    /// # refresh_interval
+    /// Time between two runs of [Peer::maintain_routing_table], set with the `REFRESH_INTERVAL` env (defaults to [REFRESH_INTERVAL])
+    pub fn refresh_interval() -> Duration {
+        env::var("REFRESH_INTERVAL").ok()
+            .and_then(|secs| secs.parse().ok())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(REFRESH_INTERVAL)
+    }
+
+    /// # add_contact
+    /// Adds a node we heard of to the routing table, following the Kademlia policy when its bucket is full:
+    /// the node waits in the replacement cache while the least recently seen contact is pinged in the background,
+    /// the contact is kept if it answers, otherwise the most recently seen candidate takes its place
+    pub fn add_contact(&self, node: &Node) {
+        let top = self.kademlia.lock().unwrap().add_node(node);
+        if let Some(top) = top {
+            let peer = self.clone();
+            tokio::spawn(async move {
+                let alive = peer.ping(&top.ip, top.port, top.id.clone()).await.is_ok();
+                if !alive {
+                    debug!("DEBUG PEER::ADD_CONTACT => {}:{} did not answer, replacing it", top.ip, top.port);
+                }
+                peer.kademlia.lock().unwrap().contact_checked(&top, alive);
+            });
+        }
+    }
+
+    /// # maintain_routing_table
+    /// Runs forever, calling [Peer::refresh_routing_table] every [Peer::refresh_interval]
+    pub async fn maintain_routing_table(self) {
+        let period = Self::refresh_interval();
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            self.refresh_routing_table(period).await;
+        }
+    }
+
+    /// # refresh_routing_table
+    /// Pings the nodes that did not answer us in the last `period` and removes the ones that stay silent,
+    /// then looks up a random ID in every bucket with no activity in that time, adding the nodes that answer,
+    /// so distant buckets do not stay empty or full of dead nodes
+    pub async fn refresh_routing_table(&self, period: Duration) {
+        let since = Instant::now().checked_sub(period).unwrap_or_else(Instant::now);
+
+        let stale = self.kademlia.lock().unwrap().stale_nodes(since);
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(10)); // Limit the number of threads
+        let tasks = stale.into_iter()
+            .map(|node| {
+                let semaphore = semaphore.clone();
+                let peer = self.clone();
+                tokio::spawn(async move {
+                    let permit = semaphore.acquire().await.expect("Failed to acquire permit");
+                    let alive = peer.ping(&node.ip, node.port, node.id.clone()).await.is_ok();
+                    drop(permit);
+                    (node, alive)
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let (node, alive) = task.await.expect("Failed to retrieve task result");
+            if !alive {
+                debug!("DEBUG PEER::REFRESH_ROUTING_TABLE => Removing {}:{}, it did not answer", node.ip, node.port);
+                self.kademlia.lock().unwrap().remove_node(node.id);
+            }
+        }
+
+        let targets = self.kademlia.lock().unwrap().buckets_to_refresh(since);
+        for target in targets {
+            // The nodes that answer the lookup are added as they go
+            if let Err(e) = self.lookup_nodes(target.clone()).await {
+                debug!("DEBUG PEER::REFRESH_ROUTING_TABLE => Failed to refresh the bucket of {target}: {e}");
+            }
+        }
+    }
+}
@@ -6,11 +6,15 @@ use std::io::ErrorKind;
 
 use log::debug;
 
+use crate::kademlia::bucket::K;
+use crate::kademlia::lookup::{ALPHA, Lookup};
 use crate::kademlia::node::{Identifier, Node};
 use crate::ledger::block::Block;
+use crate::ledger::blockchain::Blockchain;
 use crate::marco::marco::Marco;
 use crate::p2p::peer::Peer;
 use crate::p2p::private::broadcast_api::BroadCastReq;
+use crate::p2p::private::req_handler_modules::res_handler::ResHandler;
 
 // Max allowed iterations
 pub const MAX_ITER: u32 = 13;
@@ -50,6 +54,23 @@ impl Ord for NodeNewDistance {
     }
 }
 
+/// ## StoreReport
+/// Where [Peer::store] placed a record: the closest nodes to the key that acknowledged it and the ones that did not
+#[derive(Debug, Clone)]
+pub struct StoreReport {
+    pub key: Identifier,
+    pub quorum: usize,
+    pub acknowledged: Vec<Node>,
+    pub failed: Vec<Node>,
+}
+
+impl StoreReport {
+    /// true if at least `quorum` nodes acknowledged the record
+    pub fn reached_quorum(&self) -> bool {
+        self.acknowledged.len() >= self.quorum
+    }
+}
+
 impl Peer {
 
     pub async fn send_block(&self, block: Block) {
@@ -83,7 +104,7 @@ impl Peer {
                 Err(list) => {
                     if !list.is_none() {
                         for i in list.clone().unwrap() {
-                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().get_trust_score(i.id.clone()).get_score()));
+                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().lookup_distance(&id, &i)));
                         }
                     }
                 }
@@ -103,7 +124,7 @@ impl Peer {
                 Err(e) => {
                     if !e.is_none() {
                         for i in e.unwrap() {
-                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().get_trust_score(i.id.clone()).get_score()));
+                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().lookup_distance(&id, &i)));
                         }
                     }
                 }
@@ -115,13 +136,68 @@ impl Peer {
         // to out kbucket
         if id == self.node.id {
             for i in already_checked {
-                self.kademlia.lock().unwrap().add_node(&i);
+                self.add_contact(&i);
             }
         }
 
         return Err(io::Error::new(ErrorKind::NotFound, "The node was not found"));
     }
 
+    /// # lookup_nodes
+    /// Iterative lookup of the [K] closest nodes to `key`, see [Lookup].
+    /// Each round sends a [FindNodeRequest](crate::proto::FindNodeRequest) to the [ALPHA] closest nodes not queried yet
+    ///
+    /// #### Returns
+    /// Up to [K] nodes that answered, closest first, or an error if we do not know any node
+    pub async fn lookup_nodes(&self, key: Identifier) -> Result<Vec<Node>, io::Error> {
+        let seeds = self.kademlia.lock().unwrap().get_k_nearest_to_node(key.clone()).unwrap_or_default();
+        if seeds.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "No nodes found to communicate with"));
+        }
+        let mut lookup = Lookup::new(key.clone(), self.node.id.clone(), seeds);
+        // Bounded in case nodes keep answering with new (possibly made up) nodes
+        let mut count = 0;
+        while !lookup.is_done() && count < MAX_ITER * K as u32 {
+            let tasks = lookup.next_batch(ALPHA).into_iter()
+                .map(|node| {
+                    let peer = self.clone();
+                    let ident = key.clone();
+                    tokio::spawn(async move {
+                        let res = ResHandler::find_node(&peer.node, &node.ip, node.port, &ident, &peer.id).await;
+                        (res, node)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for task in tasks {
+                let (res, node) = task.await.expect("Task failed");
+                self.kademlia.lock().unwrap().increment_interactions(node.id.clone());
+                match res {
+                    Ok(result) => {
+                        let result = result.into_inner();
+                        // Type 2 means the key is the id of a node the queried node knows
+                        let answer = match result.response_type {
+                            1 => result.list.map(|l| l.nodes).unwrap_or_default(),
+                            2 => result.node.into_iter().collect(),
+                            _ => Vec::new(),
+                        };
+                        let nodes = answer.into_iter().filter_map(|n| Node::new(n.ip, n.port)).collect();
+                        self.add_contact(&node);
+                        self.kademlia.lock().unwrap().mark_seen(&node.id);
+                        lookup.respond(&node, nodes);
+                    }
+                    Err(e) => {
+                        debug!("DEBUG PEER::LOOKUP_NODES => {}:{} did not answer: {}", node.ip, node.port, e);
+                        self.kademlia.lock().unwrap().risk_penalty(node.id.clone());
+                        lookup.fail(&node);
+                    }
+                }
+            }
+            count += 1;
+        }
+        Ok(lookup.closest())
+    }
+
     pub async fn find_value(&self, id: Identifier) -> Result<String, io::Error>
     {
         let nodes = &mut self.kademlia.lock().unwrap().get_k_nearest_to_node(id.clone()).unwrap_or(Vec::new());
@@ -147,7 +223,7 @@ impl Peer {
                 Err(list) => {
                     if !list.is_none() {
                         for i in list.clone().unwrap() {
-                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().get_trust_score(i.id.clone()).get_score()));
+                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().lookup_distance(&id, &i)));
                         }
                     }
                 }
@@ -168,7 +244,7 @@ impl Peer {
                 Err(e) => {
                     if !e.is_none() {
                         for i in e.unwrap() {
-                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().get_trust_score(i.id.clone()).get_score()));
+                            priority_queue.push(NodeNewDistance::new(i.clone(), self.kademlia.lock().unwrap().lookup_distance(&id, &i)));
                         }
                     }
                 }
@@ -211,6 +287,15 @@ impl Peer {
             }
         }
 
+        let cache = self.blockchain.lock().unwrap().signature_cache.clone();
+        let trail = block_trail.clone();
+        let valid = tokio::task::spawn_blocking(move || {
+            trail.iter().all(|b| Blockchain::verify_block_signatures(&cache, b, None))
+        }).await.unwrap_or(false);
+        if !valid {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Block has an invalid signature"));
+        }
+
         for i in (0..block_trail.len()).rev() {
             if self.blockchain.lock().unwrap().can_add_block(block_trail[i].clone()) {
                 self.blockchain.lock().unwrap().add_block(block_trail[i].clone());
@@ -219,8 +219,8 @@ impl Peer {
                 Ok(result) => {
                     if result.get_ref().response_type == 2 && !result.get_ref().block.is_none(){
                         info!("Found the Block");
-                        if let Some(target_block) = result.get_ref().clone().block {
-                            return Ok(Some((Block::proto_to_block(target_block), node.clone())));
+                        if let Some(target_block) = result.get_ref().clone().block.and_then(Block::proto_to_block) {
+                            return Ok(Some((target_block, node.clone())));
                         }
                         return Err(None);
                     } else if result.get_ref().response_type == 1 {
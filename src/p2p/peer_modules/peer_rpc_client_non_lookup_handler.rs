@@ -1,14 +1,16 @@
-use std::io;
-use std::io::ErrorKind;
+use std::{env, io};
 use std::sync::Arc;
 
 use log::debug;
 use tonic::Response;
 
-use crate::kademlia::node::{Identifier, Node};
-use crate::p2p::peer::Peer;
+use crate::kademlia::bucket::K;
+use crate::kademlia::node::Identifier;
+use crate::kademlia::record::RECORD_EXPIRE;
+use crate::p2p::peer::{Peer, WRITE_QUORUM};
+use crate::p2p::peer_modules::peer_rpc_client::StoreReport;
 use crate::p2p::private::req_handler_modules::res_handler::ResHandler;
-use crate::proto::{PongPacket, StoreResponse};
+use crate::proto::PongPacket;
 
 impl Peer {
     /// # Ping Request
@@ -21,71 +23,77 @@ impl Peer {
                 self.kademlia.lock().unwrap().risk_penalty(id.clone());
                 Err(e)
             },
-            Ok(res) => return Ok(res)
+            Ok(res) => {
+                self.kademlia.lock().unwrap().mark_seen(&id);
+                Ok(res)
+            }
         }
     }
 
     /// # Store Request
-    /// Proxy for the [ResHandler::store] function.
-    pub async fn store(&self, key: Identifier, value: String) -> Result<Response<StoreResponse> , io::Error> {
+    /// Stores the record on the [K](crate::kademlia::bucket::K) closest nodes to the key, found with [Peer::lookup_nodes],
+    /// by sending each one a [ResHandler::store] they do not forward. The store is durable once [Peer::write_quorum] nodes acknowledged it.
+    /// The record is also kept locally, and this node republishes it every [REPUBLISH_INTERVAL](crate::kademlia::record::REPUBLISH_INTERVAL)
+    /// until it is no longer wanted.
+    ///
+    /// #### Returns
+    /// A [StoreReport] with the nodes that acknowledged the record, or an error if we do not know any node
+    pub async fn store(&self, key: Identifier, value: String) -> Result<StoreReport, io::Error> {
+        self.store_with_quorum(key, value, Self::write_quorum()).await
+    }
 
-        let nodes = self.kademlia.lock().unwrap().get_k_nearest_to_node(key.clone());
-        let node_list: Vec<Node>;
-        let mut arguments: Vec<(String, u32, Identifier)> = Vec::new();
-        if nodes.is_none() {
-            return Err(io::Error::new(ErrorKind::NotFound, "No nodes found"));
-        } else {
-            node_list = nodes.unwrap();
-            for i in &node_list{
-                // Exclude the possibility of creating a loop within it self
-                if i == &self.node {
-                    continue;
-                }
-                arguments.push((i.ip.clone(), i.port, i.id.clone()));
-                self.kademlia.lock().unwrap().increment_interactions(i.clone().id);
-            }
-        }
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(5)); // Limit the number of threads
+    /// # store_with_quorum
+    /// Same as [Peer::store], with the number of acknowledgements that make the store durable
+    pub async fn store_with_quorum(&self, key: Identifier, value: String, quorum: usize) -> Result<StoreReport, io::Error> {
+        self.kademlia.lock().unwrap().publish_key(key.clone(), value.clone());
+        let nodes = self.lookup_nodes(key.clone()).await?;
 
-        // Process tasks concurrently using Tokio
-        let tasks = arguments.into_iter()
-            .map(|arg| {
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(5)); // Limit the number of threads
+        let tasks = nodes.into_iter()
+            .map(|node| {
                 let semaphore = semaphore.clone();
-                let node = self.node.clone();
+                let peer = self.clone();
                 let ident = key.clone();
                 let val = value.clone();
-                let own_id = self.id.clone();
                 tokio::spawn(async move {
                     // Acquire a permit from the semaphore
                     let permit = semaphore.acquire().await.expect("Failed to acquire permit");
-                    let res = ResHandler::store(&node, arg.0, arg.1, ident, val, 15, &own_id.clone()).await;
+                    // A ttl of 0 makes the node store the record instead of forwarding it
+                    let res = ResHandler::store(&peer, node.ip.clone(), node.port, ident, val, 0, RECORD_EXPIRE.as_secs()).await;
                     drop(permit);
-                    (res, arg.2)
+                    (res, node)
                 })
             })
             .collect::<Vec<_>>();
 
-        // Output results
-        let mut counter: usize = 0;
+        let mut report = StoreReport { key: key.clone(), quorum, acknowledged: Vec::new(), failed: Vec::new() };
         for task in tasks {
-            let (result, id) = task.await.expect("Failed to retrieve task result");
+            let (result, node) = task.await.expect("Failed to retrieve task result");
             match result {
-                Err(e) => {
-                    debug!("Error found: {}", e);
-                    self.kademlia.lock().unwrap().risk_penalty(id);
+                Ok(res) if res.get_ref().response_type == 1 => {
+                    self.kademlia.lock().unwrap().mark_seen(&node.id);
+                    self.kademlia.lock().unwrap().mark_replicated(&key, node.id.clone());
+                    report.acknowledged.push(node);
                 }
-                Ok(res) => {
-                    if res.get_ref().response_type != 0 {
-                        return Ok(res);
-                    }
+                Ok(_) => report.failed.push(node),
+                Err(e) => {
+                    debug!("DEBUG PEER::STORE => {}:{} did not store {}: {}", node.ip, node.port, key, e);
+                    self.kademlia.lock().unwrap().risk_penalty(node.id.clone());
+                    report.failed.push(node);
                 }
             }
-            // Move the node we just contacted to the back of the list
-            let _ = self.kademlia.lock().unwrap().send_back_specific_node(&node_list[counter]);
-            counter += 1;
         }
+        Ok(report)
+    }
 
-        return Err(io::Error::new(ErrorKind::NotFound, "Node not found"));
+    /// # write_quorum
+    /// Acknowledgements [Peer::store] waits for, set with the `WRITE_QUORUM` env (defaults to [WRITE_QUORUM]).
+    /// A record is stored on at most [K] nodes, so the quorum is kept between 1 and [K]
+    pub fn write_quorum() -> usize {
+        env::var("WRITE_QUORUM").ok()
+            .and_then(|quorum| quorum.parse::<usize>().ok())
+            .unwrap_or(WRITE_QUORUM)
+            .clamp(1, K)
     }
 
 }
\ No newline at end of file
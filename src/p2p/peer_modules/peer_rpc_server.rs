@@ -6,52 +6,48 @@ use tonic::{Request, Response, Status};
 use crate::auxi;
 use crate::kademlia::node::{Identifier, Node};
 use crate::ledger::block::Block;
+use crate::ledger::blockchain::Blockchain;
+use crate::marco::signature::PublicKey;
 use crate::p2p::private::broadcast_api::BroadCastReq;
 use crate::p2p::private::req_handler_modules::req_handler_lookups::ReqHandler;
-use crate::proto::{BlockBroadcast, FindNodeRequest, FindNodeResponse, FindValueRequest, FindValueResponse, GetBlockRequest, GetBlockResponse, PingPacket, PongPacket, StoreRequest, StoreResponse};
+use crate::proto::{BlockBroadcast, FindNodeRequest, FindNodeResponse, FindValueRequest, FindValueResponse, GetBlockRequest, GetBlockResponse, GetChainRequest, GetChainResponse, PingPacket, PongPacket, SrcAddress, StoreRequest, StoreResponse};
 use crate::proto::packet_sending_server::PacketSending;
 
 use super::super::peer::Peer;
 
+/// Answer to a request whose source id cannot be read, see [parse_src_id]
+const INVALID_SRC_ID: &str = "The supplied source ID has an invalid size";
+
+/// # parse_src_id
+/// Reads the [Identifier] of the node that sent a request
+///
+/// #### Returns
+/// None if the request has no source, or if its id is not [ID_LEN](crate::kademlia::node::ID_LEN) bytes long
+fn parse_src_id(src: Option<&SrcAddress>) -> Option<Identifier> {
+    src.and_then(|src| Identifier::from_bytes(&src.id))
+}
+
 #[tonic::async_trait]
 impl PacketSending for Peer {
     /// # Ping Handler
     /// This function acts like a proxy function to the [ReqHandler::ping]
     async fn ping(&self, request: Request<PingPacket>) -> Result<Response<PongPacket>, Status> {
         let addr = request.remote_addr().unwrap().clone();
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
         let src = request.get_ref().src.as_ref().unwrap().clone();
         let res = ReqHandler::ping(self, request).await;
         return match res {
             Err(e) => {
                 debug!("An error has occurred while receiving the Pong from {}: {}", addr, e);
-                self.kademlia.lock().unwrap().risk_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+                self.kademlia.lock().unwrap().risk_penalty(src_id.clone());
                 Err(Status::aborted(e.to_string()))
             }
             Ok(pong) => {
-                let node = Node::new(src.ip.clone(), src.port).unwrap();
-                let add_result = self.kademlia.lock().unwrap().add_node(&node);
-                if add_result.is_none() {
-                    // Node was added
-                    Ok(pong)
-                } else {
-                    let ip = add_result.clone().unwrap().ip;
-                    let port = add_result.clone().unwrap().port;
-                    let id = add_result.clone().unwrap().id;
-                    let top_node_pong = self.ping(ip.as_ref(), port, id).await;
-                    match top_node_pong {
-                        Err(e) => {
-                            // This means that the top node of the bucket is offline, so let's replace it with the new one
-                            debug!("Error while trying to ping {}:{}: {}\nReplacing it with new node", ip, port, e);
-                            self.kademlia.lock().unwrap().replace_node(&node);
-                            Ok(pong)
-                        }
-                        Ok(_) => {
-                            info!("Top node of the bucket is on, sending it to the back of the list");
-                            self.kademlia.lock().unwrap().send_back(&add_result.unwrap());
-                            Ok(pong)
-                        }
-                    }
+                // If the bucket is full, its oldest contact is pinged in the background before it can be replaced
+                if let Some(node) = Node::new(src.ip.clone(), src.port) {
+                    self.add_contact(&node);
                 }
+                Ok(pong)
             }
         }
     }
@@ -63,13 +59,12 @@ impl PacketSending for Peer {
         if self.bootstrap {
             return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
         }
-        let id = request.get_ref().src.as_ref().unwrap().id.clone();
-        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, Identifier::new(id.try_into().unwrap())).await;
-        let src = request.get_ref().src.as_ref().unwrap();
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
+        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, src_id.clone()).await;
         match pong {
             Err(e) => {
                 debug!("Tried to Ping {} back but got: {}", request.remote_addr().unwrap().to_string(), e);
-                self.kademlia.lock().unwrap().risk_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+                self.kademlia.lock().unwrap().risk_penalty(src_id.clone());
                 return Err(Status::aborted(e.to_string()));
             }
             Ok(_) => {
@@ -82,12 +77,12 @@ impl PacketSending for Peer {
     /// This function acts like a proxy function to the [ReqHandler::find_node],
     /// however it pings the sender before proceeding with the request (to strengthen source address spoofing resistance)
     async fn find_node(&self, request: Request<FindNodeRequest>) -> Result<Response<FindNodeResponse>, Status> {
-        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, Identifier::new(request.get_ref().src.as_ref().unwrap().id.clone().try_into().unwrap())).await;
-        let src = request.get_ref().src.as_ref().unwrap();
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
+        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, src_id.clone()).await;
         match pong {
             Err(e) => {
                 debug!("Tried to Ping {} back but got: {}", request.remote_addr().unwrap().to_string(), e);
-                self.kademlia.lock().unwrap().risk_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+                self.kademlia.lock().unwrap().risk_penalty(src_id.clone());
                 return Err(Status::aborted(e.to_string()));
             }
             Ok(_) => {
@@ -103,12 +98,12 @@ impl PacketSending for Peer {
         if self.bootstrap {
             return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
         }
-        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, Identifier::new(request.get_ref().src.as_ref().unwrap().id.clone().try_into().unwrap())).await;
-        let src = request.get_ref().src.as_ref().unwrap();
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
+        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, src_id.clone()).await;
         match pong {
             Err(e) => {
                 debug!("Tried to Ping {} back but got: {}", request.remote_addr().unwrap().to_string(), e);
-                self.kademlia.lock().unwrap().risk_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+                self.kademlia.lock().unwrap().risk_penalty(src_id.clone());
                 return Err(Status::aborted(e.to_string()));
             }
             Ok(_) => {
@@ -122,24 +117,34 @@ impl PacketSending for Peer {
         if self.bootstrap {
             return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
         }
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
         // This is a broadcast so there is no need to ping back the sender
         let input = request.get_ref();
         let packed = input.marco.clone();
-        let src = request.get_ref().src.as_ref().unwrap();
-        self.kademlia.lock().unwrap().increment_interactions(Identifier::new(src.id.clone().try_into().unwrap()));
+        self.kademlia.lock().unwrap().increment_interactions(src_id.clone());
         if packed.is_none() {
-            self.kademlia.lock().unwrap().reputation_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+            self.kademlia.lock().unwrap().reputation_penalty(src_id.clone());
             return Err(Status::invalid_argument("The provided marco is invalid"));
         }
         let unpacked = packed.unwrap();
 
-        let transaction = auxi::transform_proto_to_marco(&unpacked);
+        let transaction = match auxi::transform_proto_to_marco(&unpacked) {
+            Some(m) => m,
+            None => {
+                self.kademlia.lock().unwrap().reputation_penalty(src_id.clone());
+                return Err(Status::invalid_argument("The provided marco could not be decoded"));
+            }
+        };
 
         info!("Reveived a Marco: {:?} with TTL: {} from : {}:{}", transaction, input.ttl, request.get_ref().src.as_ref().unwrap().ip.clone(), request.get_ref().src.as_ref().unwrap().port.clone());
 
         // Marco received
-        let cert = input.cert.clone();
-        let pub_key = auxi::get_public_key(cert);
+        // Marcos carry the key they were signed with, older ones are signed with the sender certificate.
+        // Either way the id of the key must be the sender of the Marco, see Marco::verify
+        let pub_key = match transaction.signer_key() {
+            Some(key) => key,
+            None => PublicKey::Rsa(auxi::get_public_key(input.cert.clone())),
+        };
         let (res, b) = self.blockchain.lock().unwrap().add_marco(transaction.clone(), pub_key) ;
         if !res{
             // Marco already stored or invalid
@@ -167,23 +172,40 @@ impl PacketSending for Peer {
         if self.bootstrap {
             return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
         }
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
         // This is a broadcast so there is no need to ping back the sender
         let input = request.get_ref();
         let packed = input.block.clone();
-        let src = request.get_ref().src.as_ref().unwrap();
-        self.kademlia.lock().unwrap().increment_interactions(Identifier::new(src.id.clone().try_into().unwrap()));
+        self.kademlia.lock().unwrap().increment_interactions(src_id.clone());
         if packed.is_none() {
-            self.kademlia.lock().unwrap().reputation_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+            self.kademlia.lock().unwrap().reputation_penalty(src_id.clone());
             return Err(Status::invalid_argument("The provided transaction is invalid"));
         }
         let unpacked = packed.unwrap();
 
-        let block = Block::proto_to_block(unpacked);
+        let block = match Block::proto_to_block(unpacked) {
+            Some(b) => b,
+            None => {
+                self.kademlia.lock().unwrap().reputation_penalty(src_id.clone());
+                return Err(Status::invalid_argument("The provided block could not be decoded"));
+            }
+        };
         info!("Reveived a Block: {:?} with TTL: {} from : {}:{}", block, input.ttl, request.get_ref().src.as_ref().unwrap().ip.clone(), request.get_ref().src.as_ref().unwrap().port.clone());
         // Block Handler
         if self.blockchain.lock().unwrap().get_block_by_hash(block.hash.clone()).is_some() {
             return Ok(Response::new(()));
         }
+        // Signatures are checked in parallel outside of the async runtime
+        let cache = self.blockchain.lock().unwrap().signature_cache.clone();
+        let fallback = PublicKey::Rsa(auxi::get_public_key(input.cert.clone()));
+        let to_verify = block.clone();
+        let valid = tokio::task::spawn_blocking(move || Blockchain::verify_block_signatures(&cache, &to_verify, Some(&fallback)))
+            .await
+            .unwrap_or(false);
+        if !valid {
+            self.kademlia.lock().unwrap().reputation_penalty(src_id.clone());
+            return Err(Status::invalid_argument("The provided block has an invalid signature"));
+        }
         let added = self.blockchain.lock().unwrap().add_block(block.clone());
         if !added {
             let _ = self.get_block(block.hash.clone()).await;
@@ -200,12 +222,12 @@ impl PacketSending for Peer {
         if self.bootstrap {
             return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
         }
-        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, Identifier::new(request.get_ref().src.as_ref().unwrap().id.clone().try_into().unwrap())).await;
-        let src = request.get_ref().src.as_ref().unwrap();
+        let src_id = parse_src_id(request.get_ref().src.as_ref()).ok_or(Status::invalid_argument(INVALID_SRC_ID))?;
+        let pong = self.ping(&request.get_ref().src.as_ref().unwrap().ip, request.get_ref().src.as_ref().unwrap().port, src_id.clone()).await;
         match pong {
             Err(e) => {
                 debug!("Tried to Ping {} back but got: {}", request.remote_addr().unwrap().to_string(), e);
-                self.kademlia.lock().unwrap().risk_penalty(Identifier::new(src.id.clone().try_into().unwrap()));
+                self.kademlia.lock().unwrap().risk_penalty(src_id.clone());
                 return Err(Status::aborted(e.to_string()));
             }
             Ok(_) => {
@@ -214,5 +236,33 @@ impl PacketSending for Peer {
         }
     }
 
+    /// # Get Chain Handler
+    /// Returns the confirmed blocks followed by the main branch, used by `chain show`
+    async fn get_chain(&self, _request: Request<GetChainRequest>) -> Result<Response<GetChainResponse>, Status> {
+        if self.bootstrap {
+            return Err(Status::aborted("Bootstrap node. Available RPCS: {PING, FIND_NODE}".to_string()));
+        }
+        let bc = self.blockchain.lock().unwrap();
+        let blocks = bc.chain.iter().chain(bc.heads.get_main().iter())
+            .map(auxi::transform_block_to_proto)
+            .collect();
+        return Ok(Response::new(GetChainResponse { blocks }));
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kademlia::node::{ID_LEN, Identifier};
+    use crate::p2p::peer_modules::peer_rpc_server::parse_src_id;
+    use crate::proto::SrcAddress;
 
+    #[test]
+    fn test_parse_src_id() {
+        let src = |id: Vec<u8>| SrcAddress { id, ip: "127.0.0.1".to_string(), port: 8888 };
+        assert_eq!(parse_src_id(Some(&src(vec![7; ID_LEN]))), Some(Identifier::new([7; ID_LEN])));
+        // A peer sending a malformed id gets an error instead of crashing the handler
+        assert_eq!(parse_src_id(Some(&src(vec![7; 3]))), None);
+        assert_eq!(parse_src_id(None), None);
+    }
 }
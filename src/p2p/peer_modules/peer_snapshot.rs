@@ -0,0 +1,93 @@
+use std::{env, fs, io};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::kademlia::kademlia::Kademlia;
+use crate::p2p::peer::Peer;
+use crate::p2p::private::req_handler_modules::res_handler::ResHandler;
+
+/// How often the routing table is saved to disk, it is also saved at shutdown
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+impl Peer {
+    /// # snapshot_path
+    /// `src/routing_table_<port>.json`, next to the `bootstrap.txt` file, so nodes sharing a folder do not overwrite each other
+    fn snapshot_path(&self) -> io::Result<PathBuf> {
+        let mut path = env::current_dir()?;
+        path.push("src");
+        path.push(format!("routing_table_{}.json", self.node.port));
+        Ok(path)
+    }
+
+    /// # save_routing_table
+    /// Writes the [snapshot](Kademlia::snapshot) of the routing table to disk.
+    /// An empty routing table is not saved, so a node stopped before it booted keeps its last snapshot
+    pub fn save_routing_table(&self) -> io::Result<()> {
+        let snapshot = {
+            let kademlia = self.kademlia.lock().unwrap();
+            if kademlia.get_all_nodes().is_none() {
+                return Ok(());
+            }
+            kademlia.snapshot()
+        };
+        fs::write(self.snapshot_path()?, snapshot)
+    }
+
+    /// # snapshot_routing_table
+    /// Runs forever, saving the routing table every [SNAPSHOT_INTERVAL]
+    pub async fn snapshot_routing_table(self) {
+        let start = tokio::time::Instant::now() + SNAPSHOT_INTERVAL;
+        let mut interval = tokio::time::interval_at(start, SNAPSHOT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.save_routing_table() {
+                debug!("DEBUG PEER::SNAPSHOT_ROUTING_TABLE => Failed to save the routing table: {e}");
+            }
+        }
+    }
+
+    /// # restore_routing_table
+    /// Loads the routing table saved on the last run and pings every saved node.
+    /// The ones that answer are added back with the trust score they had
+    ///
+    /// #### Returns
+    /// True if at least one saved node answered
+    pub async fn restore_routing_table(&self) -> bool {
+        let contacts = match self.snapshot_path().and_then(fs::read_to_string) {
+            Ok(snapshot) => Kademlia::parse_snapshot(&snapshot).unwrap_or_default(),
+            Err(_) => return false,
+        };
+        if contacts.is_empty() {
+            return false;
+        }
+        info!("Pinging the {} nodes of the saved routing table", contacts.len());
+
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(10)); // Limit the number of threads
+        let tasks = contacts.into_iter()
+            .map(|(node, trust)| {
+                let semaphore = semaphore.clone();
+                let peer = self.clone();
+                tokio::spawn(async move {
+                    let permit = semaphore.acquire().await.expect("Failed to acquire permit");
+                    let alive = ResHandler::ping(&peer, &node.ip, node.port).await.is_ok();
+                    drop(permit);
+                    (node, trust, alive)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Awaited in order, so each bucket keeps its least recently contacted node on top
+        let mut restored = 0;
+        for task in tasks {
+            let (node, trust, alive) = task.await.expect("Failed to retrieve task result");
+            if alive && self.kademlia.lock().unwrap().restore_node(&node, trust) {
+                restored += 1;
+            }
+        }
+        debug!("DEBUG PEER::RESTORE_ROUTING_TABLE => {restored} saved nodes answered");
+        restored > 0
+    }
+}
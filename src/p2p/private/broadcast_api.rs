@@ -6,7 +6,7 @@ use tonic::Request;
 use tonic::transport::{Certificate, ClientTlsConfig, Identity};
 
 use crate::{auxi, proto};
-use crate::kademlia::node::{ID_LEN, Node};
+use crate::kademlia::node::{ID_BITS, Node};
 use crate::ledger::block::Block;
 use crate::marco::marco::Marco;
 use crate::p2p::peer::{Peer, TTL};
@@ -70,7 +70,7 @@ impl BroadCastReq {
             peer.boot().await;
             return;
         }
-        let n = ( (ID_LEN / 16) as f32).ceil() as usize ; // Number of sub-vectors
+        let n = ( (ID_BITS / 16) as f32).ceil() as usize ; // Number of sub-vectors
 
         let mut chunk_size = (nodes.len() / n) + 1;
         if chunk_size <= 0 {
@@ -5,7 +5,7 @@ use log::info;
 use tonic::{Request, Response, Status};
 
 use crate::{auxi, proto};
-use crate::kademlia::node::{ID_LEN, Identifier, Node};
+use crate::kademlia::node::{Identifier, Node};
 use crate::p2p::peer::Peer;
 use crate::proto::{DstAddress, FindNodeRequest, FindNodeResponse, FindValueRequest, FindValueResponse, GetBlockRequest, GetBlockResponse, KNearestNodes, SrcAddress};
 
@@ -46,14 +46,10 @@ impl ReqHandler {
 
         let node_id = &input.id;
         let my_node = &peer.node;
-        let mut id_array: [u8; ID_LEN] = [0; ID_LEN];
-        let mut src_id_array: [u8; ID_LEN] = [0; ID_LEN];
-
-
-        for i in 0..node_id.len() {
-            id_array[i] = node_id[i];
-            src_id_array[i] = src.id[i];
-        }
+        let (id_array, src_id_array) = match (Identifier::from_bytes(node_id), Identifier::from_bytes(&src.id)) {
+            (Some(id), Some(src_id)) => (id.0, src_id.0),
+            _ => return Err(Status::invalid_argument("The supplied ID has an invalid size")),
+        };
         let placeholder_node = auxi::return_option(proto::Node { // Won't be read (used just to fill in field)
             id: my_node.id.0.to_vec(),
             ip: my_node.ip.clone(),
@@ -64,36 +60,8 @@ impl ReqHandler {
         let lookup_src_node = peer.kademlia.lock().unwrap().get_node(Identifier::new(src_id_array));
         if lookup_src_node.is_none() {
             info!("Source node not recognized. Adding to the routing table");
-            let result = peer.kademlia.lock().unwrap().add_node(&Node::new(src.ip.clone(), src.port).unwrap());
-            if !result.is_none() {
-                // This means that when we tried to add the node the corresponding bucket was full
-                // So the top most node was returned, now we need to check if this node is up or not
-                // If it is, we can just ignore this node, if it isn't we add this new node to the kbucket
-                // In order to avoid locking the response we might create a new thread that will deal with
-                // the ping to the stored node
-
-                let res = peer.ping(&src.ip.clone(), src.port, Identifier::new(src.id.clone().try_into().unwrap())).await;
-                match res{
-                    Err(_) => {
-                        // This means we couldn't contact the node on top of the list
-                        // Therefore let's substitute it
-                        let new_node = Node::new(src.ip.clone(), src.port);
-                        if !new_node.is_none() {
-                            peer.kademlia.lock().unwrap().replace_node(&new_node.unwrap());
-                        }
-                    }
-                    // If the node is alive send it to the back of the list
-                    Ok(_) => {
-                        let new_node = Node::new(src.ip.clone(), src.port);
-                        if !new_node.is_none() {
-                            // The new node is passed as a way to calculate which bucket we
-                            // will be acting upon
-                            peer.kademlia.lock().unwrap().send_back(&new_node.unwrap());
-                        }
-                    }
-                }
-
-            }
+            // If the bucket is full, its oldest contact is pinged in the background before it can be replaced
+            peer.add_contact(&Node::new(src.ip.clone(), src.port).unwrap());
         } else if format!("{}:{}", src.ip, src.port).to_string() != format!("{}:{}", lookup_src_node.as_ref().unwrap().ip, lookup_src_node.as_ref().unwrap().port) {
             return Err(Status::invalid_argument("The supplied source is different from the one stored"))
         }
@@ -103,10 +71,6 @@ impl ReqHandler {
         }
         info!("Node: {:?} asked about node: {:?}", request.remote_addr(), input);
 
-        if node_id.len() != ID_LEN {
-            return Err(Status::invalid_argument("The supplied ID has an invalid size"));
-        }
-
         let id = Identifier::new(id_array);
 
         let mut lookup = None;
@@ -174,14 +138,11 @@ impl ReqHandler {
         }
 
         let value_id = &input.value_id;
-        let mut id_array: [u8; ID_LEN] = [0; ID_LEN];
-        let mut src_id_array: [u8; ID_LEN] = [0; ID_LEN];
-
-        // Get the id's into an array so that we can generate Identities
-        for i in 0..value_id.len() {
-            id_array[i] = value_id[i];
-            src_id_array[i] = src.id[i];
-        }
+        // Get the id into an array so that we can generate Identities
+        let id_array = match Identifier::from_bytes(value_id) {
+            Some(id) => id.0,
+            None => return Err(Status::invalid_argument("The supplied ID has an invalid size")),
+        };
 
         let mutex_guard = peer.kademlia.lock().unwrap();
         let lookup_value = mutex_guard.get_value(Identifier::new(id_array));
@@ -1,12 +1,14 @@
 #[doc(inline)]
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use log::{debug, info};
 use tonic::{Request, Response, Status};
 
 use crate::auxi;
-use crate::kademlia::node::{ID_LEN, Identifier, Node};
+use crate::kademlia::node::{Identifier, Node};
+use crate::kademlia::record::RECORD_EXPIRE;
 use crate::p2p::peer::Peer;
 use crate::p2p::private::req_handler_modules::req_handler_lookups::ReqHandler;
 use crate::p2p::private::req_handler_modules::res_handler::ResHandler;
@@ -94,14 +96,11 @@ impl ReqHandler {
         }
 
         let key = &input.key;
-        let mut id_array: [u8; ID_LEN] = [0; ID_LEN];
-        let mut src_id_array: [u8; ID_LEN] = [0; ID_LEN];
-
-        // Get the id's into an array so that we can generate Identities
-        for i in 0..key.len() {
-            id_array[i] = key[i];
-            src_id_array[i] = src.id[i];
-        }
+        // Get the id into an array so that we can generate Identities
+        let id_array = match Identifier::from_bytes(key) {
+            Some(id) => id.0,
+            None => return Err(Status::invalid_argument("The supplied key has an invalid size")),
+        };
 
         //let mut mutex_guard = peer.kademlia.lock().unwrap();
         let nodes = peer.kademlia.lock().unwrap().is_closest(&Identifier::new(id_array));
@@ -109,7 +108,11 @@ impl ReqHandler {
         // If we are the closest, or the packet as traveled the entire network and died on us, store the key
         if nodes.is_none() || ttl == 0 {
             // Means we are the closest node to the key
-            peer.kademlia.lock().unwrap().add_key(Identifier::new(id_array), input.value.clone());
+            let lifetime = match input.expires_in {
+                0 => RECORD_EXPIRE,
+                secs => Duration::from_secs(secs),
+            };
+            peer.kademlia.lock().unwrap().store_record(Identifier::new(id_array), input.value.clone(), lifetime);
             return if !peer.kademlia.lock().unwrap().get_value(Identifier::new(id_array)).is_none() {
                 let response = StoreResponse {
                     response_type: 1
@@ -139,14 +142,14 @@ impl ReqHandler {
             let tasks = arguments.into_iter()
                 .map(|arg| {
                     let semaphore = semaphore.clone();
-                    let node = peer.node.clone();
+                    let peer = peer.clone();
                     let ident = id_array.clone();
                     let val = value.clone();
-                    let own_id = peer.id.clone();
+                    let expires_in = input.expires_in;
                     tokio::spawn(async move {
                         // Acquire a permit from the semaphore
                         let permit = semaphore.acquire().await.expect("Failed to acquire permit");
-                        let res = ResHandler::store(&node, arg.0, arg.1, Identifier::new(ident), val, ttl-1, &own_id.clone()).await;
+                        let res = ResHandler::store(&peer, arg.0, arg.1, Identifier::new(ident), val, ttl-1, expires_in).await;
                         drop(permit);
                         res
                     })
@@ -224,9 +224,10 @@ impl  ResHandler {
     /// # store
     /// This gRPC procedure is only non-query one and so has a slightly different behavior where we request a node to store a certain (key ([Identifier]), Value ([String]))
     /// and on the receiver side, if the receiver is the closest node to the key than stores it, otherwise the receiver itself will forward the key to the k nearest nodes.
+    /// `expires_in` is the time (in seconds) the record has left, 0 for the default lifetime.
     /// ### Returns
     /// This function can either return an error, from connection or packet-related issues, or a [proto::StoreResponse].
-    pub(crate) async fn store(node: &Node, ip: String, port: u32, key_id: Identifier, value: String, ttl: u32, own_id: &Identifier) -> Result<Response<StoreResponse>, io::Error> {
+    pub(crate) async fn store(peer: &Peer, ip: String, port: u32, key_id: Identifier, value: String, ttl: u32, expires_in: u64) -> Result<Response<StoreResponse>, io::Error> {
         if std::env!("TLS").to_string() == "1" {
             let mut url = "https://".to_string();
             url += &format!("{}:{}", ip, port);
@@ -264,9 +265,10 @@ impl  ResHandler {
             let req = StoreRequest {
                 key: key_id.0.to_vec(),
                 value,
-                src: auxi::gen_address_src(own_id.clone(), node.ip.clone(), node.port),
+                src: auxi::gen_address_src(peer.id.clone(), peer.node.ip.clone(), peer.node.port),
                 dst: auxi::gen_address_dst(ip.to_string(), port),
-                ttl
+                ttl,
+                expires_in,
             };
 
             let request = tonic::Request::new(req);